@@ -1,12 +1,11 @@
 //! The async filesystem trait definitions needed to implement new async virtual filesystems
 
-use crate::async_vfs::{AsyncVfsPath, SeekAndRead};
+use crate::async_vfs::{AsyncVfsPath, SeekAndRead, SeekAndWrite};
 use crate::error::VfsErrorKind;
-use crate::{VfsError, VfsMetadata, VfsResult};
+use crate::{VfsError, VfsMetadata, VfsResult, WatchEvent};
 
-use async_std::io::Write;
-use async_std::stream::Stream;
 use async_trait::async_trait;
+use futures::stream::Stream;
 use std::fmt::Debug;
 use std::time::SystemTime;
 
@@ -32,9 +31,9 @@ pub trait AsyncFileSystem: Debug + Sync + Send + 'static {
     /// Opens the file at this path for reading
     async fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send + Unpin>>;
     /// Creates a file at this path for writing
-    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>>;
+    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>>;
     /// Opens the file at this path for appending
-    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>>;
+    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>>;
     /// Returns the file metadata for the file at this path
     async fn metadata(&self, path: &str) -> VfsResult<VfsMetadata>;
     /// Sets the files creation timestamp, if the implementation supports it
@@ -67,6 +66,14 @@ pub trait AsyncFileSystem: Debug + Sync + Send + 'static {
     async fn move_dir(&self, _src: &str, _dest: &str) -> VfsResult<()> {
         Err(VfsErrorKind::NotSupported.into())
     }
+    /// Watches the path for changes to itself and its direct children, or all its descendants if `recursive` is set (optional)
+    async fn watch(
+        &self,
+        _path: &str,
+        _recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
 }
 
 impl<T: AsyncFileSystem> From<T> for AsyncVfsPath {
@@ -1,10 +1,10 @@
 //! A file system with its root in a particular directory of another filesystem
 
-use crate::async_vfs::{AsyncFileSystem, AsyncVfsPath, SeekAndRead};
-use crate::{error::VfsErrorKind, VfsMetadata, VfsResult};
+use crate::async_vfs::{AsyncFileSystem, AsyncVfsPath, SeekAndRead, SeekAndWrite};
+use crate::impls::altroot::strip_root;
+use crate::{error::VfsErrorKind, VfsMetadata, VfsResult, WatchEvent};
 use std::time::SystemTime;
 
-use async_std::io::Write;
 use async_trait::async_trait;
 use futures::stream::{Stream, StreamExt};
 
@@ -60,11 +60,11 @@ impl AsyncFileSystem for AsyncAltrootFS {
         self.path(path)?.open_file().await
     }
 
-    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         self.path(path)?.create_file().await
     }
 
-    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         self.path(path)?.append_file().await
     }
 
@@ -105,6 +105,18 @@ impl AsyncFileSystem for AsyncAltrootFS {
         }
         self.path(src)?.copy_file(&self.path(dest)?).await
     }
+
+    async fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        let root = self.root.as_str().to_string();
+        let events = self.path(path)?.watch(recursive).await?;
+        Ok(Box::new(events.filter_map(move |event| {
+            futures::future::ready(event.translate(|path| strip_root(&root, path)))
+        })))
+    }
 }
 
 #[cfg(test)]
@@ -128,25 +140,46 @@ mod tests {
         assert_eq!(altroot.parent(), altroot.root());
         assert_eq!(altroot_path.parent(), memory_root);
     }
+
+    #[tokio::test]
+    async fn watch_translates_paths() -> VfsResult<()> {
+        let memory_root: AsyncVfsPath = AsyncMemoryFS::new().into();
+        let altroot_path = memory_root.join("altroot")?;
+        altroot_path.create_dir().await?;
+        let altroot: AsyncVfsPath = AsyncAltrootFS::new(altroot_path).into();
+        let events = altroot.watch(true).await?;
+        altroot.join("foo")?.create_dir().await?;
+        memory_root
+            .join("altroot/foo")?
+            .move_dir(&memory_root.join("outside")?)
+            .await?;
+        drop((altroot, memory_root));
+        assert_eq!(
+            events.collect::<Vec<_>>().await,
+            vec![
+                WatchEvent::Created("/foo".into()),
+                WatchEvent::Removed("/foo".into()),
+            ]
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
+#[cfg(any(feature = "async-std", feature = "smol", feature = "async-tokio"))]
 mod tests_physical {
     use super::*;
     use crate::async_vfs::AsyncPhysicalFS;
 
-    use async_std::io::ReadExt;
-
-    test_async_vfs!(futures::executor::block_on(async {
+    test_async_vfs!({
         let temp_dir = std::env::temp_dir();
         let dir = temp_dir.join(uuid::Uuid::new_v4().to_string());
-        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(dir.join("altroot")).unwrap();
 
         let physical_root: AsyncVfsPath = AsyncPhysicalFS::new(dir).into();
         let altroot_path = physical_root.join("altroot").unwrap();
-        altroot_path.create_dir().await.unwrap();
         AsyncAltrootFS::new(altroot_path)
-    }));
+    });
 
     test_async_vfs_readonly!({
         let physical_root: AsyncVfsPath = AsyncPhysicalFS::new("test").into();
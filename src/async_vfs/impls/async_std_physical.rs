@@ -0,0 +1,177 @@
+//! A "physical" file system implementation for the async-std runtime
+use crate::async_vfs::impls::physical::{already_exists, to_vfs_metadata};
+use crate::async_vfs::{AsyncFileSystem, SeekAndRead, SeekAndWrite};
+use crate::error::VfsErrorKind;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+use crate::{async_vfs::impls::physical, WatchEvent};
+use crate::{VfsError, VfsMetadata, VfsResult};
+
+use async_std::fs::{File, OpenOptions};
+use async_trait::async_trait;
+use filetime::FileTime;
+use futures::stream::{Stream, StreamExt};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A physical filesystem implementation using the underlying OS file system through `async_std::fs`
+///
+/// Requires the `async-std` feature.
+#[derive(Debug)]
+pub struct AsyncStdPhysicalFS {
+    root: PathBuf,
+}
+
+impl AsyncStdPhysicalFS {
+    /// Create a new physical filesystem rooted in `root`
+    pub fn new<T: AsRef<Path>>(root: T) -> Self {
+        AsyncStdPhysicalFS {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn get_path(&self, mut path: &str) -> PathBuf {
+        if path.starts_with('/') {
+            path = &path[1..];
+        }
+        self.root.join(path)
+    }
+}
+
+/// Runs normal blocking io on the async-std blocking thread pool
+async fn blocking_io<F>(f: F) -> Result<(), VfsError>
+where
+    F: FnOnce() -> std::io::Result<()> + Send + 'static,
+{
+    async_std::task::spawn_blocking(f).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl AsyncFileSystem for AsyncStdPhysicalFS {
+    async fn read_dir(
+        &self,
+        path: &str,
+    ) -> VfsResult<Box<dyn Unpin + Stream<Item = String> + Send>> {
+        let entries = Box::new(
+            async_std::fs::read_dir(self.get_path(path))
+                .await?
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap()),
+        );
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &str) -> VfsResult<()> {
+        let fs_path = self.get_path(path);
+        match async_std::fs::create_dir(&fs_path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(already_exists(async_std::fs::metadata(&fs_path).await?))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send + Unpin>> {
+        Ok(Box::new(File::open(self.get_path(path)).await?))
+    }
+
+    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        Ok(Box::new(File::create(self.get_path(path)).await?))
+    }
+
+    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        Ok(Box::new(
+            OpenOptions::new()
+                .write(true)
+                .append(true)
+                .open(self.get_path(path))
+                .await?,
+        ))
+    }
+
+    async fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let metadata = async_std::fs::metadata(self.get_path(path)).await?;
+        Ok(to_vfs_metadata(metadata))
+    }
+
+    async fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        let path = self.get_path(path);
+
+        blocking_io(move || filetime::set_file_mtime(path, FileTime::from(time))).await
+    }
+
+    async fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        let path = self.get_path(path);
+
+        blocking_io(move || filetime::set_file_atime(path, FileTime::from(time))).await
+    }
+
+    async fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(async_std::fs::metadata(self.get_path(path)).await.is_ok())
+    }
+
+    async fn remove_file(&self, path: &str) -> VfsResult<()> {
+        async_std::fs::remove_file(self.get_path(path)).await?;
+        Ok(())
+    }
+
+    async fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        async_std::fs::remove_dir(self.get_path(path)).await?;
+        Ok(())
+    }
+
+    async fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        async_std::fs::copy(self.get_path(src), self.get_path(dest)).await?;
+        Ok(())
+    }
+
+    async fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        async_std::fs::rename(self.get_path(src), self.get_path(dest)).await?;
+
+        Ok(())
+    }
+
+    async fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let result = async_std::fs::rename(self.get_path(src), self.get_path(dest)).await;
+        if result.is_err() {
+            // Error possibly due to different filesystems, return not supported and let the fallback handle it
+            return Err(VfsErrorKind::NotSupported.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(all(feature = "inotify", target_os = "linux"))]
+    async fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        physical::watch(self.root.clone(), path, recursive).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    test_async_vfs!(futures::executor::block_on(async {
+        let temp_dir = std::env::temp_dir();
+        let dir = temp_dir.join(uuid::Uuid::new_v4().to_string());
+        async_std::fs::create_dir_all(&dir).await.unwrap();
+        AsyncStdPhysicalFS::new(dir)
+    }));
+    test_async_vfs_readonly!({ AsyncStdPhysicalFS::new("test/test_directory") });
+
+    #[test]
+    fn set_modification_time_without_tokio() -> VfsResult<()> {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("a.txt"), "a")?;
+        let fs = AsyncStdPhysicalFS::new(&dir);
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        async_std::task::block_on(fs.set_modification_time("/a.txt", time))?;
+        assert_eq!(std::fs::metadata(dir.join("a.txt"))?.modified()?, time);
+        Ok(())
+    }
+}
@@ -0,0 +1,504 @@
+//! An adapter using an async filesystem as a synchronous filesystem
+
+use crate::async_vfs::{
+    AsyncVfsPath, SeekAndRead as AsyncSeekAndRead, SeekAndWrite as AsyncSeekAndWrite,
+};
+use crate::impls::altroot::strip_root;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsResult, WatchEvent};
+
+#[cfg(feature = "async-tokio")]
+use futures::channel::oneshot;
+use futures::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use futures::stream::{Stream, StreamExt};
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "async-tokio")]
+use std::sync::Arc;
+use std::time::SystemTime;
+#[cfg(feature = "async-tokio")]
+use tokio::runtime::{Builder, Handle, RuntimeFlavor};
+
+/// A synchronous filesystem driving the futures of an [`AsyncVfsPath`] to completion
+///
+/// This makes async implementations, including custom ones, usable by synchronous code taking a
+/// [`VfsPath`](crate::VfsPath). Without the `async-tokio` feature the futures are driven on the calling thread,
+/// which suits runtimes like async-std and smol that do their I/O on threads of their own. With the `async-tokio`
+/// feature they are driven either on a dedicated tokio runtime thread ([`AsyncToSyncFS::new()`]) or on a
+/// provided tokio runtime (`AsyncToSyncFS::with_handle()`).
+///
+/// Calls are safe from inside an async context as well, including from tasks of the runtime driving the futures.
+/// Inside a tokio runtime the future is spawned on the runtime driving the filesystem instead of blocking with
+/// a nested `block_on`, while the calling thread waits for the result. A provided current-thread runtime might be
+/// the one blocked by the call, so there the future is driven by a short-lived helper thread instead. Note that a
+/// blocked current-thread runtime cannot drive tokio I/O or timers, so async filesystems relying on those need a
+/// multi-threaded or the dedicated runtime.
+///
+/// ```
+/// use vfs::async_vfs::{AsyncMemoryFS, AsyncToSyncFS};
+/// use vfs::{VfsError, VfsPath};
+///
+/// let root: VfsPath = AsyncToSyncFS::new(AsyncMemoryFS::new().into()).into();
+/// root.join("foo.txt")?.create_file()?.write_all(b"Hello")?;
+/// assert_eq!(root.join("foo.txt")?.read_to_string()?, "Hello");
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncToSyncFS {
+    root: AsyncVfsPath,
+    executor: Executor,
+}
+
+impl AsyncToSyncFS {
+    /// Create a new synchronous filesystem driving the futures of `root`
+    ///
+    /// With the `async-tokio` feature the futures are driven on a dedicated executor thread,
+    /// which is stopped when the filesystem and all of its clones are dropped.
+    pub fn new(root: AsyncVfsPath) -> Self {
+        AsyncToSyncFS {
+            root,
+            executor: Executor::dedicated(),
+        }
+    }
+
+    /// Create a new synchronous filesystem driving the futures of `root` on the given tokio runtime
+    ///
+    /// Requires the `async-tokio` feature.
+    #[cfg(feature = "async-tokio")]
+    pub fn with_handle(root: AsyncVfsPath, handle: Handle) -> Self {
+        AsyncToSyncFS {
+            root,
+            executor: Executor {
+                handle,
+                _shutdown: None,
+            },
+        }
+    }
+
+    fn path(&self, path: &str) -> VfsResult<AsyncVfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+#[derive(Debug, Clone)]
+struct Executor {
+    handle: Handle,
+    /// Stops the dedicated executor thread when the last clone is dropped
+    _shutdown: Option<Arc<oneshot::Sender<()>>>,
+}
+
+#[cfg(feature = "async-tokio")]
+impl Executor {
+    fn dedicated() -> Self {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Could not create the executor runtime");
+        let handle = runtime.handle().clone();
+        let (sender, receiver) = oneshot::channel::<()>();
+        std::thread::Builder::new()
+            .name("vfs-async-to-sync".into())
+            .spawn(move || {
+                // Drives the I/O, timers and spawned tasks for the futures until shutdown
+                let _ = runtime.block_on(receiver);
+            })
+            .expect("Could not spawn the executor thread");
+        Executor {
+            handle,
+            _shutdown: Some(Arc::new(sender)),
+        }
+    }
+
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        if Handle::try_current().is_err() {
+            return self.handle.block_on(future);
+        }
+        if self._shutdown.is_none() && self.handle.runtime_flavor() == RuntimeFlavor::CurrentThread
+        {
+            // A task spawned on a provided current-thread runtime never runs if that runtime is blocked by this call
+            return std::thread::scope(|scope| {
+                scope
+                    .spawn(|| self.handle.block_on(future))
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            });
+        }
+        // A nested block_on would panic inside a runtime, so the future runs as a task and this thread waits for it
+        match futures_lite::future::block_on(self.handle.spawn(future)) {
+            Ok(output) => output,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(_) => panic!("The executor runtime was shut down"),
+        }
+    }
+}
+
+#[cfg(not(feature = "async-tokio"))]
+#[derive(Debug, Clone)]
+struct Executor;
+
+#[cfg(not(feature = "async-tokio"))]
+impl Executor {
+    fn dedicated() -> Self {
+        Executor
+    }
+
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        // Unlike `futures::executor::block_on`, this may be nested inside other executors
+        futures_lite::future::block_on(future)
+    }
+}
+
+impl FileSystem for AsyncToSyncFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let path = self.path(path)?;
+        let entries = self
+            .executor
+            .block_on(async move { path.read_dir().await })?;
+        Ok(Box::new(StreamIterator {
+            stream: Blocking::new(entries.map(|path| path.filename()), self.executor.clone()),
+        }))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        let path = self.path(path)?;
+        self.executor
+            .block_on(async move { path.create_dir().await })
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let path = self.path(path)?;
+        let file = self
+            .executor
+            .block_on(async move { path.open_file().await })?;
+        Ok(Box::new(BlockingReader::new(file, self.executor.clone())))
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let path = self.path(path)?;
+        let file = self
+            .executor
+            .block_on(async move { path.create_file().await })?;
+        Ok(Box::new(BlockingWriter::new(file, self.executor.clone())))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let path = self.path(path)?;
+        let file = self
+            .executor
+            .block_on(async move { path.append_file().await })?;
+        Ok(Box::new(BlockingWriter::new(file, self.executor.clone())))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let path = self.path(path)?;
+        self.executor.block_on(async move { path.metadata().await })
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        let path = self.path(path)?;
+        self.executor
+            .block_on(async move { path.set_creation_time(time).await })
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        let path = self.path(path)?;
+        self.executor
+            .block_on(async move { path.set_modification_time(time).await })
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        let path = self.path(path)?;
+        self.executor
+            .block_on(async move { path.set_access_time(time).await })
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        match self.path(path) {
+            Ok(path) => self.executor.block_on(async move { path.exists().await }),
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        let path = self.path(path)?;
+        self.executor
+            .block_on(async move { path.remove_file().await })
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        let path = self.path(path)?;
+        self.executor
+            .block_on(async move { path.remove_dir().await })
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let (src, dest) = (self.path(src)?, self.path(dest)?);
+        self.executor
+            .block_on(async move { src.copy_file(&dest).await })
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let (src, dest) = (self.path(src)?, self.path(dest)?);
+        self.executor
+            .block_on(async move { src.move_file(&dest).await })
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let (src, dest) = (self.path(src)?, self.path(dest)?);
+        self.executor
+            .block_on(async move { src.move_dir(&dest).await })
+    }
+
+    fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        let path = self.path(path)?;
+        let events = self
+            .executor
+            .block_on(async move { path.watch(recursive).await })?;
+        let root = self.root.as_str().to_string();
+        Ok(Box::new(StreamIterator {
+            stream: Blocking::new(
+                events.filter_map(move |event| {
+                    futures::future::ready(event.translate(|path| strip_root(&root, path)))
+                }),
+                self.executor.clone(),
+            ),
+        }))
+    }
+}
+
+/// An iterator blocking on the items of a stream
+struct StreamIterator<S> {
+    stream: Blocking<S>,
+}
+
+impl<S: Stream + Send + Unpin + 'static> Iterator for StreamIterator<S>
+where
+    S::Item: Send + 'static,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        self.stream.run(|mut stream, buffer| async move {
+            let item = stream.next().await;
+            (stream, buffer, item)
+        })
+    }
+}
+
+/// A value moved into the futures driven by the executor and back, so that they can be spawned as tasks
+///
+/// A buffer is moved along, to be reused across calls copying data in or out.
+struct Blocking<T> {
+    value: Option<(T, Vec<u8>)>,
+    executor: Executor,
+}
+
+impl<T: Send + 'static> Blocking<T> {
+    fn new(value: T, executor: Executor) -> Self {
+        Blocking {
+            value: Some((value, vec![])),
+            executor,
+        }
+    }
+
+    fn run<R, F, Fut>(&mut self, operation: F) -> R
+    where
+        F: FnOnce(T, Vec<u8>) -> Fut,
+        Fut: Future<Output = (T, Vec<u8>, R)> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (value, buffer) = self
+            .value
+            .take()
+            .expect("The value is lost if an operation panicked");
+        let (value, buffer, result) = self.executor.block_on(operation(value, buffer));
+        self.value = Some((value, buffer));
+        result
+    }
+
+    fn buffer(&self) -> &[u8] {
+        self.value.as_ref().map_or(&[], |(_, buffer)| buffer)
+    }
+}
+
+type BlockingReader = Blocking<Box<dyn AsyncSeekAndRead + Send + Unpin>>;
+
+impl Read for BlockingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len();
+        let amount = self.run(move |mut file, mut buffer| async move {
+            buffer.resize(len, 0);
+            let result = file.read(&mut buffer).await;
+            (file, buffer, result)
+        })?;
+        buf[..amount].copy_from_slice(&self.buffer()[..amount]);
+        Ok(amount)
+    }
+}
+
+impl Seek for BlockingReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.run(move |mut file, buffer| async move {
+            let result = file.seek(pos).await;
+            (file, buffer, result)
+        })
+    }
+}
+
+type BlockingWriter = Blocking<Box<dyn AsyncSeekAndWrite + Send + Unpin>>;
+
+impl Write for BlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.run(|mut file, mut buffer| {
+            buffer.clear();
+            buffer.extend_from_slice(buf);
+            async move {
+                let result = file.write(&buffer).await;
+                (file, buffer, result)
+            }
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.run(|mut file, buffer| async move {
+            let result = file.flush().await;
+            (file, buffer, result)
+        })
+    }
+}
+
+impl Seek for BlockingWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.run(move |mut file, buffer| async move {
+            let result = file.seek(pos).await;
+            (file, buffer, result)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_vfs::{AsyncMemoryFS, SyncToAsyncFS};
+    use crate::{PhysicalFS, VfsPath};
+
+    test_vfs!(AsyncToSyncFS::new(AsyncMemoryFS::new().into()));
+    test_vfs_readonly!({
+        let fs = SyncToAsyncFS::new(PhysicalFS::new("test/test_directory"));
+        AsyncToSyncFS::new(fs.into())
+    });
+
+    fn exercise(root: &VfsPath) -> VfsResult<()> {
+        root.join("dir/sub")?.create_dir_all()?;
+        root.join("dir/a.txt")?.create_file()?.write_all(b"Hello")?;
+        root.join("dir/a.txt")?
+            .append_file()?
+            .write_all(b" World")?;
+        assert_eq!(root.join("dir/a.txt")?.read_to_string()?, "Hello World");
+        assert_eq!(root.join("dir/a.txt")?.metadata()?.len, 11);
+
+        let mut file = root.join("dir/a.txt")?.open_file()?;
+        file.seek(SeekFrom::Start(6))?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        assert_eq!(content, "World");
+
+        root.join("dir/a.txt")?
+            .copy_file(&root.join("dir/b.txt")?)?;
+        root.join("dir/b.txt")?
+            .move_file(&root.join("dir/sub/c.txt")?)?;
+        let mut names: Vec<_> = root
+            .join("dir")?
+            .read_dir()?
+            .map(|path| path.filename())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a.txt", "sub"]);
+        assert_eq!(root.join("dir/sub/c.txt")?.read_to_string()?, "Hello World");
+
+        root.join("dir")?.remove_dir_all()?;
+        assert!(!root.join("dir")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn dedicated_executor() -> VfsResult<()> {
+        let root: VfsPath = AsyncToSyncFS::new(AsyncMemoryFS::new().into()).into();
+        exercise(&root)
+    }
+
+    #[test]
+    #[cfg(feature = "async-tokio")]
+    fn provided_runtime() -> VfsResult<()> {
+        let runtime = Builder::new_current_thread().build()?;
+        let fs = AsyncToSyncFS::with_handle(AsyncMemoryFS::new().into(), runtime.handle().clone());
+        exercise(&fs.into())
+    }
+
+    #[tokio::test]
+    async fn called_from_async_context() -> VfsResult<()> {
+        let root: VfsPath = AsyncToSyncFS::new(AsyncMemoryFS::new().into()).into();
+        exercise(&root)
+    }
+
+    #[test]
+    fn called_from_futures_executor() -> VfsResult<()> {
+        let root: VfsPath = AsyncToSyncFS::new(AsyncMemoryFS::new().into()).into();
+        futures::executor::block_on(async { exercise(&root) })
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "async-tokio")]
+    async fn called_from_provided_runtime() -> VfsResult<()> {
+        // The runtime executing this test drives the futures itself
+        let fs = AsyncToSyncFS::with_handle(AsyncMemoryFS::new().into(), Handle::current());
+        exercise(&fs.into())
+    }
+
+    #[test]
+    fn errors_are_passed_through() -> VfsResult<()> {
+        let root: VfsPath = AsyncToSyncFS::new(AsyncMemoryFS::new().into()).into();
+        let error = root
+            .join("missing.txt")?
+            .open_file()
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            error.kind().to_string(),
+            "The file or directory could not be found"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watch_events_are_iterated() -> VfsResult<()> {
+        let memory_root: AsyncVfsPath = AsyncMemoryFS::new().into();
+        futures::executor::block_on(memory_root.join("dir")?.create_dir())?;
+        let root: VfsPath = AsyncToSyncFS::new(memory_root.join("dir")?).into();
+        let mut events = root.watch(true)?;
+        root.join("foo")?.create_dir()?;
+        assert_eq!(events.next(), Some(WatchEvent::Created("/foo".into())));
+        root.join("foo")?.remove_dir()?;
+        drop((root, memory_root));
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![WatchEvent::Removed("/foo".into())]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+//! An async read-only file system embedded in the executable
+
+use crate::async_vfs::{AsyncFileSystem, SeekAndRead, SeekAndWrite};
+use crate::error::VfsErrorKind;
+use crate::{EmbeddedFS, FileSystem, VfsMetadata, VfsResult};
+
+use async_trait::async_trait;
+use futures::io::Cursor;
+use futures::stream::{self, Stream};
+use rust_embed::RustEmbed;
+use std::fmt::Debug;
+
+/// An async read-only file system embedded in the executable
+///
+/// See [rust-embed](https://docs.rs/rust-embed/) for how to create a `RustEmbed`.
+/// The embedded files are held in memory, so all operations complete without blocking.
+/// Requires both the `embedded-fs` and `async-vfs-core` features.
+///
+/// ```
+/// use futures::io::AsyncReadExt;
+/// use rust_embed::RustEmbed;
+/// use vfs::async_vfs::{AsyncEmbeddedFS, AsyncVfsPath};
+/// use vfs::VfsError;
+///
+/// #[derive(RustEmbed, Debug)]
+/// #[folder = "test/test_directory"]
+/// struct Assets;
+///
+/// # tokio_test::block_on(async {
+/// let root: AsyncVfsPath = AsyncEmbeddedFS::<Assets>::new().into();
+/// let mut content = String::new();
+/// root.join("a.txt")?.open_file().await?.read_to_string(&mut content).await?;
+/// assert_eq!(content, "a");
+/// # Ok::<(), VfsError>(())
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct AsyncEmbeddedFS<T>
+where
+    T: RustEmbed + Send + Sync + Debug + 'static,
+{
+    inner: EmbeddedFS<T>,
+}
+
+impl<T> AsyncEmbeddedFS<T>
+where
+    T: RustEmbed + Send + Sync + Debug + 'static,
+{
+    /// Create a new async file system from the embedded files
+    pub fn new() -> Self {
+        AsyncEmbeddedFS {
+            inner: EmbeddedFS::new(),
+        }
+    }
+}
+
+impl<T> Default for AsyncEmbeddedFS<T>
+where
+    T: RustEmbed + Send + Sync + Debug + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<T> AsyncFileSystem for AsyncEmbeddedFS<T>
+where
+    T: RustEmbed + Send + Sync + Debug + 'static,
+{
+    async fn read_dir(
+        &self,
+        path: &str,
+    ) -> VfsResult<Box<dyn Unpin + Stream<Item = String> + Send>> {
+        Ok(Box::new(stream::iter(self.inner.read_dir(path)?)))
+    }
+
+    async fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    async fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send + Unpin>> {
+        match path.get(1..).and_then(T::get) {
+            None => Err(VfsErrorKind::FileNotFound.into()),
+            Some(file) => Ok(Box::new(Cursor::new(file.data))),
+        }
+    }
+
+    async fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    async fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    async fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.inner.metadata(path)
+    }
+
+    async fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.inner.exists(path)
+    }
+
+    async fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    async fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_vfs::{AsyncMemoryFS, AsyncOverlayFS, AsyncVfsPath};
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+    use futures::stream::StreamExt;
+    use std::collections::HashSet;
+
+    #[derive(RustEmbed, Debug)]
+    #[folder = "test/test_directory"]
+    struct TestEmbed;
+
+    fn get_test_fs() -> AsyncEmbeddedFS<TestEmbed> {
+        AsyncEmbeddedFS::new()
+    }
+
+    test_async_vfs_readonly!({ get_test_fs() });
+
+    #[tokio::test]
+    async fn read_dir_lists_directory() -> VfsResult<()> {
+        let fs = get_test_fs();
+        let names: HashSet<String> = fs.read_dir("/a").await?.collect().await;
+        assert_eq!(names, HashSet::from(["d.txt".to_string(), "x".to_string()]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn modifications_not_supported() {
+        let fs = get_test_fs();
+        for result in [
+            fs.create_dir("/abc").await,
+            fs.create_file("/abc.txt").await.map(|_| ()),
+            fs.append_file("/a.txt").await.map(|_| ()),
+            fs.remove_file("/a.txt").await,
+            fs.remove_dir("/a").await,
+        ] {
+            assert!(matches!(
+                result.unwrap_err().kind(),
+                VfsErrorKind::NotSupported
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn open_file_not_found() {
+        let fs = get_test_fs();
+        for path in ["/", "/abc.txt", "/c/f.txt", ""] {
+            assert!(matches!(
+                fs.open_file(path).await.map(|_| ()).unwrap_err().kind(),
+                VfsErrorKind::FileNotFound
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn writable_overlay_over_embedded_defaults() -> VfsResult<()> {
+        let upper: AsyncVfsPath = AsyncMemoryFS::new().into();
+        let root: AsyncVfsPath = AsyncOverlayFS::new(&[upper, get_test_fs().into()]).into();
+        let file = root.join("a.txt")?;
+        assert_eq!(file.read_to_string().await?, "a");
+
+        file.create_file().await?.write_all(b"customized").await?;
+        let mut content = String::new();
+        file.open_file().await?.read_to_string(&mut content).await?;
+        assert_eq!(content, "customized");
+        assert_eq!(root.join("b.txt")?.read_to_string().await?, "b");
+        Ok(())
+    }
+}
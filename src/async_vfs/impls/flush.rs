@@ -0,0 +1,69 @@
+//! Flushing async writers that are dropped without being closed
+
+use futures::io::{AsyncSeek, AsyncWrite, AsyncWriteExt, SeekFrom};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A file writer flushing its buffered data when dropped
+///
+/// The smol and tokio runtimes and [`SyncToAsyncFS`](super::sync_to_async::SyncToAsyncFS) hand writes off
+/// to a blocking thread, so data written without an explicit flush might otherwise not have reached the
+/// file once the writer is gone.
+/// Flushing when dropped blocks the dropping thread, which may be a runtime worker, so it is
+/// only a last resort for writers with unflushed data. Callers should `close()` writers instead.
+pub(crate) struct FlushOnDrop<F: AsyncWrite + Unpin> {
+    file: F,
+    /// Whether data was written since the last flush
+    dirty: bool,
+}
+
+impl<F: AsyncWrite + Unpin> FlushOnDrop<F> {
+    pub(crate) fn new(file: F) -> Self {
+        FlushOnDrop { file, dirty: false }
+    }
+}
+
+impl<F: AsyncWrite + Unpin> AsyncWrite for FlushOnDrop<F> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = futures::ready!(Pin::new(&mut self.file).poll_write(cx, buf))?;
+        self.dirty |= written > 0;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures::ready!(Pin::new(&mut self.file).poll_flush(cx))?;
+        self.dirty = false;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures::ready!(Pin::new(&mut self.file).poll_close(cx))?;
+        self.dirty = false;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsyncWrite + AsyncSeek + Unpin> AsyncSeek for FlushOnDrop<F> {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        Pin::new(&mut self.file).poll_seek(cx, pos)
+    }
+}
+
+impl<F: AsyncWrite + Unpin> Drop for FlushOnDrop<F> {
+    fn drop(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        // Blocking is unavoidable here, the alternative would be losing data
+        let _ = futures_lite::future::block_on(self.file.flush());
+    }
+}
@@ -1,12 +1,16 @@
 //! An ephemeral in-memory file system, intended mainly for unit tests
-use crate::async_vfs::{AsyncFileSystem, SeekAndRead};
+use crate::async_vfs::{AsyncFileSystem, SeekAndRead, SeekAndWrite};
 use crate::error::VfsErrorKind;
 use crate::path::VfsFileType;
-use crate::{VfsMetadata, VfsResult};
+use crate::watch::Watchers;
+use crate::{VfsMetadata, VfsResult, WatchEvent};
 
-use async_std::io::{prelude::SeekExt, Cursor, Read, Seek, SeekFrom, Write};
-use async_std::sync::{Arc, RwLock};
+use async_lock::RwLock;
 use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::io::{
+    AsyncRead as Read, AsyncSeek as Seek, AsyncSeekExt, AsyncWrite as Write, Cursor, SeekFrom,
+};
 use futures::task::{Context, Poll};
 use futures::{Stream, StreamExt};
 use std::collections::hash_map::Entry;
@@ -15,6 +19,7 @@ use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::mem::swap;
 use std::pin::Pin;
+use std::sync::Arc;
 
 type AsyncMemoryFsHandle = Arc<RwLock<AsyncMemoryFsImpl>>;
 
@@ -40,8 +45,11 @@ impl AsyncMemoryFS {
     async fn ensure_has_parent(&self, path: &str) -> VfsResult<()> {
         let separator = path.rfind('/');
         if let Some(index) = separator {
-            if self.exists(&path[..index]).await? {
-                return Ok(());
+            let handle = self.handle.read().await;
+            if let Some(parent) = handle.files.get(&path[..index]) {
+                if parent.file_type == VfsFileType::Directory {
+                    return Ok(());
+                }
             }
         }
         Err(VfsErrorKind::Other("Parent path does not exist".into()).into())
@@ -65,27 +73,33 @@ impl Write for AsyncWritableFile {
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
-    ) -> Poll<Result<usize, async_std::io::Error>> {
+    ) -> Poll<Result<usize, std::io::Error>> {
         let this = self.get_mut();
         let file = Pin::new(&mut this.content);
         file.poll_write(cx, buf)
     }
     // Flush any bytes left in the write buffer to the virtual file
-    fn poll_flush(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-    ) -> Poll<Result<(), async_std::io::Error>> {
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
         let this = self.get_mut();
         let file = Pin::new(&mut this.content);
         file.poll_flush(cx)
     }
-    fn poll_close(
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        let this = self.get_mut();
+        let file = Pin::new(&mut this.content);
+        file.poll_close(cx)
+    }
+}
+
+impl Seek for AsyncWritableFile {
+    fn poll_seek(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-    ) -> Poll<Result<(), async_std::io::Error>> {
+        pos: SeekFrom,
+    ) -> Poll<Result<u64, std::io::Error>> {
         let this = self.get_mut();
         let file = Pin::new(&mut this.content);
-        file.poll_close(cx)
+        file.poll_seek(cx, pos)
     }
 }
 
@@ -93,13 +107,17 @@ impl Drop for AsyncWritableFile {
     fn drop(&mut self) {
         let mut content = vec![];
         swap(&mut content, self.content.get_mut());
-        futures::executor::block_on(self.fs.write()).files.insert(
+        let mut handle = futures_lite::future::block_on(self.fs.write());
+        handle.files.insert(
             self.destination.clone(),
             AsyncMemoryFile {
                 file_type: VfsFileType::File,
                 content: Arc::new(content),
             },
         );
+        handle
+            .watchers
+            .emit(WatchEvent::Modified(self.destination.clone()));
     }
 }
 
@@ -121,7 +139,7 @@ impl Read for AsyncReadableFile {
         self: Pin<&mut Self>,
         _cx: &mut Context<'_>,
         buf: &mut [u8],
-    ) -> Poll<Result<usize, async_std::io::Error>> {
+    ) -> Poll<Result<usize, std::io::Error>> {
         let this = self.get_mut();
         let bytes_left = this.len() - this.cursor_pos;
         let bytes_read = std::cmp::min(buf.len() as u64, bytes_left);
@@ -141,7 +159,7 @@ impl Seek for AsyncReadableFile {
         self: Pin<&mut Self>,
         _cx: &mut Context<'_>,
         pos: SeekFrom,
-    ) -> Poll<Result<u64, async_std::io::Error>> {
+    ) -> Poll<Result<u64, std::io::Error>> {
         let this = self.get_mut();
         let new_pos = match pos {
             SeekFrom::Start(offset) => offset as i64,
@@ -149,8 +167,8 @@ impl Seek for AsyncReadableFile {
             SeekFrom::Current(offset) => this.cursor_pos as i64 + offset,
         };
         if new_pos < 0 || new_pos >= this.len() as i64 {
-            Poll::Ready(Err(async_std::io::Error::new(
-                async_std::io::ErrorKind::InvalidData,
+            Poll::Ready(Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
                 "Requested offset is outside the file!",
             )))
         } else {
@@ -194,7 +212,8 @@ impl AsyncFileSystem for AsyncMemoryFS {
 
     async fn create_dir(&self, path: &str) -> VfsResult<()> {
         self.ensure_has_parent(path).await?;
-        let map = &mut self.handle.write().await.files;
+        let mut handle = self.handle.write().await;
+        let map = &mut handle.files;
         let entry = map.entry(path.to_string());
         match entry {
             Entry::Occupied(file) => {
@@ -213,6 +232,7 @@ impl AsyncFileSystem for AsyncMemoryFS {
                 );
             }
         }
+        handle.watchers.emit(WatchEvent::Created(path.to_string()));
         Ok(())
     }
 
@@ -226,16 +246,21 @@ impl AsyncFileSystem for AsyncMemoryFS {
         }))
     }
 
-    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         self.ensure_has_parent(path).await?;
         let content = Arc::new(Vec::<u8>::new());
-        self.handle.write().await.files.insert(
+        let mut handle = self.handle.write().await;
+        let previous = handle.files.insert(
             path.to_string(),
             AsyncMemoryFile {
                 file_type: VfsFileType::File,
                 content,
             },
         );
+        handle.watchers.emit(match previous {
+            Some(_) => WatchEvent::Modified(path.to_string()),
+            None => WatchEvent::Created(path.to_string()),
+        });
         let writer = AsyncWritableFile {
             content: Cursor::new(vec![]),
             destination: path.to_string(),
@@ -244,7 +269,7 @@ impl AsyncFileSystem for AsyncMemoryFS {
         Ok(Box::new(writer))
     }
 
-    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         let handle = self.handle.write().await;
         let file = handle.files.get(path).ok_or(VfsErrorKind::FileNotFound)?;
         let mut content = Cursor::new(file.content.as_ref().clone());
@@ -280,6 +305,7 @@ impl AsyncFileSystem for AsyncMemoryFS {
             .files
             .remove(path)
             .ok_or(VfsErrorKind::FileNotFound)?;
+        handle.watchers.emit(WatchEvent::Removed(path.to_string()));
         Ok(())
     }
 
@@ -292,13 +318,74 @@ impl AsyncFileSystem for AsyncMemoryFS {
             .files
             .remove(path)
             .ok_or(VfsErrorKind::FileNotFound)?;
+        handle.watchers.emit(WatchEvent::Removed(path.to_string()));
         Ok(())
     }
+
+    async fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.ensure_has_parent(dest).await?;
+        let mut handle = self.handle.write().await;
+        ensure_file(handle.files.get(src).ok_or(VfsErrorKind::FileNotFound)?)?;
+        let file = handle.files.remove(src).expect("File checked above");
+        handle.files.insert(dest.to_string(), file);
+        handle.watchers.emit(WatchEvent::Renamed {
+            from: src.to_string(),
+            to: dest.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.ensure_has_parent(dest).await?;
+        if dest.starts_with(&format!("{src}/")) {
+            return Err(VfsErrorKind::Other("Cannot move a directory into itself".into()).into());
+        }
+        let mut handle = self.handle.write().await;
+        let directory = handle.files.get(src).ok_or(VfsErrorKind::FileNotFound)?;
+        if directory.file_type != VfsFileType::Directory {
+            return Err(VfsErrorKind::Other("Not a directory".into()).into());
+        }
+        let prefix = format!("{src}/");
+        let moved: Vec<String> = handle
+            .files
+            .keys()
+            .filter(|path| *path == src || path.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for path in moved {
+            let file = handle.files.remove(&path).expect("Path collected above");
+            handle
+                .files
+                .insert(format!("{dest}{}", &path[src.len()..]), file);
+        }
+        handle.watchers.emit(WatchEvent::Renamed {
+            from: src.to_string(),
+            to: dest.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        let handle = self.handle.read().await;
+        if !handle.files.contains_key(path) {
+            return Err(VfsErrorKind::FileNotFound.into());
+        }
+        let (sender, receiver) = mpsc::unbounded();
+        handle.watchers.add(path, recursive, move |event| {
+            sender.unbounded_send(event.clone()).is_ok()
+        });
+        Ok(Box::new(receiver))
+    }
 }
 
 #[derive(Debug)]
 struct AsyncMemoryFsImpl {
     files: HashMap<String, AsyncMemoryFile>,
+    watchers: Watchers,
 }
 
 impl AsyncMemoryFsImpl {
@@ -312,7 +399,10 @@ impl AsyncMemoryFsImpl {
                 content: Arc::new(vec![]),
             },
         );
-        Self { files }
+        Self {
+            files,
+            watchers: Watchers::default(),
+        }
     }
 }
 
@@ -327,7 +417,7 @@ struct AsyncMemoryFile {
 mod tests {
     use super::*;
     use crate::async_vfs::AsyncVfsPath;
-    use async_std::io::{ReadExt, WriteExt};
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
     test_async_vfs!(AsyncMemoryFS::new());
 
     #[tokio::test]
@@ -337,8 +427,8 @@ mod tests {
         let _send = &path as &dyn Send;
         {
             let mut file = path.create_file().await.unwrap();
-            write!(file, "Hello world").await.unwrap();
-            write!(file, "!").await.unwrap();
+            file.write_all(b"Hello world").await.unwrap();
+            file.write_all(b"!").await.unwrap();
         }
         {
             let mut file = path.open_file().await.unwrap();
@@ -379,6 +469,21 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn append_file_with_seek() -> VfsResult<()> {
+        let root = AsyncVfsPath::new(AsyncMemoryFS::new());
+        let path = root.join("test_append.txt")?;
+        path.create_file().await?.write_all(b"Testing 1").await?;
+        path.append_file().await?.write_all(b"Testing 2").await?;
+        {
+            let mut file = path.append_file().await?;
+            file.seek(SeekFrom::End(-1)).await?;
+            file.write_all(b"Testing 3").await?;
+        }
+        assert_eq!(path.read_to_string().await?, "Testing 1Testing Testing 3");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn create_dir() {
         let root = AsyncVfsPath::new(AsyncMemoryFS::new());
@@ -427,6 +532,63 @@ mod tests {
         assert_eq!(&dest.read_to_string().await?, "Hello World");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn watch_reports_mutations() -> VfsResult<()> {
+        let root = AsyncVfsPath::new(AsyncMemoryFS::new());
+        let events = root.watch(true).await?;
+        root.join("a")?.create_dir().await?;
+        root.join("a/b.txt")?
+            .create_file()
+            .await?
+            .write_all(b"Hello")
+            .await?;
+        root.join("a/b.txt")?
+            .move_file(&root.join("a/c.txt")?)
+            .await?;
+        root.join("a/c.txt")?.remove_file().await?;
+        root.join("a")?.move_dir(&root.join("d")?).await?;
+        root.join("d")?.remove_dir().await?;
+        // The stream ends once the filesystem is dropped
+        drop(root);
+        assert_eq!(
+            events.collect::<Vec<_>>().await,
+            vec![
+                WatchEvent::Created("/a".into()),
+                WatchEvent::Created("/a/b.txt".into()),
+                WatchEvent::Modified("/a/b.txt".into()),
+                WatchEvent::Renamed {
+                    from: "/a/b.txt".into(),
+                    to: "/a/c.txt".into()
+                },
+                WatchEvent::Removed("/a/c.txt".into()),
+                WatchEvent::Renamed {
+                    from: "/a".into(),
+                    to: "/d".into()
+                },
+                WatchEvent::Removed("/d".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn watch_non_recursive() -> VfsResult<()> {
+        let root = AsyncVfsPath::new(AsyncMemoryFS::new());
+        root.join("a/b")?.create_dir_all().await?;
+        let events = root.join("a")?.watch(false).await?;
+        root.join("a/b/deep.txt")?.create_file().await?;
+        root.join("a/c.txt")?.create_file().await?;
+        drop(root);
+        assert_eq!(
+            events.collect::<Vec<_>>().await,
+            vec![
+                WatchEvent::Created("/a/c.txt".into()),
+                WatchEvent::Modified("/a/c.txt".into()),
+            ]
+        );
+        Ok(())
+    }
 }
 
 fn ensure_file(file: &AsyncMemoryFile) -> VfsResult<()> {
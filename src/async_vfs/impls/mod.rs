@@ -1,6 +1,18 @@
 //! Async Virtual filesystem implementations
 
 pub mod altroot;
+#[cfg(feature = "async-std")]
+pub mod async_std_physical;
+pub mod async_to_sync;
+#[cfg(feature = "embedded-fs")]
+pub mod embedded;
+pub(crate) mod flush;
 pub mod memory;
 pub mod overlay;
+#[cfg(any(feature = "async-std", feature = "smol", feature = "async-tokio"))]
 pub mod physical;
+#[cfg(feature = "smol")]
+pub mod smol_physical;
+pub mod sync_to_async;
+#[cfg(feature = "async-tokio")]
+pub mod tokio_physical;
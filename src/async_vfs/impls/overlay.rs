@@ -1,12 +1,12 @@
 //! An overlay file system combining two filesystems, an upper layer with read/write access and a lower layer with only read access
 
-use crate::async_vfs::{AsyncFileSystem, AsyncVfsPath, SeekAndRead};
+use crate::async_vfs::{AsyncFileSystem, AsyncVfsPath, SeekAndRead, SeekAndWrite};
 use crate::error::VfsErrorKind;
-use crate::{VfsMetadata, VfsResult};
+use crate::impls::overlay::{merge_targets, translate_layer_event, watch_targets};
+use crate::{VfsMetadata, VfsResult, WatchEvent};
 
-use async_std::io::Write;
 use async_trait::async_trait;
-use futures::stream::{Stream, StreamExt};
+use futures::stream::{self, Stream, StreamExt};
 use std::collections::HashSet;
 use std::time::SystemTime;
 
@@ -16,6 +16,9 @@ use std::time::SystemTime;
 ///
 /// NOTE: To allow removing files and directories (e.g. via remove_file()) from the lower layer filesystems, this mechanism creates a `.whiteout` folder in the root of the upper level filesystem to mark removed files
 ///
+/// Watching an overlay watches the path in every layer and reports the changes of each layer as they happen.
+/// Marking a file as removed is reported as its removal. Layers that cannot be watched are skipped.
+///
 #[derive(Debug, Clone)]
 pub struct AsyncOverlayFS {
     layers: Vec<AsyncVfsPath>,
@@ -132,7 +135,7 @@ impl AsyncFileSystem for AsyncOverlayFS {
         self.read_path(path).await?.open_file().await
     }
 
-    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         self.ensure_has_parent(path).await?;
         let result = self.write_path(path)?.create_file().await?;
         let whiteout_path = self.whiteout_path(path)?;
@@ -142,7 +145,7 @@ impl AsyncFileSystem for AsyncOverlayFS {
         Ok(result)
     }
 
-    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         let write_path = self.write_path(path)?;
         if !write_path.exists().await? {
             self.ensure_has_parent(path).await?;
@@ -207,6 +210,64 @@ impl AsyncFileSystem for AsyncOverlayFS {
         whiteout_path.create_file().await?;
         Ok(())
     }
+
+    async fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        // Ensure path exists
+        self.read_path(path).await?;
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for (index, layer) in self.layers.iter().enumerate() {
+            let mut targets = Vec::new();
+            for (target, target_recursive) in watch_targets(index, path, recursive) {
+                targets.push(existing_target(layer, target, target_recursive).await?);
+            }
+            for (target, target_recursive) in merge_targets(targets) {
+                let events = match layer
+                    .join(target.trim_start_matches('/'))?
+                    .watch(target_recursive)
+                    .await
+                {
+                    Ok(events) => events,
+                    // Layers that cannot be watched, like embedded files, are not expected to change
+                    Err(err) if matches!(err.kind(), VfsErrorKind::NotSupported) => continue,
+                    Err(err) => return Err(err),
+                };
+                let layer_root = layer.as_str().to_string();
+                let watched = path.to_string();
+                let events = events.filter_map(move |event| {
+                    futures::future::ready(translate_layer_event(
+                        event,
+                        index,
+                        &layer_root,
+                        &watched,
+                        recursive,
+                    ))
+                });
+                layers.push(events.boxed());
+            }
+        }
+        if layers.is_empty() {
+            return Err(VfsErrorKind::NotSupported.into());
+        }
+        Ok(Box::new(stream::select_all(layers)))
+    }
+}
+
+/// Replaces a target missing in the layer by its closest existing ancestor, watched recursively
+async fn existing_target(
+    layer: &AsyncVfsPath,
+    target: String,
+    recursive: bool,
+) -> VfsResult<(String, bool)> {
+    let (mut target, mut recursive) = (target, recursive);
+    while !target.is_empty() && !layer.join(&target[1..])?.exists().await? {
+        target.truncate(target.rfind('/').unwrap_or(0));
+        recursive = true;
+    }
+    Ok((target, recursive))
 }
 
 #[cfg(test)]
@@ -214,7 +275,7 @@ mod tests {
     use super::*;
     use crate::async_vfs::AsyncMemoryFS;
 
-    use async_std::io::WriteExt;
+    use futures::io::AsyncWriteExt;
     use futures::stream::StreamExt;
 
     test_async_vfs!({
@@ -425,23 +486,67 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn watch_reports_whiteouts_as_removals() -> VfsResult<()> {
+        let (lower_root, upper_root, overlay_root) = create_roots();
+        lower_root.join("foo")?.create_dir().await?;
+        lower_root.join("foo/bar.txt")?.create_file().await?;
+        let events = overlay_root.join("foo")?.watch(false).await?;
+        overlay_root.join("foo/bar.txt")?.remove_file().await?;
+        overlay_root.join("foo/baz.txt")?.create_file().await?;
+        drop((lower_root, upper_root, overlay_root));
+        assert_eq!(
+            events.collect::<Vec<_>>().await,
+            vec![
+                WatchEvent::Removed("/foo/bar.txt".into()),
+                WatchEvent::Created("/foo".into()),
+                WatchEvent::Created("/foo/baz.txt".into()),
+                WatchEvent::Modified("/foo/baz.txt".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn watch_skips_layers_without_watching() -> VfsResult<()> {
+        let upper_root: AsyncVfsPath = AsyncMemoryFS::new().into();
+        let faulty = crate::FaultyFS::new(crate::MemoryFS::new().into());
+        let lower_root: AsyncVfsPath = crate::async_vfs::SyncToAsyncFS::new(faulty).into();
+        lower_root.join("foo")?.create_dir().await?;
+        let overlay_root: AsyncVfsPath =
+            AsyncOverlayFS::new(&[upper_root.clone(), lower_root]).into();
+        let events = overlay_root.join("foo")?.watch(false).await?;
+        overlay_root.join("foo/bar.txt")?.create_file().await?;
+        drop((upper_root, overlay_root));
+        assert_eq!(
+            events.collect::<Vec<_>>().await,
+            vec![
+                WatchEvent::Created("/foo".into()),
+                WatchEvent::Created("/foo/bar.txt".into()),
+                WatchEvent::Modified("/foo/bar.txt".into()),
+            ]
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
+#[cfg(any(feature = "async-std", feature = "smol", feature = "async-tokio"))]
 mod tests_physical {
     use super::*;
     use crate::async_vfs::AsyncPhysicalFS;
 
-    test_async_vfs!(futures::executor::block_on(async {
+    test_async_vfs!({
         let temp_dir = std::env::temp_dir();
         let dir = temp_dir.join(uuid::Uuid::new_v4().to_string());
         let lower_path = dir.join("lower");
-        async_std::fs::create_dir_all(&lower_path).await.unwrap();
+        std::fs::create_dir_all(&lower_path).unwrap();
         let upper_path = dir.join("upper");
-        async_std::fs::create_dir_all(&upper_path).await.unwrap();
+        std::fs::create_dir_all(&upper_path).unwrap();
 
         let upper_root: AsyncVfsPath = AsyncPhysicalFS::new(upper_path).into();
         let lower_root: AsyncVfsPath = AsyncPhysicalFS::new(lower_path).into();
         AsyncOverlayFS::new(&[upper_root, lower_root])
-    }));
+    });
 }
@@ -1,204 +1,101 @@
-//! An async implementation of a "physical" file system implementation using the underlying OS file system
-use crate::async_vfs::{AsyncFileSystem, SeekAndRead};
+//! Async implementations of a "physical" file system using the underlying OS file system
+//!
+//! Every supported async runtime has a native backend, enabled by the cargo feature of the same name:
+//!
+//!  * **[`AsyncStdPhysicalFS`]** - uses `async_std::fs`, requires the `async-std` feature
+//!  * **[`SmolPhysicalFS`]** - uses `smol::fs`, requires the `smol` feature
+//!  * **[`TokioPhysicalFS`]** - uses `tokio::fs`, requires the `async-tokio` feature
+//!
+//! [`AsyncPhysicalFS`] names one of the enabled backends. The `async-std` and `smol` backends run their
+//! blocking I/O on a thread pool of their own and work under any executor, so they are preferred over
+//! the `tokio` backend, which must be used from within a tokio runtime.
+
 use crate::error::VfsErrorKind;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+use crate::impls::inotify_watch::InotifyWatch;
 use crate::path::VfsFileType;
-use crate::{VfsError, VfsMetadata, VfsResult};
-
-use async_std::fs::{File, OpenOptions};
-use async_std::io::{ErrorKind, Write};
-use async_std::path::{Path, PathBuf};
-use async_trait::async_trait;
-use filetime::FileTime;
-use futures::stream::{Stream, StreamExt};
-use std::pin::Pin;
-use std::time::SystemTime;
-use tokio::runtime::Handle;
-
-/// A physical filesystem implementation using the underlying OS file system
-#[derive(Debug)]
-pub struct AsyncPhysicalFS {
-    root: Pin<PathBuf>,
-}
-
-impl AsyncPhysicalFS {
-    /// Create a new physical filesystem rooted in `root`
-    pub fn new<T: AsRef<Path>>(root: T) -> Self {
-        AsyncPhysicalFS {
-            root: Pin::new(root.as_ref().to_path_buf()),
+use crate::{VfsError, VfsMetadata};
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+use crate::{VfsResult, WatchEvent};
+
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+use futures::stream::Stream;
+use std::fs::Metadata;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+use std::path::PathBuf;
+
+#[cfg(feature = "async-std")]
+pub use super::async_std_physical::AsyncStdPhysicalFS;
+#[cfg(feature = "smol")]
+pub use super::smol_physical::SmolPhysicalFS;
+#[cfg(feature = "async-tokio")]
+pub use super::tokio_physical::TokioPhysicalFS;
+
+/// The physical filesystem backend of the enabled async runtime
+#[cfg(feature = "async-std")]
+pub type AsyncPhysicalFS = AsyncStdPhysicalFS;
+
+/// The physical filesystem backend of the enabled async runtime
+#[cfg(all(feature = "smol", not(feature = "async-std")))]
+pub type AsyncPhysicalFS = SmolPhysicalFS;
+
+/// The physical filesystem backend of the enabled async runtime
+#[cfg(all(
+    feature = "async-tokio",
+    not(any(feature = "async-std", feature = "smol"))
+))]
+pub type AsyncPhysicalFS = TokioPhysicalFS;
+
+pub(crate) fn to_vfs_metadata(metadata: Metadata) -> VfsMetadata {
+    if metadata.is_dir() {
+        VfsMetadata {
+            file_type: VfsFileType::Directory,
+            len: 0,
+            modified: metadata.modified().ok(),
+            created: metadata.created().ok(),
+            accessed: metadata.accessed().ok(),
         }
-    }
-
-    fn get_path(&self, mut path: &str) -> PathBuf {
-        if path.starts_with('/') {
-            path = &path[1..];
+    } else {
+        VfsMetadata {
+            file_type: VfsFileType::File,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            created: metadata.created().ok(),
+            accessed: metadata.accessed().ok(),
         }
-        self.root.join(path)
     }
 }
 
-/// Runs normal blocking io on a tokio thread.
-/// Requires a tokio runtime.
-async fn blocking_io<F>(f: F) -> Result<(), VfsError>
-where
-    F: FnOnce() -> std::io::Result<()> + Send + 'static,
-{
-    if Handle::try_current().is_ok() {
-        let result = tokio::task::spawn_blocking(f).await;
-
-        match result {
-            Ok(val) => val,
-            Err(err) => {
-                return Err(VfsError::from(VfsErrorKind::Other(format!(
-                    "Tokio Concurrency Error: {err}"
-                ))));
-            }
-        }?;
-
-        Ok(())
+/// The error for creating a directory where an entry with the given metadata already exists
+pub(crate) fn already_exists(metadata: Metadata) -> VfsError {
+    if metadata.is_dir() {
+        VfsError::from(VfsErrorKind::DirectoryExists)
     } else {
-        Err(VfsError::from(VfsErrorKind::NotSupported))
+        VfsError::from(VfsErrorKind::FileExists)
     }
 }
 
-#[async_trait]
-impl AsyncFileSystem for AsyncPhysicalFS {
-    async fn read_dir(
-        &self,
-        path: &str,
-    ) -> VfsResult<Box<dyn Unpin + Stream<Item = String> + Send>> {
-        let entries = Box::new(
-            self.get_path(path)
-                .read_dir()
-                .await?
-                .map(|entry| entry.unwrap().file_name().into_string().unwrap()),
-        );
-        Ok(entries)
-    }
-
-    async fn create_dir(&self, path: &str) -> VfsResult<()> {
-        let fs_path = self.get_path(path);
-        match async_std::fs::create_dir(&fs_path).await {
-            Ok(()) => Ok(()),
-            Err(e) => match e.kind() {
-                ErrorKind::AlreadyExists => {
-                    let metadata = async_std::fs::metadata(&fs_path).await.unwrap();
-                    if metadata.is_dir() {
-                        return Err(VfsError::from(VfsErrorKind::DirectoryExists));
-                    }
-                    Err(VfsError::from(VfsErrorKind::FileExists))
-                }
-                _ => Err(e.into()),
-            },
-        }
-    }
-
-    async fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send + Unpin>> {
-        Ok(Box::new(File::open(self.get_path(path)).await?))
-    }
-
-    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
-        Ok(Box::new(File::create(self.get_path(path)).await?))
-    }
-
-    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn Write + Send + Unpin>> {
-        Ok(Box::new(
-            OpenOptions::new()
-                .write(true)
-                .append(true)
-                .open(self.get_path(path))
-                .await?,
-        ))
-    }
-
-    async fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
-        let metadata = self.get_path(path).metadata().await?;
-        Ok(if metadata.is_dir() {
-            VfsMetadata {
-                file_type: VfsFileType::Directory,
-                len: 0,
-                modified: metadata.modified().ok(),
-                created: metadata.created().ok(),
-                accessed: metadata.accessed().ok(),
-            }
-        } else {
-            VfsMetadata {
-                file_type: VfsFileType::File,
-                len: metadata.len(),
-                modified: metadata.modified().ok(),
-                created: metadata.created().ok(),
-                accessed: metadata.accessed().ok(),
-            }
-        })
-    }
-
-    async fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
-        let path = self.get_path(path);
-
-        blocking_io(move || filetime::set_file_mtime(path, FileTime::from(time))).await?;
-
-        Ok(())
-    }
-
-    async fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
-        let path = self.get_path(path);
-
-        blocking_io(move || filetime::set_file_atime(path, FileTime::from(time))).await?;
-
-        Ok(())
-    }
-
-    async fn exists(&self, path: &str) -> VfsResult<bool> {
-        Ok(self.get_path(path).exists().await)
-    }
-
-    async fn remove_file(&self, path: &str) -> VfsResult<()> {
-        async_std::fs::remove_file(self.get_path(path)).await?;
-        Ok(())
-    }
-
-    async fn remove_dir(&self, path: &str) -> VfsResult<()> {
-        async_std::fs::remove_dir(self.get_path(path)).await?;
-        Ok(())
-    }
-
-    async fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
-        async_std::fs::copy(self.get_path(src), self.get_path(dest)).await?;
-        Ok(())
-    }
-
-    async fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
-        async_std::fs::rename(self.get_path(src), self.get_path(dest)).await?;
-
-        Ok(())
-    }
-
-    async fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
-        let result = async_std::fs::rename(self.get_path(src), self.get_path(dest)).await;
-        if result.is_err() {
-            // Error possibly due to different filesystems, return not supported and let the fallback handle it
-            return Err(VfsErrorKind::NotSupported.into());
-        }
-        Ok(())
-    }
+/// Watches a path below `root` with inotify, reading the events on the blocking thread pool
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+pub(crate) async fn watch(
+    root: PathBuf,
+    path: &str,
+    recursive: bool,
+) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+    let path = path.to_string();
+    let watch = blocking::unblock(move || InotifyWatch::new(&root, &path, recursive)).await?;
+    Ok(Box::new(blocking::Unblock::new(watch)))
 }
 
 #[cfg(test)]
+#[cfg(any(feature = "async-std", feature = "smol", feature = "async-tokio"))]
 mod tests {
     use super::*;
     use crate::async_vfs::AsyncVfsPath;
 
-    use async_std::io::ReadExt;
-    use async_std::io::WriteExt;
-    use async_std::path::Path;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
     use futures::stream::StreamExt;
-
-    test_async_vfs!(futures::executor::block_on(async {
-        let temp_dir = std::env::temp_dir();
-        let dir = temp_dir.join(uuid::Uuid::new_v4().to_string());
-        async_std::fs::create_dir_all(&dir).await.unwrap();
-        AsyncPhysicalFS::new(dir)
-    }));
-    test_async_vfs_readonly!({ AsyncPhysicalFS::new("test/test_directory") });
+    use std::path::Path;
 
     fn create_root() -> AsyncVfsPath {
         AsyncPhysicalFS::new(std::env::current_dir().unwrap()).into()
@@ -206,7 +103,7 @@ mod tests {
 
     #[tokio::test]
     async fn open_file() {
-        let expected = async_std::fs::read_to_string("Cargo.toml").await.unwrap();
+        let expected = std::fs::read_to_string("Cargo.toml").unwrap();
         let root = create_root();
         let mut string = String::new();
         root.join("Cargo.toml")
@@ -224,7 +121,7 @@ mod tests {
     async fn create_file() {
         let root = create_root();
         let _string = String::new();
-        let _ = async_std::fs::remove_file("target/test.txt").await;
+        let _ = std::fs::remove_file("target/test.txt");
         root.join("target/test.txt")
             .unwrap()
             .create_file()
@@ -241,7 +138,7 @@ mod tests {
     async fn append_file() {
         let root = create_root();
         let _string = String::new();
-        let _ = async_std::fs::remove_file("target/test_append.txt").await;
+        let _ = std::fs::remove_file("target/test_append.txt");
         let path = Box::pin(root.join("target/test_append.txt").unwrap());
         path.create_file()
             .await
@@ -255,15 +152,13 @@ mod tests {
             .write_all(b"Testing 2")
             .await
             .unwrap();
-        let read = async_std::fs::read_to_string("target/test_append.txt")
-            .await
-            .unwrap();
+        let read = std::fs::read_to_string("target/test_append.txt").unwrap();
         assert_eq!(read, "Testing 1Testing 2");
     }
 
     #[tokio::test]
     async fn read_dir() {
-        let _expected = async_std::fs::read_to_string("Cargo.toml").await.unwrap();
+        let _expected = std::fs::read_to_string("Cargo.toml").unwrap();
         let root = create_root();
         let entries: Vec<_> = root.read_dir().await.unwrap().collect().await;
         let map: Vec<_> = entries
@@ -276,7 +171,7 @@ mod tests {
 
     #[tokio::test]
     async fn create_dir() {
-        let _ = async_std::fs::remove_dir("target/fs_test").await;
+        let _ = std::fs::remove_dir("target/fs_test");
         let root = create_root();
         root.join("target/fs_test")
             .unwrap()
@@ -284,14 +179,14 @@ mod tests {
             .await
             .unwrap();
         let path = Path::new("target/fs_test");
-        assert!(path.exists().await, "Path was not created");
-        assert!(path.is_dir().await, "Path is not a directory");
-        async_std::fs::remove_dir("target/fs_test").await.unwrap();
+        assert!(path.exists(), "Path was not created");
+        assert!(path.is_dir(), "Path is not a directory");
+        std::fs::remove_dir("target/fs_test").unwrap();
     }
 
     #[tokio::test]
     async fn file_metadata() {
-        let expected = async_std::fs::read_to_string("Cargo.toml").await.unwrap();
+        let expected = std::fs::read_to_string("Cargo.toml").unwrap();
         let root = create_root();
         let metadata = root.join("Cargo.toml").unwrap().metadata().await.unwrap();
         assert_eq!(metadata.len, expected.len() as u64);
@@ -308,4 +203,27 @@ mod tests {
         assert_eq!(metadata.len, 0);
         assert_eq!(metadata.file_type, VfsFileType::Directory);
     }
+
+    #[tokio::test]
+    #[cfg(all(feature = "inotify", target_os = "linux"))]
+    async fn watch_reports_changes() -> VfsResult<()> {
+        use crate::WatchEvent;
+
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        let root: AsyncVfsPath = AsyncPhysicalFS::new(&dir).into();
+        let mut events = root.watch(false).await?;
+        root.join("foo")?.create_dir().await?;
+        assert_eq!(
+            events.next().await,
+            Some(WatchEvent::Created("/foo".into()))
+        );
+        root.join("foo")?.remove_dir().await?;
+        assert_eq!(
+            events.next().await,
+            Some(WatchEvent::Removed("/foo".into()))
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,359 @@
+//! An adapter using a synchronous filesystem as an async filesystem
+
+use crate::async_vfs::impls::flush::FlushOnDrop;
+use crate::async_vfs::{AsyncFileSystem, SeekAndRead, SeekAndWrite};
+use crate::impls::seek::resolve_seek;
+use crate::{FileSystem, VfsMetadata, VfsResult, WatchEvent};
+
+use async_trait::async_trait;
+use blocking::{unblock, Unblock};
+use futures::io::{AsyncRead, AsyncSeek, SeekFrom};
+use futures::stream::Stream;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+/// Number of directory entries fetched from the synchronous iterator per blocking call
+const READ_DIR_BATCH_SIZE: usize = 64;
+/// Maximum number of bytes read from or written to a synchronous file per blocking call
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// An async filesystem running the calls of a synchronous [`FileSystem`] on a blocking thread pool
+///
+/// This makes all synchronous implementations, including custom ones, usable through
+/// [`AsyncVfsPath`](crate::async_vfs::AsyncVfsPath). Directory iterators are adapted into streams
+/// and file handles into async readers and writers, which perform the synchronous I/O on the thread pool as well.
+/// Writes are buffered, so writers should be closed to learn about errors writing the last data.
+///
+/// ```
+/// use futures::io::AsyncReadExt;
+/// use vfs::async_vfs::{AsyncVfsPath, SyncToAsyncFS};
+/// use vfs::{PhysicalFS, VfsError};
+///
+/// # tokio_test::block_on(async {
+/// let root: AsyncVfsPath = SyncToAsyncFS::new(PhysicalFS::new("test/test_directory")).into();
+///
+/// let mut content = String::new();
+/// root.join("a.txt")?.open_file().await?.read_to_string(&mut content).await?;
+/// assert_eq!(content, "a");
+/// # Ok::<(), VfsError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct SyncToAsyncFS {
+    fs: Arc<dyn FileSystem>,
+}
+
+impl SyncToAsyncFS {
+    /// Create a new async filesystem delegating to the given synchronous filesystem
+    pub fn new<T: FileSystem>(fs: T) -> Self {
+        SyncToAsyncFS { fs: Arc::new(fs) }
+    }
+
+    /// Runs a call of the synchronous filesystem on the blocking thread pool
+    async fn unblock<T, F>(&self, path: &str, call: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn FileSystem, &str) -> T + Send + 'static,
+    {
+        let fs = self.fs.clone();
+        let path = path.to_string();
+        unblock(move || call(&*fs, &path)).await
+    }
+}
+
+#[async_trait]
+impl AsyncFileSystem for SyncToAsyncFS {
+    async fn read_dir(
+        &self,
+        path: &str,
+    ) -> VfsResult<Box<dyn Unpin + Stream<Item = String> + Send>> {
+        let entries = self.unblock(path, |fs, path| fs.read_dir(path)).await?;
+        Ok(Box::new(Unblock::with_capacity(
+            READ_DIR_BATCH_SIZE,
+            entries,
+        )))
+    }
+
+    async fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.unblock(path, |fs, path| fs.create_dir(path)).await
+    }
+
+    async fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send + Unpin>> {
+        let file = self.unblock(path, |fs, path| fs.open_file(path)).await?;
+        Ok(Box::new(BlockingReader {
+            file: Unblock::with_capacity(READ_CHUNK_SIZE, file),
+            position: 0,
+        }))
+    }
+
+    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        let file = self.unblock(path, |fs, path| fs.create_file(path)).await?;
+        Ok(Box::new(FlushOnDrop::new(Unblock::with_capacity(
+            READ_CHUNK_SIZE,
+            file,
+        ))))
+    }
+
+    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        let file = self.unblock(path, |fs, path| fs.append_file(path)).await?;
+        Ok(Box::new(FlushOnDrop::new(Unblock::with_capacity(
+            READ_CHUNK_SIZE,
+            file,
+        ))))
+    }
+
+    async fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.unblock(path, |fs, path| fs.metadata(path)).await
+    }
+
+    async fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.unblock(path, move |fs, path| fs.set_creation_time(path, time))
+            .await
+    }
+
+    async fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.unblock(path, move |fs, path| fs.set_modification_time(path, time))
+            .await
+    }
+
+    async fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.unblock(path, move |fs, path| fs.set_access_time(path, time))
+            .await
+    }
+
+    async fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.unblock(path, |fs, path| fs.exists(path)).await
+    }
+
+    async fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.unblock(path, |fs, path| fs.remove_file(path)).await
+    }
+
+    async fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.unblock(path, |fs, path| fs.remove_dir(path)).await
+    }
+
+    async fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = dest.to_string();
+        self.unblock(src, move |fs, src| fs.copy_file(src, &dest))
+            .await
+    }
+
+    async fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = dest.to_string();
+        self.unblock(src, move |fs, src| fs.move_file(src, &dest))
+            .await
+    }
+
+    async fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = dest.to_string();
+        self.unblock(src, move |fs, src| fs.move_dir(src, &dest))
+            .await
+    }
+
+    async fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        let events = self
+            .unblock(path, move |fs, path| fs.watch(path, recursive))
+            .await?;
+        Ok(Box::new(Unblock::new(events)))
+    }
+}
+
+/// A reader performing the reads of a synchronous file on the thread pool
+///
+/// The file is read ahead, so its position is tracked here to resolve relative seeks.
+struct BlockingReader<T> {
+    file: Unblock<T>,
+    position: u64,
+}
+
+impl<T: io::Read + Send + 'static> AsyncRead for BlockingReader<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = futures::ready!(Pin::new(&mut self.file).poll_read(cx, buf))?;
+        self.position += read as u64;
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl<T: io::Seek + Send + 'static> AsyncSeek for BlockingReader<T> {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let pos = match pos {
+            SeekFrom::Current(_) => SeekFrom::Start(resolve_seek(pos, self.position, 0)?),
+            pos => pos,
+        };
+        let position = futures::ready!(Pin::new(&mut self.file).poll_seek(cx, pos))?;
+        self.position = position;
+        Poll::Ready(Ok(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_vfs::AsyncVfsPath;
+    use crate::error::VfsErrorKind;
+    use crate::impls::faulty::{Fault, FaultRule};
+    use crate::impls::tracing::TraceOperation;
+    use crate::{FaultyFS, MemoryFS, OverlayFS, VfsPath};
+    use futures::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+    use futures::stream::StreamExt;
+    test_async_vfs!(SyncToAsyncFS::new(MemoryFS::new()));
+
+    #[tokio::test]
+    async fn seek_in_buffered_reader() -> VfsResult<()> {
+        let memory = MemoryFS::new();
+        let content: Vec<u8> = (0..200_000).map(|index| (index % 251) as u8).collect();
+        memory.create_file("/data.bin")?.write_all(&content)?;
+        let root: AsyncVfsPath = SyncToAsyncFS::new(memory).into();
+
+        let mut file = root.join("data.bin")?.open_file().await?;
+        let mut buffer = [0; 10];
+        file.read_exact(&mut buffer).await?;
+        assert_eq!(&buffer[..], &content[..10]);
+        assert_eq!(file.seek(SeekFrom::Current(5)).await?, 15);
+        file.read_exact(&mut buffer).await?;
+        assert_eq!(&buffer[..], &content[15..25]);
+        assert_eq!(file.seek(SeekFrom::End(-10)).await?, 199_990);
+        let mut rest = vec![];
+        file.read_to_end(&mut rest).await?;
+        assert_eq!(rest, &content[199_990..]);
+
+        file.seek(SeekFrom::Start(0)).await?;
+        let mut all = vec![];
+        file.read_to_end(&mut all).await?;
+        assert_eq!(all, content);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn abandoned_operations_do_not_answer_new_calls() -> VfsResult<()> {
+        let memory = MemoryFS::new();
+        memory.create_file("/data.txt")?.write_all(b"0123456789")?;
+        let root: AsyncVfsPath = SyncToAsyncFS::new(memory).into();
+
+        let mut file = root.join("data.txt")?.open_file().await?;
+        let _ = futures::poll!(file.seek(SeekFrom::Start(5)));
+        assert_eq!(file.seek(SeekFrom::Start(2)).await?, 2);
+        let mut buffer = [0; 3];
+        file.read_exact(&mut buffer).await?;
+        assert_eq!(&buffer, b"234");
+
+        let mut file = root.join("out.txt")?.create_file().await?;
+        let _ = futures::poll!(file.write(b"abc"));
+        assert_eq!(file.write(b"defg").await?, 4);
+        file.close().await?;
+        assert_eq!(root.join("out.txt")?.read_to_string().await?, "abcdefg");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_dir_in_batches() -> VfsResult<()> {
+        let memory = MemoryFS::new();
+        for index in 0..150 {
+            memory.create_file(&format!("/{index:03}.txt"))?;
+        }
+        let root: AsyncVfsPath = SyncToAsyncFS::new(memory).into();
+        let mut names: Vec<String> = root
+            .read_dir()
+            .await?
+            .map(|path| path.filename())
+            .collect()
+            .await;
+        names.sort();
+        assert_eq!(names.len(), 150);
+        assert_eq!(names[149], "149.txt");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wraps_composed_filesystems() -> VfsResult<()> {
+        let lower: VfsPath = MemoryFS::new().into();
+        lower
+            .join("lower.txt")?
+            .create_file()?
+            .write_all(b"lower")?;
+        let upper: VfsPath = MemoryFS::new().into();
+        let root: AsyncVfsPath = SyncToAsyncFS::new(OverlayFS::new(&[upper, lower])).into();
+
+        let file = root.join("lower.txt")?;
+        assert_eq!(file.read_to_string().await?, "lower");
+        file.append_file().await?.write_all(b" and upper").await?;
+        assert_eq!(file.read_to_string().await?, "lower and upper");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn errors_are_passed_through() -> VfsResult<()> {
+        let root: AsyncVfsPath = SyncToAsyncFS::new(MemoryFS::new()).into();
+        let result = root.join("missing.txt")?.open_file().await;
+        assert_eq!(
+            result.map(|_| ()).unwrap_err().to_string(),
+            "Could not open file for '/missing.txt': The file or directory could not be found"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn copy_dir_concurrent_collects_errors() -> VfsResult<()> {
+        let src: AsyncVfsPath = SyncToAsyncFS::new(MemoryFS::new()).into();
+        src.join("dir")?.create_dir().await?;
+        for name in ["a.txt", "b.lock", "dir/c.txt", "dir/d.lock"] {
+            src.join(name)?
+                .create_file()
+                .await?
+                .write_all(b"data")
+                .await?;
+        }
+        let dest: AsyncVfsPath = SyncToAsyncFS::new(
+            FaultyFS::new(MemoryFS::new().into()).with_rule(
+                FaultRule::new(Fault::Error(VfsErrorKind::ReadOnly))
+                    .on(TraceOperation::CreateFile)
+                    .matching("*.lock"),
+            ),
+        )
+        .into();
+
+        let stats = src.copy_dir_concurrent(&dest.join("copy")?, 3).await?;
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.directories, 2);
+        assert_eq!(stats.errors.len(), 2);
+        assert!(!stats.is_success());
+        assert_eq!(dest.join("copy/a.txt")?.read_to_string().await?, "data");
+        assert_eq!(dest.join("copy/dir/c.txt")?.read_to_string().await?, "data");
+        assert!(!dest.join("copy/dir/d.lock")?.exists().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn watch_events_are_streamed() -> VfsResult<()> {
+        let root: AsyncVfsPath = SyncToAsyncFS::new(MemoryFS::new()).into();
+        let mut events = root.watch(true).await?;
+        root.join("foo")?.create_dir().await?;
+        assert_eq!(
+            events.next().await,
+            Some(WatchEvent::Created("/foo".into()))
+        );
+        root.join("foo")?.remove_dir().await?;
+        drop(root);
+        assert_eq!(
+            events.collect::<Vec<_>>().await,
+            vec![WatchEvent::Removed("/foo".into())]
+        );
+        Ok(())
+    }
+}
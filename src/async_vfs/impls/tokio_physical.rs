@@ -0,0 +1,272 @@
+//! A "physical" file system implementation for the tokio runtime
+use crate::async_vfs::impls::flush::FlushOnDrop;
+use crate::async_vfs::impls::physical::{already_exists, to_vfs_metadata};
+use crate::async_vfs::{AsyncFileSystem, SeekAndRead, SeekAndWrite};
+use crate::error::VfsErrorKind;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+use crate::{async_vfs::impls::physical, WatchEvent};
+use crate::{VfsError, VfsMetadata, VfsResult};
+
+use async_trait::async_trait;
+use filetime::FileTime;
+use futures::io::{AsyncRead, AsyncSeek, AsyncWrite};
+use futures::ready;
+use futures::stream::{self, Stream, StreamExt};
+use std::io::{self, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncRead as _, AsyncSeek as _, AsyncWrite as _, ReadBuf};
+
+/// A physical filesystem implementation using the underlying OS file system through `tokio::fs`
+///
+/// Requires the `async-tokio` feature. All operations must be awaited from within a tokio runtime.
+///
+/// Writes complete on the blocking thread pool, so files should be closed with `close()` once written.
+/// Dropping a file with unflushed data flushes it by blocking the dropping thread, which stalls a runtime worker.
+#[derive(Debug)]
+pub struct TokioPhysicalFS {
+    root: PathBuf,
+}
+
+impl TokioPhysicalFS {
+    /// Create a new physical filesystem rooted in `root`
+    pub fn new<T: AsRef<Path>>(root: T) -> Self {
+        TokioPhysicalFS {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn get_path(&self, mut path: &str) -> PathBuf {
+        if path.starts_with('/') {
+            path = &path[1..];
+        }
+        self.root.join(path)
+    }
+}
+
+/// Runs normal blocking io on the tokio blocking thread pool
+async fn blocking_io<F>(f: F) -> Result<(), VfsError>
+where
+    F: FnOnce() -> std::io::Result<()> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => Ok(result?),
+        Err(err) => Err(VfsError::from(VfsErrorKind::Other(format!(
+            "Tokio Concurrency Error: {err}"
+        )))),
+    }
+}
+
+/// Adapts a tokio file to the `futures` I/O traits
+struct TokioFile {
+    file: File,
+    seeking: bool,
+}
+
+impl TokioFile {
+    fn new(file: File) -> Self {
+        TokioFile {
+            file,
+            seeking: false,
+        }
+    }
+}
+
+impl AsyncRead for TokioFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(Pin::new(&mut self.file).poll_read(cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl AsyncWrite for TokioFile {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.file).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSeek for TokioFile {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        if !self.seeking {
+            // A seek can only be started once pending writes have completed
+            ready!(Pin::new(&mut self.file).poll_complete(cx))?;
+            Pin::new(&mut self.file).start_seek(pos)?;
+            self.seeking = true;
+        }
+        let result = ready!(Pin::new(&mut self.file).poll_complete(cx));
+        self.seeking = false;
+        Poll::Ready(result)
+    }
+}
+
+#[async_trait]
+impl AsyncFileSystem for TokioPhysicalFS {
+    async fn read_dir(
+        &self,
+        path: &str,
+    ) -> VfsResult<Box<dyn Unpin + Stream<Item = String> + Send>> {
+        let entries = tokio::fs::read_dir(self.get_path(path)).await?;
+        let entries = stream::unfold(entries, |mut entries| async move {
+            let entry = entries.next_entry().await.unwrap()?;
+            Some((entry.file_name().into_string().unwrap(), entries))
+        });
+        Ok(Box::new(Box::pin(entries.fuse())))
+    }
+
+    async fn create_dir(&self, path: &str) -> VfsResult<()> {
+        let fs_path = self.get_path(path);
+        match tokio::fs::create_dir(&fs_path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(already_exists(tokio::fs::metadata(&fs_path).await?))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send + Unpin>> {
+        Ok(Box::new(TokioFile::new(
+            File::open(self.get_path(path)).await?,
+        )))
+    }
+
+    async fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        Ok(Box::new(FlushOnDrop::new(TokioFile::new(
+            File::create(self.get_path(path)).await?,
+        ))))
+    }
+
+    async fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
+        Ok(Box::new(FlushOnDrop::new(TokioFile::new(
+            OpenOptions::new()
+                .write(true)
+                .append(true)
+                .open(self.get_path(path))
+                .await?,
+        ))))
+    }
+
+    async fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let metadata = tokio::fs::metadata(self.get_path(path)).await?;
+        Ok(to_vfs_metadata(metadata))
+    }
+
+    async fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        let path = self.get_path(path);
+
+        blocking_io(move || filetime::set_file_mtime(path, FileTime::from(time))).await
+    }
+
+    async fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        let path = self.get_path(path);
+
+        blocking_io(move || filetime::set_file_atime(path, FileTime::from(time))).await
+    }
+
+    async fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(tokio::fs::metadata(self.get_path(path)).await.is_ok())
+    }
+
+    async fn remove_file(&self, path: &str) -> VfsResult<()> {
+        tokio::fs::remove_file(self.get_path(path)).await?;
+        Ok(())
+    }
+
+    async fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        tokio::fs::remove_dir(self.get_path(path)).await?;
+        Ok(())
+    }
+
+    async fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        tokio::fs::copy(self.get_path(src), self.get_path(dest)).await?;
+        Ok(())
+    }
+
+    async fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        tokio::fs::rename(self.get_path(src), self.get_path(dest)).await?;
+
+        Ok(())
+    }
+
+    async fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let result = tokio::fs::rename(self.get_path(src), self.get_path(dest)).await;
+        if result.is_err() {
+            // Error possibly due to different filesystems, return not supported and let the fallback handle it
+            return Err(VfsErrorKind::NotSupported.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(all(feature = "inotify", target_os = "linux"))]
+    async fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        physical::watch(self.root.clone(), path, recursive).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_vfs::AsyncVfsPath;
+    use futures::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    test_async_vfs!({
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        TokioPhysicalFS::new(dir)
+    });
+    test_async_vfs_readonly!({ TokioPhysicalFS::new("test/test_directory") });
+
+    #[tokio::test]
+    async fn written_data_is_visible_after_drop() -> VfsResult<()> {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        let root: AsyncVfsPath = TokioPhysicalFS::new(&dir).into();
+        let path = root.join("a.txt")?;
+        for _ in 0..20 {
+            path.create_file().await?.write_all(b"Hello").await?;
+            assert_eq!(std::fs::read_to_string(dir.join("a.txt"))?, "Hello");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn seek_after_read() -> VfsResult<()> {
+        let root: AsyncVfsPath = TokioPhysicalFS::new("test/test_directory").into();
+        let mut file = root.join("a.txt")?.open_file().await?;
+        let mut content = String::new();
+        file.read_to_string(&mut content).await?;
+        assert_eq!(file.seek(SeekFrom::Start(0)).await?, 0);
+        content.clear();
+        file.read_to_string(&mut content).await?;
+        assert_eq!(content, "a");
+        Ok(())
+    }
+}
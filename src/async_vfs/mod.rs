@@ -5,15 +5,33 @@
 //!
 //! This module currently has the following asynchronous file system implementations:
 //!
-//!  * **[`AsyncPhysicalFS`](impls/physical/struct.AsyncPhysicalFS.html)** - the actual filesystem of the underlying OS
+//!  * **[`AsyncPhysicalFS`](impls/physical/type.AsyncPhysicalFS.html)** - the actual filesystem of the underlying OS, requires a runtime feature (see below)
 //!  * **[`AsyncMemoryFS`](impls/memory/struct.AsyncMemoryFS.html)** - an ephemeral in-memory implementation (intended for unit tests)
 //!  * **[`AsyncAltrootFS`](impls/altroot/struct.AsyncAltrootFS.html)** - a file system with its root in a particular directory of another filesystem
 //!  * **[`AsyncOverlayFS`](impls/overlay/struct.AsyncOverlayFS.html)** - a union file system consisting of a read/writable upper layer and several read-only lower layers
+//!  * **[`AsyncEmbeddedFS`](impls/embedded/struct.AsyncEmbeddedFS.html)** - a read-only file system embedded in the executable, requires `embedded-fs` feature
+//!  * **[`SyncToAsyncFS`](impls/sync_to_async/struct.SyncToAsyncFS.html)** - an adapter making any synchronous [`FileSystem`](crate::FileSystem) usable as an async file system
+//!  * **[`AsyncToSyncFS`](impls/async_to_sync/struct.AsyncToSyncFS.html)** - an adapter making any async file system usable as a synchronous [`FileSystem`](crate::FileSystem)
+//!
+//! # Runtimes
+//!
+//! The `async-vfs-core` feature enables this module without tying it to an async runtime. The physical
+//! filesystem is provided by a native backend of the runtime chosen by one of the following features,
+//! each of which implies `async-vfs-core`:
+//!
+//!  * `async-std` - [`AsyncStdPhysicalFS`](impls/physical/struct.AsyncStdPhysicalFS.html) using `async_std::fs`
+//!  * `smol` - [`SmolPhysicalFS`](impls/physical/struct.SmolPhysicalFS.html) using `smol::fs`
+//!  * `async-tokio` - [`TokioPhysicalFS`](impls/physical/struct.TokioPhysicalFS.html) using `tokio::fs`
+//!
+//! [`AsyncPhysicalFS`](impls/physical/type.AsyncPhysicalFS.html) names the backend of the enabled runtime.
+//! The `async-vfs` feature enables the `async-std` backend, as it did before the runtime became selectable.
 //!
 //! # Usage Examples
 //!
 //! ```
-//! use async_std::io::{ReadExt, WriteExt};
+//! # #[cfg(any(feature = "async-std", feature = "smol", feature = "async-tokio"))]
+//! # {
+//! use futures::io::{AsyncReadExt, AsyncWriteExt};
 //! use vfs::async_vfs::{AsyncVfsPath, AsyncPhysicalFS};
 //! use vfs::VfsError;
 //!
@@ -25,11 +43,12 @@
 //! root.join("README.md")?.open_file().await?.read_to_string(&mut content).await?;
 //! assert!(content.contains("vfs"));
 //! # Ok::<(), VfsError>(())
-//! # });
+//! # }).unwrap();
+//! # }
 //! ```
 //!
 //! ```
-//! use async_std::io::{ReadExt, WriteExt};
+//! use futures::io::{AsyncReadExt, AsyncWriteExt};
 //! use vfs::async_vfs::{AsyncVfsPath, AsyncMemoryFS};
 //! use vfs::VfsError;
 //!
@@ -58,7 +77,18 @@ pub mod path;
 
 pub use filesystem::AsyncFileSystem;
 pub use impls::altroot::AsyncAltrootFS;
+pub use impls::async_to_sync::AsyncToSyncFS;
+#[cfg(feature = "embedded-fs")]
+pub use impls::embedded::AsyncEmbeddedFS;
 pub use impls::memory::AsyncMemoryFS;
 pub use impls::overlay::AsyncOverlayFS;
+#[cfg(any(feature = "async-std", feature = "smol", feature = "async-tokio"))]
 pub use impls::physical::AsyncPhysicalFS;
+#[cfg(feature = "async-std")]
+pub use impls::physical::AsyncStdPhysicalFS;
+#[cfg(feature = "smol")]
+pub use impls::physical::SmolPhysicalFS;
+#[cfg(feature = "async-tokio")]
+pub use impls::physical::TokioPhysicalFS;
+pub use impls::sync_to_async::SyncToAsyncFS;
 pub use path::*;
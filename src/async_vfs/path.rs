@@ -7,14 +7,18 @@ use crate::async_vfs::AsyncFileSystem;
 use crate::error::{VfsError, VfsErrorKind};
 use crate::path::PathLike;
 use crate::path::VfsFileType;
-use crate::{VfsMetadata, VfsResult};
+use crate::progress::{DirOptions, Progress, CHUNK_SIZE};
+use crate::{VfsMetadata, VfsResult, WatchEvent};
 
 use async_recursion::async_recursion;
-use async_std::io::{Read, ReadExt, Seek, Write};
-use async_std::sync::Arc;
-use async_std::task::{Context, Poll};
-use futures::{future::BoxFuture, FutureExt, Stream, StreamExt};
+use futures::io::{
+    AsyncRead as Read, AsyncReadExt, AsyncSeek as Seek, AsyncWrite as Write, AsyncWriteExt,
+};
+use futures::{future, future::BoxFuture, stream, FutureExt, Stream, StreamExt};
+use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::SystemTime;
 
 /// Trait combining Seek and Read, return value for opening files
@@ -22,6 +26,37 @@ pub trait SeekAndRead: Seek + Read {}
 
 impl<T> SeekAndRead for T where T: Seek + Read {}
 
+/// Trait combining Seek and Write, return value for writing files
+pub trait SeekAndWrite: Seek + Write {}
+
+impl<T> SeekAndWrite for T where T: Seek + Write {}
+
+/// Aggregated outcome of a concurrent directory operation like [`AsyncVfsPath::copy_dir_concurrent()`]
+#[derive(Debug, Default)]
+pub struct DirStats {
+    /// Number of files copied or removed
+    pub files: u64,
+    /// Number of directories created or removed, including the directory the operation was called on
+    pub directories: u64,
+    /// Errors of the entries that could not be processed, in the order they occurred
+    pub errors: Vec<VfsError>,
+}
+
+impl DirStats {
+    /// Returns true if all entries were processed without errors
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    fn record(&mut self, result: VfsResult<VfsFileType>) {
+        match result {
+            Ok(VfsFileType::File) => self.files += 1,
+            Ok(VfsFileType::Directory) => self.directories += 1,
+            Err(err) => self.errors.push(err),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct AsyncVFS {
     fs: Box<dyn AsyncFileSystem>,
@@ -52,8 +87,8 @@ impl AsyncVfsPath {
     /// Creates a root path for the given filesystem
     ///
     /// ```
-    /// # use vfs::async_vfs::{AsyncPhysicalFS, AsyncVfsPath};
-    /// let path = AsyncVfsPath::new(AsyncPhysicalFS::new("."));
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// ````
     pub fn new<T: AsyncFileSystem>(filesystem: T) -> Self {
         AsyncVfsPath {
@@ -67,9 +102,9 @@ impl AsyncVfsPath {
     /// Returns the string representation of this path
     ///
     /// ```
-    /// # use vfs::async_vfs::{AsyncPhysicalFS, AsyncVfsPath};
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
-    /// let path = AsyncVfsPath::new(AsyncPhysicalFS::new("."));
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     ///
     /// assert_eq!(path.as_str(), "");
     /// assert_eq!(path.join("foo.txt")?.as_str(), "/foo.txt");
@@ -82,9 +117,9 @@ impl AsyncVfsPath {
     /// Appends a path segment to this path, returning the result
     ///
     /// ```
-    /// # use vfs::async_vfs::{AsyncPhysicalFS, AsyncVfsPath};
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
-    /// let path = AsyncVfsPath::new(AsyncPhysicalFS::new("."));
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     ///
     /// assert_eq!(path.join("foo.txt")?.as_str(), "/foo.txt");
     /// assert_eq!(path.join("foo/bar.txt")?.as_str(), "/foo/bar.txt");
@@ -259,12 +294,12 @@ impl AsyncVfsPath {
     /// ```
     /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
-    /// use async_std::io:: {ReadExt, WriteExt};
+    /// use futures::io::{AsyncReadExt, AsyncWriteExt};
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
     ///
-    /// write!(file.create_file().await?, "Hello, world!").await?;
+    /// file.create_file().await?.write_all(b"Hello, world!").await?;
     ///
     /// let mut result = String::new();
     /// file.open_file().await?.read_to_string(&mut result).await?;
@@ -272,7 +307,7 @@ impl AsyncVfsPath {
     /// # Ok::<(), VfsError>(())
     /// # });
     /// ```
-    pub async fn create_file(&self) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    pub async fn create_file(&self) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         self.get_parent("create file").await?;
         self.fs.fs.create_file(&self.path).await.map_err(|err| {
             err.with_path(&self.path)
@@ -285,11 +320,11 @@ impl AsyncVfsPath {
     /// ```
     /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
-    /// use async_std::io:: {ReadExt, WriteExt};
+    /// use futures::io::{AsyncReadExt, AsyncWriteExt};
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
-    /// write!(file.create_file().await?, "Hello, world!").await?;
+    /// file.create_file().await?.write_all(b"Hello, world!").await?;
     /// let mut result = String::new();
     ///
     /// file.open_file().await?.read_to_string(&mut result).await?;
@@ -329,19 +364,19 @@ impl AsyncVfsPath {
     /// ```
     /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
-    /// use async_std::io:: {ReadExt, WriteExt};
+    /// use futures::io::{AsyncReadExt, AsyncWriteExt};
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
-    /// write!(file.create_file().await?, "Hello, ").await?;
-    /// write!(file.append_file().await?, "world!").await?;
+    /// file.create_file().await?.write_all(b"Hello, ").await?;
+    /// file.append_file().await?.write_all(b"world!").await?;
     /// let mut result = String::new();
     /// file.open_file().await?.read_to_string(&mut result).await?;
     /// assert_eq!(&result, "Hello, world!");
     /// # Ok::<(), VfsError>(())
     /// # });
     /// ```
-    pub async fn append_file(&self) -> VfsResult<Box<dyn Write + Send + Unpin>> {
+    pub async fn append_file(&self) -> VfsResult<Box<dyn SeekAndWrite + Send + Unpin>> {
         self.fs.fs.append_file(&self.path).await.map_err(|err| {
             err.with_path(&self.path)
                 .with_context(|| "Could not open file for appending")
@@ -351,13 +386,13 @@ impl AsyncVfsPath {
     /// Removes the file at this path
     ///
     /// ```
-    /// use async_std::io:: {ReadExt, WriteExt};
+    /// use futures::io::{AsyncReadExt, AsyncWriteExt};
     /// # use vfs::async_vfs::{AsyncMemoryFS , AsyncVfsPath};
     /// # use vfs::VfsError;
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
-    /// write!(file.create_file().await?, "Hello, ").await?;
+    /// file.create_file().await?.write_all(b"Hello, ").await?;
     /// assert!(file.exists().await?);
     ///
     /// file.remove_file().await?;
@@ -435,12 +470,122 @@ impl AsyncVfsPath {
         Ok(())
     }
 
+    /// Ensures that the directory at this path is removed like [`remove_dir_all()`](AsyncVfsPath::remove_dir_all),
+    /// removing up to `max_in_flight` entries concurrently
+    ///
+    /// Files are removed first, then the directories from the deepest level upwards.
+    /// Entries that cannot be removed do not stop the operation, their errors are collected in the returned stats.
+    /// An error is only returned if the directory cannot be listed at all.
+    ///
+    /// ```
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
+    /// # use vfs::VfsError;
+    /// # tokio_test::block_on(async {
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
+    /// let directory = path.join("foo")?;
+    /// directory.join("bar")?.create_dir_all().await?;
+    /// directory.join("baz.txt")?.create_file().await?;
+    ///
+    /// let stats = directory.remove_dir_all_concurrent(8).await?;
+    ///
+    /// assert!(stats.is_success());
+    /// assert_eq!((stats.files, stats.directories), (1, 2));
+    /// assert!(!directory.exists().await?);
+    /// # Ok::<(), VfsError>(())
+    /// # });
+    /// ```
+    pub async fn remove_dir_all_concurrent(&self, max_in_flight: usize) -> VfsResult<DirStats> {
+        let mut stats = DirStats::default();
+        if !self.exists().await? {
+            return Ok(stats);
+        }
+        let mut files = vec![];
+        let mut levels: Vec<Vec<AsyncVfsPath>> = vec![vec![self.clone()]];
+        // Directories are walked before their entries, so their depth is known when reaching the entries
+        let mut depths = HashMap::from([(self.path.clone(), 0)]);
+        let mut path_stream = self.walk_dir().await?;
+        while let Some(entry) = path_stream.next().await {
+            let path = match entry {
+                Ok(path) => path,
+                Err(err) => {
+                    stats.errors.push(err);
+                    continue;
+                }
+            };
+            match path.metadata().await {
+                Ok(metadata) if metadata.file_type == VfsFileType::File => files.push(path),
+                Ok(_) => {
+                    // Directories below one that could not be inspected are removed before all others
+                    let depth = depths
+                        .get(&path.parent().path)
+                        .map_or(levels.len(), |depth| depth + 1);
+                    depths.insert(path.path.clone(), depth);
+                    if levels.len() <= depth {
+                        levels.resize(depth + 1, vec![]);
+                    }
+                    levels[depth].push(path);
+                }
+                Err(err) => stats.errors.push(err),
+            }
+        }
+        let max_in_flight = max_in_flight.max(1);
+        let mut removals = stream::iter(files)
+            .map(|file| async move { file.remove_file().await.map(|_| VfsFileType::File) })
+            .buffer_unordered(max_in_flight);
+        while let Some(result) = removals.next().await {
+            stats.record(result);
+        }
+        // Directories of the same level are independent of each other
+        for level in levels.into_iter().rev() {
+            let mut removals = stream::iter(level)
+                .map(|dir| async move { dir.remove_dir().await.map(|_| VfsFileType::Directory) })
+                .buffer_unordered(max_in_flight);
+            while let Some(result) = removals.next().await {
+                stats.record(result);
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Watches this file or directory for changes, if the filesystem supports it
+    ///
+    /// Reports changes to this path and its direct children, or all its descendants if `recursive` is set.
+    /// The event paths are absolute within the filesystem, like the result of [`as_str()`](AsyncVfsPath::as_str).
+    ///
+    /// ```
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
+    /// use futures::stream::StreamExt;
+    /// use vfs::{VfsError, WatchEvent};
+    /// # tokio_test::block_on(async {
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
+    /// let mut events = path.watch(true).await?;
+    ///
+    /// path.join("foo")?.create_dir().await?;
+    ///
+    /// assert_eq!(events.next().await, Some(WatchEvent::Created("/foo".into())));
+    /// # Ok::<(), VfsError>(())
+    /// # });
+    /// ```
+    pub async fn watch(
+        &self,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Stream<Item = WatchEvent> + Send + Unpin>> {
+        self.fs
+            .fs
+            .watch(&self.path, recursive)
+            .await
+            .map_err(|err| {
+                err.with_path(&self.path)
+                    .with_context(|| "Could not watch path")
+            })
+    }
+
     /// Returns the file metadata for the file at this path
     ///
     /// ```
     /// use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// use vfs::{VfsError, VfsFileType, VfsMetadata};
-    /// use async_std::io::WriteExt;
+    /// use futures::io::AsyncWriteExt;
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let directory = path.join("foo")?;
@@ -450,7 +595,7 @@ impl AsyncVfsPath {
     /// assert_eq!(directory.metadata().await?.file_type, VfsFileType::Directory);
     ///
     /// let file = path.join("bar.txt")?;
-    /// write!(file.create_file().await?, "Hello, world!").await?;
+    /// file.create_file().await?.write_all(b"Hello, world!").await?;
     ///
     /// assert_eq!(file.metadata().await?.len, 13);
     /// assert_eq!(file.metadata().await?.file_type, VfsFileType::File);
@@ -468,7 +613,7 @@ impl AsyncVfsPath {
     /// ```
     /// use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// use vfs::{VfsError, VfsFileType, VfsMetadata, VfsPath};
-    /// use async_std::io::WriteExt;
+    /// use futures::io::AsyncWriteExt;
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
@@ -498,7 +643,7 @@ impl AsyncVfsPath {
     /// ```
     /// use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// use vfs::{VfsError, VfsFileType, VfsMetadata, VfsPath};
-    /// use async_std::io::WriteExt;
+    /// use futures::io::AsyncWriteExt;
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
@@ -528,7 +673,7 @@ impl AsyncVfsPath {
     /// ```
     /// use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// use vfs::{VfsError, VfsFileType, VfsMetadata, VfsPath};
-    /// use async_std::io::WriteExt;
+    /// use futures::io::AsyncWriteExt;
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
@@ -722,11 +867,11 @@ impl AsyncVfsPath {
     /// ```
     /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
-    /// use async_std::io::{ReadExt, WriteExt};
+    /// use futures::io::{AsyncReadExt, AsyncWriteExt};
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let file = path.join("foo.txt")?;
-    /// write!(file.create_file().await?, "Hello, world!").await?;
+    /// file.create_file().await?.write_all(b"Hello, world!").await?;
     ///
     /// let result = file.read_to_string().await?;
     ///
@@ -761,13 +906,13 @@ impl AsyncVfsPath {
     /// The destination must not exist, but its parent directory must
     ///
     /// ```
-    /// use async_std::io::{ReadExt, WriteExt};
+    /// use futures::io::{AsyncReadExt, AsyncWriteExt};
     /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let src = path.join("foo.txt")?;
-    /// write!(src.create_file().await?, "Hello, world!").await?;
+    /// src.create_file().await?.write_all(b"Hello, world!").await?;
     /// let dest = path.join("bar.txt")?;
     ///
     /// src.copy_file(&dest).await?;
@@ -788,7 +933,7 @@ impl AsyncVfsPath {
                 let result = self.fs.fs.copy_file(&self.path, &destination.path).await;
                 match result {
                     Err(err) => match err.kind() {
-                        VfsErrorKind::NotSupported => {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
                             // continue
                         }
                         _ => return Err(err),
@@ -798,7 +943,7 @@ impl AsyncVfsPath {
             }
             let mut src = self.open_file().await?;
             let mut dest = destination.create_file().await?;
-            async_std::io::copy(&mut src, &mut dest)
+            futures::io::copy(&mut src, &mut dest)
                 .await
                 .map_err(|source| {
                     VfsError::from(source)
@@ -827,11 +972,11 @@ impl AsyncVfsPath {
     /// ```
     /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
     /// # use vfs::VfsError;
-    /// use async_std::io::{ReadExt, WriteExt};
+    /// use futures::io::{AsyncReadExt, AsyncWriteExt};
     /// # tokio_test::block_on(async {
     /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
     /// let src = path.join("foo.txt")?;
-    /// write!(src.create_file().await?, "Hello, world!").await?;
+    /// src.create_file().await?.write_all(b"Hello, world!").await?;
     /// let dest = path.join("bar.txt")?;
     ///
     /// src.move_file(&dest).await?;
@@ -853,7 +998,7 @@ impl AsyncVfsPath {
                 let result = self.fs.fs.move_file(&self.path, &destination.path);
                 match result.await {
                     Err(err) => match err.kind() {
-                        VfsErrorKind::NotSupported => {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
                             // continue
                         }
                         _ => return Err(err),
@@ -863,7 +1008,7 @@ impl AsyncVfsPath {
             }
             let mut src = self.open_file().await?;
             let mut dest = destination.create_file().await?;
-            async_std::io::copy(&mut src, &mut dest)
+            futures::io::copy(&mut src, &mut dest)
                 .await
                 .map_err(|source| {
                     VfsError::from(source)
@@ -944,6 +1089,103 @@ impl AsyncVfsPath {
         Ok(files_copied)
     }
 
+    /// Copies a directory to a new destination like [`copy_dir()`](AsyncVfsPath::copy_dir),
+    /// copying up to `max_in_flight` files concurrently
+    ///
+    /// Directories are created in order while walking the source, so each is created before
+    /// the files in it are copied. Entries that cannot be copied do not stop the operation,
+    /// their errors are collected in the returned stats.
+    ///
+    /// The destination must not exist, but the parent directory must. An error is only returned
+    /// if the destination exists already or cannot be created, or if the source cannot be listed at all.
+    ///
+    /// ```
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
+    /// # use vfs::VfsError;
+    /// use futures::io::AsyncWriteExt;
+    /// # tokio_test::block_on(async {
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
+    /// let src = path.join("foo")?;
+    /// src.join("dir")?.create_dir_all().await?;
+    /// src.join("dir/a.txt")?.create_file().await?.write_all(b"a").await?;
+    /// src.join("b.txt")?.create_file().await?.write_all(b"b").await?;
+    /// let dest = path.join("bar")?;
+    ///
+    /// let stats = src.copy_dir_concurrent(&dest, 8).await?;
+    ///
+    /// assert!(stats.is_success());
+    /// assert_eq!((stats.files, stats.directories), (2, 2));
+    /// assert_eq!(dest.join("dir/a.txt")?.read_to_string().await?, "a");
+    /// # Ok::<(), VfsError>(())
+    /// # });
+    /// ```
+    pub async fn copy_dir_concurrent(
+        &self,
+        destination: &AsyncVfsPath,
+        max_in_flight: usize,
+    ) -> VfsResult<DirStats> {
+        let context = || {
+            format!(
+                "Could not copy directory '{}' to '{}'",
+                self.as_str(),
+                destination.as_str()
+            )
+        };
+        async {
+            if destination.exists().await? {
+                return Err(VfsErrorKind::Other("Destination exists already".into()).into());
+            }
+            destination.create_dir().await
+        }
+        .await
+        .map_err(|err: VfsError| err.with_path(&destination.path).with_context(context))?;
+        let path_stream = self
+            .walk_dir()
+            .await
+            .map_err(|err| err.with_path(&self.path).with_context(context))?;
+        let mut stats = DirStats {
+            directories: 1,
+            ..Default::default()
+        };
+        let prefix_len = self.path.len();
+        let mut copies = path_stream
+            .then(|entry| {
+                let destination = destination.clone();
+                async move { Self::copy_entry(entry, &destination, prefix_len).await }.boxed()
+            })
+            .map(|entry| match entry {
+                Ok(Some((src_path, dest_path))) => async move {
+                    src_path.copy_file(&dest_path).await?;
+                    Ok(VfsFileType::File)
+                }
+                .boxed(),
+                Ok(None) => future::ready(Ok(VfsFileType::Directory)).boxed(),
+                Err(err) => future::ready(Err(err)).boxed(),
+            })
+            .buffer_unordered(max_in_flight.max(1));
+        while let Some(result) = copies.next().await {
+            stats.record(result);
+        }
+        Ok(stats)
+    }
+
+    /// Creates the destination of a directory entry right away, returning the source and destination of files
+    async fn copy_entry(
+        entry: VfsResult<AsyncVfsPath>,
+        destination: &AsyncVfsPath,
+        prefix_len: usize,
+    ) -> VfsResult<Option<(AsyncVfsPath, AsyncVfsPath)>> {
+        let src_path = entry?;
+        let dest_path = destination.join(&src_path.as_str()[prefix_len + 1..])?;
+        match src_path.metadata().await?.file_type {
+            VfsFileType::Directory => {
+                dest_path.create_dir().await?;
+                Ok(None)
+            }
+            VfsFileType::File => Ok(Some((src_path, dest_path))),
+        }
+    }
+
     /// Moves a directory to a new destination, including subdirectories and files
     ///
     /// The destination must not exist, but its parent directory must
@@ -976,7 +1218,7 @@ impl AsyncVfsPath {
                 let result = self.fs.fs.move_dir(&self.path, &destination.path).await;
                 match result {
                     Err(err) => match err.kind() {
-                        VfsErrorKind::NotSupported => {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
                             // continue
                         }
                         _ => return Err(err),
@@ -1011,6 +1253,268 @@ impl AsyncVfsPath {
         })?;
         Ok(())
     }
+
+    /// Copies a directory to a new destination like [`copy_dir()`](AsyncVfsPath::copy_dir),
+    /// reporting progress and checking for cancellation
+    ///
+    /// The files are copied in chunks, progress is reported and cancellation checked after each chunk.
+    /// A cancelled copy fails with [`VfsErrorKind::Cancelled`], holding the progress made so far,
+    /// and leaves the destination partially copied.
+    ///
+    /// Returns the number of files copied
+    ///
+    /// ```
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
+    /// use vfs::{CancellationToken, DirOptions, VfsError};
+    /// use vfs::error::VfsErrorKind;
+    /// use futures::io::AsyncWriteExt;
+    /// # tokio_test::block_on(async {
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
+    /// let src = path.join("foo")?;
+    /// src.join("dir")?.create_dir_all().await?;
+    /// src.join("dir/a.txt")?.create_file().await?.write_all(b"a").await?;
+    ///
+    /// let token = CancellationToken::new();
+    /// let options = DirOptions::new().with_cancellation(token.clone());
+    /// assert_eq!(src.copy_dir_with(&path.join("bar")?, &options).await?, 2);
+    ///
+    /// token.cancel();
+    /// let error = src.copy_dir_with(&path.join("baz")?, &options).await.unwrap_err();
+    /// assert!(matches!(error.kind(), VfsErrorKind::Cancelled(progress) if progress.files_total == 1));
+    /// # Ok::<(), VfsError>(())
+    /// # });
+    /// ```
+    pub async fn copy_dir_with(
+        &self,
+        destination: &AsyncVfsPath,
+        options: &DirOptions,
+    ) -> VfsResult<u64> {
+        async {
+            if destination.exists().await? {
+                return Err(VfsError::from(VfsErrorKind::Other(
+                    "Destination exists already".into(),
+                ))
+                .with_path(&destination.path));
+            }
+            self.copy_entries_with(destination, options).await
+        }
+        .await
+        .map_err(|err| {
+            err.with_path(&self.path).with_context(|| {
+                format!(
+                    "Could not copy directory '{}' to '{}'",
+                    self.as_str(),
+                    destination.as_str()
+                )
+            })
+        })
+    }
+
+    /// Moves a directory to a new destination like [`move_dir()`](AsyncVfsPath::move_dir),
+    /// reporting progress and checking for cancellation
+    ///
+    /// If the filesystem can move the directory itself, this happens in a single step without
+    /// progress reports. Otherwise the directory is copied like in [`copy_dir_with()`](AsyncVfsPath::copy_dir_with)
+    /// and the source is removed once everything was copied. A cancelled move leaves the source untouched
+    /// and the destination partially copied.
+    ///
+    /// ```
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
+    /// use vfs::{DirOptions, VfsError};
+    /// # tokio_test::block_on(async {
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
+    /// let src = path.join("foo")?;
+    /// src.join("dir")?.create_dir_all().await?;
+    /// let dest = path.join("bar")?;
+    ///
+    /// src.move_dir_with(&dest, &DirOptions::new()).await?;
+    ///
+    /// assert!(dest.join("dir")?.exists().await?);
+    /// assert!(!src.exists().await?);
+    /// # Ok::<(), VfsError>(())
+    /// # });
+    /// ```
+    pub async fn move_dir_with(
+        &self,
+        destination: &AsyncVfsPath,
+        options: &DirOptions,
+    ) -> VfsResult<()> {
+        async {
+            if destination.exists().await? {
+                return Err(VfsError::from(VfsErrorKind::Other(
+                    "Destination exists already".into(),
+                ))
+                .with_path(&destination.path));
+            }
+            options.check_cancelled(&Progress::default())?;
+            if Arc::ptr_eq(&self.fs, &destination.fs) {
+                let result = self.fs.fs.move_dir(&self.path, &destination.path).await;
+                match result {
+                    Err(err) => match err.kind() {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
+                            // continue
+                        }
+                        _ => return Err(err),
+                    },
+                    other => return other,
+                }
+            }
+            self.copy_entries_with(destination, options).await?;
+            self.remove_dir_all().await?;
+            Ok(())
+        }
+        .await
+        .map_err(|err| {
+            err.with_path(&self.path).with_context(|| {
+                format!(
+                    "Could not move directory '{}' to '{}'",
+                    self.as_str(),
+                    destination.as_str()
+                )
+            })
+        })
+    }
+
+    /// Ensures that the directory at this path is removed like [`remove_dir_all()`](AsyncVfsPath::remove_dir_all),
+    /// reporting progress and checking for cancellation
+    ///
+    /// The files are removed first, progress is reported and cancellation checked after each file.
+    /// A cancelled removal fails with [`VfsErrorKind::Cancelled`], holding the progress made so far,
+    /// and leaves the remaining files and all directories in place.
+    ///
+    /// ```
+    /// # use vfs::async_vfs::{AsyncMemoryFS, AsyncVfsPath};
+    /// use vfs::{DirOptions, VfsError};
+    /// # tokio_test::block_on(async {
+    /// let path = AsyncVfsPath::new(AsyncMemoryFS::new());
+    /// let directory = path.join("foo")?;
+    /// directory.join("bar")?.create_dir_all().await?;
+    /// directory.join("bar/baz.txt")?.create_file().await?;
+    ///
+    /// let options = DirOptions::new().on_progress(|progress| assert_eq!(progress.files_total, 1));
+    /// directory.remove_dir_all_with(&options).await?;
+    ///
+    /// assert!(!directory.exists().await?);
+    /// # Ok::<(), VfsError>(())
+    /// # });
+    /// ```
+    pub async fn remove_dir_all_with(&self, options: &DirOptions) -> VfsResult<()> {
+        if !self.exists().await? {
+            return Ok(());
+        }
+        async {
+            let mut progress = Progress::default();
+            let entries = self.scan_dir(&mut progress).await?;
+            options.report(&progress);
+            let mut directories = vec![];
+            for (path, metadata) in entries {
+                match metadata.file_type {
+                    VfsFileType::File => {
+                        options.check_cancelled(&progress)?;
+                        path.remove_file().await?;
+                        progress.files_done += 1;
+                        progress.bytes_done += metadata.len;
+                        options.report(&progress);
+                    }
+                    VfsFileType::Directory => directories.push(path),
+                }
+            }
+            // Directories are walked before their children, so the children are removed first here
+            for directory in directories.into_iter().rev() {
+                directory.remove_dir().await?;
+            }
+            self.remove_dir().await
+        }
+        .await
+        .map_err(|err| {
+            err.with_path(&self.path)
+                .with_context(|| format!("Could not remove directory '{}'", self.as_str()))
+        })
+    }
+
+    /// Collects all entries below this directory, adding the files to the totals of the progress
+    async fn scan_dir(
+        &self,
+        progress: &mut Progress,
+    ) -> VfsResult<Vec<(AsyncVfsPath, VfsMetadata)>> {
+        let mut entries = vec![];
+        let mut path_stream = self.walk_dir().await?;
+        while let Some(path) = path_stream.next().await {
+            let path = path?;
+            let metadata = path.metadata().await?;
+            if metadata.file_type == VfsFileType::File {
+                progress.files_total += 1;
+                progress.bytes_total += metadata.len;
+            }
+            entries.push((path, metadata));
+        }
+        Ok(entries)
+    }
+
+    /// Copies the contents of this directory to the not yet existing destination, reporting progress
+    async fn copy_entries_with(
+        &self,
+        destination: &AsyncVfsPath,
+        options: &DirOptions,
+    ) -> VfsResult<u64> {
+        let mut progress = Progress::default();
+        let entries = self.scan_dir(&mut progress).await?;
+        options.report(&progress);
+        destination.create_dir().await?;
+        let prefix_len = self.path.len();
+        let mut files_copied = 0u64;
+        for (src_path, metadata) in entries {
+            options.check_cancelled(&progress)?;
+            let dest_path = destination.join(&src_path.as_str()[prefix_len + 1..])?;
+            match metadata.file_type {
+                VfsFileType::Directory => dest_path.create_dir().await?,
+                VfsFileType::File => {
+                    src_path
+                        .copy_file_chunked(&dest_path, options, &mut progress)
+                        .await?;
+                    progress.files_done += 1;
+                    options.report(&progress);
+                }
+            }
+            files_copied += 1;
+        }
+        Ok(files_copied)
+    }
+
+    /// Copies a file chunk by chunk, reporting progress and checking for cancellation between chunks
+    async fn copy_file_chunked(
+        &self,
+        destination: &AsyncVfsPath,
+        options: &DirOptions,
+        progress: &mut Progress,
+    ) -> VfsResult<()> {
+        let mut src = self.open_file().await?;
+        let mut dest = destination.create_file().await?;
+        let mut buffer = vec![0; CHUNK_SIZE];
+        loop {
+            let read = src.read(&mut buffer).await.map_err(|source| {
+                VfsError::from(source)
+                    .with_path(&self.path)
+                    .with_context(|| "Could not read path")
+            })?;
+            if read == 0 {
+                break;
+            }
+            options.check_cancelled(progress)?;
+            dest.write_all(&buffer[..read]).await.map_err(|source| {
+                VfsError::from(source)
+                    .with_path(&destination.path)
+                    .with_context(|| "Could not write path")
+            })?;
+            progress.bytes_done += read as u64;
+            options.report(progress);
+        }
+        dest.flush().await.map_err(|source| {
+            VfsError::from(source)
+                .with_path(&destination.path)
+                .with_context(|| "Could not write path")
+        })
+    }
 }
 
 /// An iterator for recursively walking a file hierarchy
@@ -11,7 +11,7 @@ macro_rules! test_async_vfs {
             use $crate::VfsResult;
             use $crate::error::VfsErrorKind;
             use futures::stream::StreamExt;
-            use async_std::io::{WriteExt, ReadExt};
+            use futures::io::{AsyncWriteExt, AsyncReadExt, AsyncSeekExt, SeekFrom};
             use std::time::SystemTime;
 
             fn create_root() -> AsyncVfsPath {
@@ -102,8 +102,8 @@ macro_rules! test_async_vfs {
                 let _send = &path as &dyn Send;
                 {
                     let mut file = path.create_file().await.unwrap();
-                    write!(file, "Hello world").await.unwrap();
-                    write!(file, "!").await.unwrap();
+                    file.write_all(b"Hello world").await.unwrap();
+                    file.write_all(b"!").await.unwrap();
                 }
                 {
                     let mut file = path.open_file().await.unwrap();
@@ -119,6 +119,32 @@ macro_rules! test_async_vfs {
                 Ok(())
             }
 
+            #[tokio::test]
+            async fn write_and_seek_and_read_file()  -> VfsResult<()>{
+                let root = create_root();
+                let path = root.join("foobar.txt").unwrap();
+                let _send = &path as &dyn Send;
+                {
+                    let mut file = path.create_file().await.unwrap();
+                    file.write_all(b"Hello world").await.unwrap();
+                    file.write_all(b"!").await.unwrap();
+                    file.seek(SeekFrom::Start(5)).await.unwrap();
+                    file.write_all(b"SeekCompleted").await.unwrap();
+                }
+                {
+                    let mut file = path.open_file().await.unwrap();
+                    let mut string: String = String::new();
+                    file.read_to_string(&mut string).await.unwrap();
+                    assert_eq!(string, "HelloSeekCompleted");
+                }
+                assert!(path.exists().await?);
+                assert!(!root.join("foo").unwrap().exists().await?);
+                let metadata = path.metadata().await.unwrap();
+                assert_eq!(metadata.len, 18);
+                assert_eq!(metadata.file_type, VfsFileType::File);
+                Ok(())
+            }
+
             #[tokio::test]
             async fn append_file() {
                 let root = create_root();
@@ -317,6 +343,32 @@ macro_rules! test_async_vfs {
                 Ok(())
             }
 
+            #[tokio::test]
+            async fn remove_dir_all_concurrent() -> VfsResult<()> {
+                let root = create_root();
+                let path = root.join("foo")?;
+                path.join("bar/baz/fizz")?.create_dir_all().await?;
+                path.join("bar/buzz")?.create_file().await?;
+                path.join("bar/baz/fizz/a.txt")?.create_file().await?;
+                path.join("b.txt")?.create_file().await?;
+                let stats = path.remove_dir_all_concurrent(2).await?;
+                assert!(stats.is_success(), "{:?}", stats.errors);
+                assert_eq!(stats.files, 3);
+                assert_eq!(stats.directories, 4);
+                assert!(!path.exists().await?);
+                assert!(root.exists().await?);
+                Ok(())
+            }
+
+            #[tokio::test]
+            async fn remove_dir_all_concurrent_nonexisting() -> VfsResult<()> {
+                let root = create_root();
+                let stats = root.join("baz")?.remove_dir_all_concurrent(2).await?;
+                assert!(stats.is_success());
+                assert_eq!((stats.files, stats.directories), (0, 0));
+                Ok(())
+            }
+
             #[test]
             fn filename() {
                 let root = create_root();
@@ -911,6 +963,49 @@ macro_rules! test_async_vfs {
                Ok(())
             }
 
+            #[tokio::test]
+            async fn copy_dir_concurrent() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.join("bar/biz/fizz/buzz")?.create_dir_all().await?;
+                src.join("bar/baz.txt")?.create_file().await?.write_all(b"Hello World").await?;
+                src.join("bar/biz/a.txt")?.create_file().await?.write_all(b"a").await?;
+                src.join("b.txt")?.create_file().await?.write_all(b"b").await?;
+
+                let dest = root.join("foo2")?;
+                let stats = src.copy_dir_concurrent(&dest, 2).await?;
+                assert!(stats.is_success(), "{:?}", stats.errors);
+                assert_eq!(stats.files, 3);
+                assert_eq!(stats.directories, 5);
+                assert_eq!(&dest.join("bar/baz.txt")?.read_to_string().await?, "Hello World");
+                assert_eq!(&dest.join("bar/biz/a.txt")?.read_to_string().await?, "a");
+                assert_eq!(&dest.join("b.txt")?.read_to_string().await?, "b");
+                assert!(&dest.join("bar/biz/fizz/buzz")?.exists().await?, "directory should exist");
+                Ok(())
+            }
+
+            #[tokio::test]
+            async fn copy_dir_concurrent_to_existing() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.create_dir_all().await?;
+                let dest = root.join("foo2")?;
+                dest.create_dir_all().await?;
+
+                let error = src
+                    .copy_dir_concurrent(&dest, 2)
+                    .await
+                    .expect_err("copy_dir_concurrent");
+                assert_eq!(error.path(), "/foo2");
+                let error_message = error.to_string();
+                assert!(
+                    error_message.starts_with("Could not copy directory '/foo' to '/foo2'"),
+                    "Actual message: {}",
+                    error_message
+                );
+                Ok(())
+            }
+
             #[tokio::test]
             async fn move_dir() -> VfsResult<()> {
                 let root = create_root();
@@ -957,6 +1052,112 @@ macro_rules! test_async_vfs {
                Ok(())
             }
 
+            #[tokio::test]
+            async fn copy_dir_with_progress() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.join("bar/biz")?.create_dir_all().await?;
+                src.join("bar/baz.txt")?.create_file().await?.write_all(b"Hello World").await?;
+                src.join("bar/biz/a.txt")?.create_file().await?.write_all(b"a").await?;
+
+                let reports = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+                let recorded = reports.clone();
+                let options = $crate::DirOptions::new()
+                    .on_progress(move |progress| recorded.lock().unwrap().push(*progress));
+                let dest = root.join("foo2")?;
+                assert_eq!(src.copy_dir_with(&dest, &options).await?, 4);
+                assert_eq!(&dest.join("bar/baz.txt")?.read_to_string().await?, "Hello World");
+                assert_eq!(&dest.join("bar/biz/a.txt")?.read_to_string().await?, "a");
+                let reports = reports.lock().unwrap();
+                assert_eq!(
+                    reports.last(),
+                    Some(&$crate::Progress {
+                        files_done: 2,
+                        files_total: 2,
+                        bytes_done: 12,
+                        bytes_total: 12,
+                    })
+                );
+                Ok(())
+            }
+
+            #[tokio::test]
+            async fn copy_dir_with_cancelled() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.join("bar")?.create_dir_all().await?;
+                src.join("bar/baz.txt")?.create_file().await?.write_all(b"Hello World").await?;
+                src.join("a.txt")?.create_file().await?.write_all(b"a").await?;
+
+                let token = $crate::CancellationToken::new();
+                let cancel = token.clone();
+                let options = $crate::DirOptions::new()
+                    .with_cancellation(token)
+                    .on_progress(move |progress| {
+                        if progress.files_done == 1 {
+                            cancel.cancel();
+                        }
+                    });
+                let error = src
+                    .copy_dir_with(&root.join("foo2")?, &options)
+                    .await
+                    .expect_err("copy_dir_with");
+                match error.kind() {
+                    VfsErrorKind::Cancelled(progress) => {
+                        assert_eq!((progress.files_done, progress.files_total), (1, 2));
+                    }
+                    other => panic!("Unexpected error: {}", other),
+                }
+                assert!(
+                    error.to_string().starts_with("Could not copy directory '/foo' to '/foo2'"),
+                    "Actual message: {}",
+                    error
+                );
+                assert!(src.join("bar/baz.txt")?.exists().await?, "source should be untouched");
+                Ok(())
+            }
+
+            #[tokio::test]
+            async fn move_dir_with() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.join("bar/biz")?.create_dir_all().await?;
+                src.join("bar/baz.txt")?.create_file().await?.write_all(b"Hello World").await?;
+
+                let dest = root.join("foo2")?;
+                src.move_dir_with(&dest, &$crate::DirOptions::new()).await?;
+                assert_eq!(&dest.join("bar/baz.txt")?.read_to_string().await?, "Hello World");
+                assert!(&dest.join("bar/biz")?.exists().await?, "directory should exist");
+                assert!(!src.exists().await?, "source directory should not exist");
+                Ok(())
+            }
+
+            #[tokio::test]
+            async fn remove_dir_all_with_cancelled() -> VfsResult<()> {
+                let root = create_root();
+                let path = root.join("foo")?;
+                path.join("bar")?.create_dir_all().await?;
+                path.join("bar/a.txt")?.create_file().await?.write_all(b"a").await?;
+                path.join("b.txt")?.create_file().await?.write_all(b"bb").await?;
+
+                let token = $crate::CancellationToken::new();
+                let cancel = token.clone();
+                let options = $crate::DirOptions::new()
+                    .with_cancellation(token.clone())
+                    .on_progress(move |progress| {
+                        if progress.files_done == 1 {
+                            cancel.cancel();
+                        }
+                    });
+                let error = path.remove_dir_all_with(&options).await.expect_err("remove_dir_all_with");
+                assert!(matches!(error.kind(), VfsErrorKind::Cancelled(progress) if progress.files_done == 1));
+                assert!(path.exists().await?);
+
+                path.remove_dir_all_with(&$crate::DirOptions::new()).await?;
+                assert!(!path.exists().await?);
+                Ok(())
+            }
+
             #[tokio::test]
             async fn is_file_is_dir() -> VfsResult<()> {
                 let root = create_root();
@@ -997,6 +1198,7 @@ macro_rules! test_async_vfs_readonly {
         #[cfg(test)]
         mod vfs_tests_readonly {
             use super::*;
+            use futures::io::AsyncReadExt;
             use futures::stream::StreamExt;
             use $crate::async_vfs::AsyncVfsPath;
             use $crate::{VfsFileType, VfsResult};
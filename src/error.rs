@@ -1,5 +1,6 @@
 //! Error and Result definitions
 
+use crate::progress::Progress;
 use std::{error, fmt, io};
 
 /// The error type of this crate
@@ -29,6 +30,11 @@ impl From<VfsErrorKind> for VfsError {
                 // TODO: If MSRV changes to 1.53, enable this. Alternatively,
                 //      if it's possible to #[cfg] just this line, try that
                 // io::ErrorKind::Unsupported => VfsErrorKind::NotSupported,
+                // Errors of file handles wrapping a VfsError, e.g. integrity violations
+                _ if io.get_ref().map_or(false, |inner| inner.is::<VfsError>()) => {
+                    let inner = io.into_inner().expect("Inner error checked above");
+                    return *inner.downcast::<VfsError>().expect("Type checked above");
+                }
                 _ => VfsErrorKind::IoError(io),
             },
             // Remaining kinda are passed through as-is
@@ -99,14 +105,17 @@ impl error::Error for VfsError {
 }
 
 /// The kinds of errors that can occur
+///
+/// New kinds may be added in minor releases, so matches must include a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum VfsErrorKind {
     /// A generic I/O error
     ///
     /// Certain standard I/O errors are normalized to their VfsErrorKind counterparts
     IoError(io::Error),
 
-    #[cfg(feature = "async-vfs")]
+    #[cfg(feature = "async-vfs-core")]
     /// A generic async I/O error
     AsyncIoError(io::Error),
 
@@ -127,6 +136,57 @@ pub enum VfsErrorKind {
 
     /// Functionality not supported by this filesystem
     NotSupported,
+
+    /// The source and destination of an operation are located on different mounts
+    ///
+    /// Operations like moving files fall back to copying in this case
+    CrossMount,
+
+    /// The path would lead outside of the filesystem root, e.g. via '..' or a symbolic link
+    EscapesRoot,
+
+    /// The filesystem does not allow modifications
+    ReadOnly,
+
+    /// The path matches several entries that only differ by case
+    AmbiguousPath,
+
+    /// The stored data failed an integrity check, e.g. because it was tampered with
+    IntegrityViolation,
+
+    /// The path is hidden by a filter and can not be written
+    Excluded,
+
+    /// The operation was cancelled, holding the progress made until then
+    Cancelled(Progress),
+}
+
+impl VfsErrorKind {
+    /// Creates a copy of this error kind, I/O errors only keep their kind and message
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            VfsErrorKind::IoError(cause) => {
+                VfsErrorKind::IoError(io::Error::new(cause.kind(), cause.to_string()))
+            }
+            #[cfg(feature = "async-vfs-core")]
+            VfsErrorKind::AsyncIoError(cause) => {
+                VfsErrorKind::AsyncIoError(io::Error::new(cause.kind(), cause.to_string()))
+            }
+            VfsErrorKind::FileNotFound => VfsErrorKind::FileNotFound,
+            VfsErrorKind::InvalidPath => VfsErrorKind::InvalidPath,
+            VfsErrorKind::Other(message) => VfsErrorKind::Other(message.clone()),
+            VfsErrorKind::DirectoryExists => VfsErrorKind::DirectoryExists,
+            VfsErrorKind::FileExists => VfsErrorKind::FileExists,
+            VfsErrorKind::NotSupported => VfsErrorKind::NotSupported,
+            VfsErrorKind::CrossMount => VfsErrorKind::CrossMount,
+            VfsErrorKind::EscapesRoot => VfsErrorKind::EscapesRoot,
+            VfsErrorKind::ReadOnly => VfsErrorKind::ReadOnly,
+            VfsErrorKind::AmbiguousPath => VfsErrorKind::AmbiguousPath,
+            VfsErrorKind::IntegrityViolation => VfsErrorKind::IntegrityViolation,
+            VfsErrorKind::Excluded => VfsErrorKind::Excluded,
+            VfsErrorKind::Cancelled(progress) => VfsErrorKind::Cancelled(*progress),
+        }
+    }
 }
 
 impl fmt::Display for VfsErrorKind {
@@ -135,7 +195,7 @@ impl fmt::Display for VfsErrorKind {
             VfsErrorKind::IoError(cause) => {
                 write!(f, "IO error: {cause}")
             }
-            #[cfg(feature = "async-vfs")]
+            #[cfg(feature = "async-vfs-core")]
             VfsErrorKind::AsyncIoError(cause) => {
                 write!(f, "Async IO error: {cause}")
             }
@@ -157,6 +217,27 @@ impl fmt::Display for VfsErrorKind {
             VfsErrorKind::FileExists => {
                 write!(f, "File already exists")
             }
+            VfsErrorKind::CrossMount => {
+                write!(f, "Source and destination are on different mounts")
+            }
+            VfsErrorKind::EscapesRoot => {
+                write!(f, "The path leads outside of the filesystem root")
+            }
+            VfsErrorKind::ReadOnly => {
+                write!(f, "The filesystem is read-only")
+            }
+            VfsErrorKind::AmbiguousPath => {
+                write!(f, "The path matches several entries differing only by case")
+            }
+            VfsErrorKind::IntegrityViolation => {
+                write!(f, "The data failed an integrity check")
+            }
+            VfsErrorKind::Excluded => {
+                write!(f, "The path is excluded by a filter")
+            }
+            VfsErrorKind::Cancelled(progress) => {
+                write!(f, "The operation was cancelled after {progress}")
+            }
         }
     }
 }
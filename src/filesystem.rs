@@ -1,7 +1,7 @@
 //! The filesystem trait definitions needed to implement new virtual filesystems
 
 use crate::error::VfsErrorKind;
-use crate::{SeekAndRead, SeekAndWrite, VfsError, VfsMetadata, VfsPath, VfsResult};
+use crate::{SeekAndRead, SeekAndWrite, VfsError, VfsMetadata, VfsPath, VfsResult, WatchEvent};
 use std::fmt::Debug;
 use std::time::SystemTime;
 
@@ -58,6 +58,16 @@ pub trait FileSystem: Debug + Sync + Send + 'static {
     fn move_dir(&self, _src: &str, _dest: &str) -> VfsResult<()> {
         Err(VfsErrorKind::NotSupported.into())
     }
+    /// Watches the path for changes to itself and its direct children, or all its descendants if `recursive` is set (optional)
+    ///
+    /// The returned iterator blocks until the next change is reported.
+    fn watch(
+        &self,
+        _path: &str,
+        _recursive: bool,
+    ) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
 }
 
 impl<T: FileSystem> From<T> for VfsPath {
@@ -0,0 +1,181 @@
+//! Minimal glob matching for `.gitignore`-style patterns, used by the filtering filesystem wrappers
+
+/// A single `.gitignore`-style glob pattern
+///
+/// Supports `*` and `?` (not matching '/'), `**` as a whole path component (matching any number of directories)
+/// and character classes like `[abc]`, `[a-z]` and `[!a]`.
+///
+/// Patterns without a slash match the filename at any depth, patterns containing a slash are anchored at the root.
+/// A trailing slash only matches directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GlobPattern {
+    components: Vec<String>,
+    anchored: bool,
+    directory_only: bool,
+}
+
+impl GlobPattern {
+    pub(crate) fn new(pattern: &str) -> Self {
+        let directory_only = pattern.len() > 1 && pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let components = pattern
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(String::from)
+            .collect();
+        GlobPattern {
+            components,
+            anchored,
+            directory_only,
+        }
+    }
+
+    /// Matches a path relative to the root (without leading slash)
+    pub(crate) fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let path = path.trim_start_matches('/');
+        if self.anchored {
+            let path_components: Vec<&str> = path.split('/').collect();
+            let pattern_components: Vec<&str> =
+                self.components.iter().map(String::as_str).collect();
+            match_components(&pattern_components, &path_components)
+        } else {
+            let filename = &path[path.rfind('/').map(|index| index + 1).unwrap_or(0)..];
+            self.components
+                .iter()
+                .all(|component| component == "**" || match_component(component, filename))
+        }
+    }
+}
+
+fn match_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_components(rest, &path[skip..])),
+        Some((component, rest)) => match path.split_first() {
+            Some((first, path_rest)) => {
+                match_component(component, first) && match_components(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Matches a single path component against a pattern component
+fn match_component(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_chars(&pattern, &text)
+}
+
+fn match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| match_chars(rest, &text[skip..])),
+        Some(('?', rest)) => !text.is_empty() && match_chars(rest, &text[1..]),
+        Some(('[', rest)) => match (parse_class(rest), text.split_first()) {
+            (Some((matcher, rest)), Some((c, text_rest))) => {
+                matcher(*c) && match_chars(rest, text_rest)
+            }
+            // An unterminated class is matched literally
+            (None, Some(('[', text_rest))) => match_chars(rest, text_rest),
+            _ => false,
+        },
+        Some(('\\', rest)) if !rest.is_empty() => {
+            text.first() == Some(&rest[0]) && match_chars(&rest[1..], &text[1..])
+        }
+        Some((c, rest)) => text.first() == Some(c) && match_chars(rest, &text[1..]),
+    }
+}
+
+/// Parses a character class after the opening '[', returning a matcher and the remaining pattern
+fn parse_class(pattern: &[char]) -> Option<(impl Fn(char) -> bool, &[char])> {
+    let (negated, pattern) = match pattern.first() {
+        Some('!') | Some('^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    // A ']' directly after the opening bracket is part of the class
+    let end = pattern
+        .iter()
+        .skip(1)
+        .position(|c| *c == ']')
+        .map(|index| index + 1)?;
+    let class = &pattern[..end];
+    let mut ranges = vec![];
+    let mut index = 0;
+    while index < class.len() {
+        if index + 2 < class.len() && class[index + 1] == '-' {
+            ranges.push((class[index], class[index + 2]));
+            index += 3;
+        } else {
+            ranges.push((class[index], class[index]));
+            index += 1;
+        }
+    }
+    let matcher =
+        move |c: char| ranges.iter().any(|(start, end)| *start <= c && c <= *end) != negated;
+    Some((matcher, &pattern[end + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        GlobPattern::new(pattern).matches(path, false)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.log", "server.log"));
+        assert!(matches("*.log", "var/log/server.log"));
+        assert!(!matches("*.log", "server.log.gz"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file10.txt"));
+        assert!(matches("*", "anything"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches("file[0-9].txt", "file5.txt"));
+        assert!(!matches("file[0-9].txt", "filex.txt"));
+        assert!(matches("file[!0-9].txt", "filex.txt"));
+        assert!(matches("[ab]c", "bc"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a[b", "a[b"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+    }
+
+    #[test]
+    fn anchored() {
+        assert!(matches("/build", "build"));
+        assert!(!matches("/build", "src/build"));
+        assert!(matches("build", "src/build"));
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(!matches("src/*.rs", "src/impls/mod.rs"));
+        assert!(!matches("src/*.rs", "other/src/lib.rs"));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(matches("**/foo", "foo"));
+        assert!(matches("**/foo", "a/b/foo"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "a/x/y/c"));
+        assert!(matches("a/**", "a/x/y"));
+        assert!(!matches("a/**", "b/x"));
+    }
+
+    #[test]
+    fn directory_only() {
+        let pattern = GlobPattern::new("target/");
+        assert!(pattern.matches("target", true));
+        assert!(pattern.matches("sub/target", true));
+        assert!(!pattern.matches("target", false));
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::{
     error::VfsErrorKind, FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult,
+    WatchEvent,
 };
 
 use std::time::SystemTime;
@@ -97,6 +98,26 @@ impl FileSystem for AltrootFS {
         }
         self.path(src)?.copy_file(&self.path(dest)?)
     }
+
+    fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        let root = self.root.as_str().to_string();
+        let events = self.path(path)?.watch(recursive)?;
+        Ok(Box::new(events.filter_map(move |event| {
+            event.translate(|path| strip_root(&root, path))
+        })))
+    }
+}
+
+/// Translates a path of the underlying filesystem to a path below the given root
+pub(crate) fn strip_root(root: &str, path: &str) -> Option<String> {
+    match path.strip_prefix(root) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => Some(rest.to_string()),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
@@ -119,6 +140,28 @@ mod tests {
         assert_eq!(altroot.parent(), altroot.root());
         assert_eq!(altroot_path.parent(), memory_root);
     }
+
+    #[test]
+    fn watch_translates_paths() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        let altroot_path = memory_root.join("altroot")?;
+        altroot_path.create_dir()?;
+        let altroot: VfsPath = AltrootFS::new(altroot_path).into();
+        let events = altroot.watch(true)?;
+        altroot.join("foo")?.create_dir()?;
+        memory_root
+            .join("altroot/foo")?
+            .move_dir(&memory_root.join("outside")?)?;
+        drop((altroot, memory_root));
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![
+                WatchEvent::Created("/foo".into()),
+                WatchEvent::Removed("/foo".into()),
+            ]
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
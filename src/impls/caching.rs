@@ -0,0 +1,628 @@
+//! A read-through cache in front of another filesystem
+
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Statistics about the cache lookups of a [`CachingFS`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of lookups answered from the cache
+    pub hits: u64,
+    /// Number of lookups that had to go to the inner filesystem
+    pub misses: u64,
+    /// Number of entries dropped to stay within the limits
+    pub evictions: u64,
+}
+
+/// A read-through cache for the directory tree at a particular path of another filesystem
+///
+/// File contents, metadata and directory listings are kept in a least-recently-used cache
+/// limited by the total number of bytes and the number of entries. Optionally, entries expire after a time-to-live.
+/// Files larger than the byte limit are never cached.
+///
+/// Modifications made through this filesystem invalidate the affected entries.
+/// Use [`CachingFS::clear()`] after modifying the inner filesystem directly.
+///
+/// Clones share the same cache, so a clone can be kept for reading the statistics.
+///
+/// ```
+/// # use vfs::{CachingFS, MemoryFS, VfsError, VfsPath};
+/// # use std::time::Duration;
+/// let inner: VfsPath = MemoryFS::new().into();
+/// inner.join("data.txt")?.create_file()?.write_all(b"expensive")?;
+///
+/// let caching = CachingFS::new(inner)
+///     .max_bytes(1024 * 1024)
+///     .max_entries(100)
+///     .ttl(Duration::from_secs(60));
+/// let root: VfsPath = caching.clone().into();
+///
+/// assert_eq!(root.join("data.txt")?.read_to_string()?, "expensive");
+/// assert_eq!(root.join("data.txt")?.read_to_string()?, "expensive");
+/// assert!(caching.stats().hits > 0);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachingFS {
+    root: VfsPath,
+    cache: Arc<Mutex<Lru>>,
+}
+
+impl CachingFS {
+    /// Creates a new cache with its root at the given virtual path
+    ///
+    /// By default up to 64 MiB in up to 1024 entries are cached, without expiration.
+    pub fn new(root: VfsPath) -> Self {
+        CachingFS {
+            root,
+            cache: Arc::new(Mutex::new(Lru {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+                generation: 0,
+                bytes: 0,
+                max_bytes: 64 * 1024 * 1024,
+                max_entries: 1024,
+                ttl: None,
+                stats: CacheStats::default(),
+            })),
+        }
+    }
+
+    /// Sets the maximum total size of the cached data in bytes
+    pub fn max_bytes(self, max_bytes: usize) -> Self {
+        self.cache.lock().unwrap().max_bytes = max_bytes;
+        self
+    }
+
+    /// Sets the maximum number of cached entries
+    pub fn max_entries(self, max_entries: usize) -> Self {
+        self.cache.lock().unwrap().max_entries = max_entries;
+        self
+    }
+
+    /// Sets the time after which cached entries expire
+    pub fn ttl(self, ttl: Duration) -> Self {
+        self.cache.lock().unwrap().ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the current statistics
+    pub fn stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
+    }
+
+    /// Drops all cached entries
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<CacheValue> {
+        self.cache.lock().unwrap().get(key)
+    }
+
+    /// Returns the current generation, to be taken before reading from the inner filesystem
+    fn generation(&self) -> u64 {
+        self.cache.lock().unwrap().generation
+    }
+
+    /// Caches a value read from the inner filesystem, unless it was invalidated since `generation`
+    fn insert(&self, generation: u64, key: CacheKey, value: CacheValue) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.generation == generation {
+            cache.insert(key, value);
+        }
+    }
+
+    /// Invalidates everything that changes when creating or removing the entry at the given path
+    fn invalidate_entry(&self, path: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.remove_path(path);
+        cache.remove(&CacheKey::Listing(parent(path).to_string()));
+    }
+
+    fn invalidate_tree(&self, path: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.remove_tree(path);
+        cache.remove(&CacheKey::Listing(parent(path).to_string()));
+    }
+
+    fn writer(
+        &self,
+        path: &str,
+        inner: Box<dyn SeekAndWrite + Send>,
+    ) -> Box<dyn SeekAndWrite + Send> {
+        Box::new(InvalidatingWriter {
+            inner,
+            cache: self.cache.clone(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl FileSystem for CachingFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let key = CacheKey::Listing(path.to_string());
+        let entries = match self.lookup(&key) {
+            Some(CacheValue::Listing(entries)) => entries,
+            _ => {
+                let generation = self.generation();
+                let entries: Vec<String> = self
+                    .path(path)?
+                    .read_dir()?
+                    .map(|path| path.filename())
+                    .collect();
+                self.insert(generation, key, CacheValue::Listing(entries.clone()));
+                entries
+            }
+        };
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.create_dir()?;
+        self.invalidate_entry(path);
+        Ok(())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let key = CacheKey::Content(path.to_string());
+        if let Some(CacheValue::Content(content)) = self.lookup(&key) {
+            return Ok(Box::new(Cursor::new(content)));
+        }
+        let generation = self.generation();
+        let metadata = self.metadata(path)?;
+        let mut file = self.path(path)?.open_file()?;
+        if metadata.len > self.cache.lock().unwrap().max_bytes as u64 {
+            return Ok(file);
+        }
+        let mut content = Vec::with_capacity(metadata.len as usize);
+        file.read_to_end(&mut content)?;
+        let content: Arc<[u8]> = content.into();
+        self.insert(generation, key, CacheValue::Content(content.clone()));
+        Ok(Box::new(Cursor::new(content)))
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.path(path)?.create_file()?;
+        self.invalidate_entry(path);
+        Ok(self.writer(path, file))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.path(path)?.append_file()?;
+        self.cache.lock().unwrap().remove_path(path);
+        Ok(self.writer(path, file))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let key = CacheKey::Metadata(path.to_string());
+        if let Some(CacheValue::Metadata(metadata)) = self.lookup(&key) {
+            return Ok(metadata);
+        }
+        let generation = self.generation();
+        let metadata = self.path(path)?.metadata()?;
+        self.insert(generation, key, CacheValue::Metadata(metadata.clone()));
+        Ok(metadata)
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_creation_time(time)?;
+        self.cache.lock().unwrap().remove_path(path);
+        Ok(())
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_modification_time(time)?;
+        self.cache.lock().unwrap().remove_path(path);
+        Ok(())
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_access_time(time)?;
+        self.cache.lock().unwrap().remove_path(path);
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        if let Some(CacheValue::Metadata(_)) = self.lookup(&CacheKey::Metadata(path.to_string())) {
+            return Ok(true);
+        }
+        self.path(path)?.exists()
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.remove_file()?;
+        self.invalidate_entry(path);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.remove_dir()?;
+        self.invalidate_tree(path);
+        Ok(())
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.copy_file(&self.path(dest)?)?;
+        self.invalidate_entry(dest);
+        Ok(())
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_file(&self.path(dest)?)?;
+        self.invalidate_entry(src);
+        self.invalidate_entry(dest);
+        Ok(())
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_dir(&self.path(dest)?)?;
+        self.invalidate_tree(src);
+        self.invalidate_tree(dest);
+        Ok(())
+    }
+}
+
+fn parent(path: &str) -> &str {
+    &path[..path.rfind('/').unwrap_or(0)]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Content(String),
+    Metadata(String),
+    Listing(String),
+}
+
+impl CacheKey {
+    fn path(&self) -> &str {
+        match self {
+            CacheKey::Content(path) | CacheKey::Metadata(path) | CacheKey::Listing(path) => path,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum CacheValue {
+    Content(Arc<[u8]>),
+    Metadata(VfsMetadata),
+    Listing(Vec<String>),
+}
+
+impl CacheValue {
+    /// Approximate memory used by the value
+    fn size(&self) -> usize {
+        match self {
+            CacheValue::Content(content) => content.len(),
+            CacheValue::Metadata(metadata) => std::mem::size_of_val(metadata),
+            CacheValue::Listing(entries) => entries.iter().map(String::len).sum(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    value: CacheValue,
+    size: usize,
+    inserted: Instant,
+    last_used: u64,
+}
+
+/// A least-recently-used cache, ordered by a counter incremented on every access
+///
+/// Values are read from the inner filesystem without holding the lock, so every invalidation
+/// increments the generation to keep reads that overlapped a modification from being cached.
+#[derive(Debug)]
+struct Lru {
+    entries: HashMap<CacheKey, CacheEntry>,
+    order: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    generation: u64,
+    bytes: usize,
+    max_bytes: usize,
+    max_entries: usize,
+    ttl: Option<Duration>,
+    stats: CacheStats,
+}
+
+impl Lru {
+    fn get(&mut self, key: &CacheKey) -> Option<CacheValue> {
+        let ttl = self.ttl;
+        let expired = match self.entries.get(key) {
+            Some(entry) => ttl.map_or(false, |ttl| entry.inserted.elapsed() >= ttl),
+            None => {
+                self.stats.misses += 1;
+                return None;
+            }
+        };
+        if expired {
+            self.remove(key);
+            self.stats.misses += 1;
+            return None;
+        }
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.order.insert(self.tick, key.clone());
+        self.stats.hits += 1;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, value: CacheValue) {
+        self.remove(&key);
+        let size = key.path().len() + value.size();
+        if size > self.max_bytes || self.max_entries == 0 {
+            return;
+        }
+        while self.entries.len() >= self.max_entries || self.bytes + size > self.max_bytes {
+            let oldest = match self.order.values().next() {
+                Some(oldest) => oldest.clone(),
+                None => break,
+            };
+            self.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.bytes += size;
+        self.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                size,
+                inserted: Instant::now(),
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.bytes -= entry.size;
+        }
+    }
+
+    /// Removes all entries for the given path
+    fn remove_path(&mut self, path: &str) {
+        self.generation += 1;
+        self.remove(&CacheKey::Content(path.to_string()));
+        self.remove(&CacheKey::Metadata(path.to_string()));
+        self.remove(&CacheKey::Listing(path.to_string()));
+    }
+
+    /// Removes all entries for the given path and everything below it
+    fn remove_tree(&mut self, path: &str) {
+        self.generation += 1;
+        let prefix = format!("{path}/");
+        let keys: Vec<CacheKey> = self
+            .entries
+            .keys()
+            .filter(|key| key.path() == path || key.path().starts_with(&prefix))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+}
+
+/// Invalidates the cached content and metadata of the file whenever it is written to
+struct InvalidatingWriter {
+    inner: Box<dyn SeekAndWrite + Send>,
+    cache: Arc<Mutex<Lru>>,
+    path: String,
+}
+
+impl InvalidatingWriter {
+    fn invalidate(&self) {
+        self.cache.lock().unwrap().remove_path(&self.path);
+    }
+}
+
+impl Write for InvalidatingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.invalidate();
+        result
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let result = self.inner.flush();
+        self.invalidate();
+        result
+    }
+}
+
+impl Seek for InvalidatingWriter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl Drop for InvalidatingWriter {
+    fn drop(&mut self) {
+        // Buffered data may only reach the inner filesystem now
+        let _ = self.inner.flush();
+        self.invalidate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFS;
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        CachingFS::new(memory_root)
+    });
+
+    fn create_inner() -> VfsResult<VfsPath> {
+        let inner: VfsPath = MemoryFS::new().into();
+        inner.join("dir")?.create_dir()?;
+        inner.join("dir/a.txt")?.create_file()?.write_all(b"aaaa")?;
+        inner.join("dir/b.txt")?.create_file()?.write_all(b"bbbb")?;
+        Ok(inner)
+    }
+
+    #[test]
+    fn serves_repeated_reads_from_cache() -> VfsResult<()> {
+        let inner = create_inner()?;
+        let fs = CachingFS::new(inner.clone());
+
+        let mut content = String::new();
+        fs.open_file("/dir/a.txt")?.read_to_string(&mut content)?;
+        assert_eq!(content, "aaaa");
+        assert_eq!(fs.read_dir("/dir")?.count(), 2);
+        let misses = fs.stats().misses;
+
+        // Changes to the inner filesystem are not visible until the cache is cleared
+        inner
+            .join("dir/a.txt")?
+            .create_file()?
+            .write_all(b"changed")?;
+        inner.join("dir/c.txt")?.create_file()?;
+        let mut content = String::new();
+        fs.open_file("/dir/a.txt")?.read_to_string(&mut content)?;
+        assert_eq!(content, "aaaa");
+        assert_eq!(fs.metadata("/dir/a.txt")?.len, 4);
+        assert!(fs.exists("/dir/a.txt")?);
+        assert_eq!(fs.read_dir("/dir")?.count(), 2);
+        assert_eq!(
+            fs.stats(),
+            CacheStats {
+                hits: 4,
+                misses,
+                evictions: 0
+            }
+        );
+
+        fs.clear();
+        let mut content = String::new();
+        fs.open_file("/dir/a.txt")?.read_to_string(&mut content)?;
+        assert_eq!(content, "changed");
+        assert_eq!(fs.read_dir("/dir")?.count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn writes_invalidate_entries() -> VfsResult<()> {
+        let root: VfsPath = CachingFS::new(create_inner()?).into();
+        let file = root.join("dir/a.txt")?;
+        assert_eq!(file.read_to_string()?, "aaaa");
+
+        let mut writer = file.append_file()?;
+        writer.write_all(b"++")?;
+        writer.flush()?;
+        assert_eq!(file.read_to_string()?, "aaaa++");
+        writer.write_all(b"--")?;
+        drop(writer);
+        assert_eq!(file.read_to_string()?, "aaaa++--");
+        assert_eq!(file.metadata()?.len, 8);
+
+        assert_eq!(root.join("dir")?.read_dir()?.count(), 2);
+        root.join("dir/new.txt")?.create_file()?;
+        assert_eq!(root.join("dir")?.read_dir()?.count(), 3);
+        file.remove_file()?;
+        assert!(!file.exists()?);
+        assert_eq!(root.join("dir")?.read_dir()?.count(), 2);
+
+        root.join("dir")?.move_dir(&root.join("moved")?)?;
+        assert!(!root.join("dir/b.txt")?.exists()?);
+        assert_eq!(root.join("moved/b.txt")?.read_to_string()?, "bbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn reads_overlapping_writes_are_not_cached() -> VfsResult<()> {
+        let fs = CachingFS::new(create_inner()?);
+        let root: VfsPath = fs.clone().into();
+        // A read that started before the file was written to finishes afterwards
+        let generation = fs.generation();
+        let stale: Arc<[u8]> = root
+            .join("dir/a.txt")?
+            .read_to_string()?
+            .into_bytes()
+            .into();
+        root.join("dir/a.txt")?.append_file()?.write_all(b"++")?;
+        let key = CacheKey::Content("/dir/a.txt".into());
+        fs.insert(generation, key.clone(), CacheValue::Content(stale));
+
+        assert!(fs.lookup(&key).is_none());
+        assert_eq!(root.join("dir/a.txt")?.read_to_string()?, "aaaa++");
+        Ok(())
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() -> VfsResult<()> {
+        let fs = CachingFS::new(create_inner()?).max_entries(2);
+        fs.metadata("/dir/a.txt")?;
+        fs.metadata("/dir/b.txt")?;
+        fs.metadata("/dir/a.txt")?;
+        fs.metadata("/dir")?;
+        assert_eq!(fs.stats().evictions, 1);
+
+        // b.txt was the least recently used entry
+        let misses = fs.stats().misses;
+        fs.metadata("/dir/a.txt")?;
+        assert_eq!(fs.stats().misses, misses);
+        fs.metadata("/dir/b.txt")?;
+        assert_eq!(fs.stats().misses, misses + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn respects_byte_limit() -> VfsResult<()> {
+        let inner = create_inner()?;
+        inner
+            .join("large.bin")?
+            .create_file()?
+            .write_all(&[0; 1000])?;
+        let fs = CachingFS::new(inner).max_bytes(500);
+
+        for _ in 0..2 {
+            let mut content = vec![];
+            fs.open_file("/large.bin")?.read_to_end(&mut content)?;
+            assert_eq!(content.len(), 1000);
+        }
+        let cache = fs.cache.lock().unwrap();
+        assert!(!cache
+            .entries
+            .contains_key(&CacheKey::Content("/large.bin".into())));
+        assert!(cache.bytes <= 500);
+        Ok(())
+    }
+
+    #[test]
+    fn entries_expire() -> VfsResult<()> {
+        let fs = CachingFS::new(create_inner()?).ttl(Duration::from_millis(20));
+        fs.metadata("/dir/a.txt")?;
+        fs.metadata("/dir/a.txt")?;
+        assert_eq!(fs.stats().hits, 1);
+        std::thread::sleep(Duration::from_millis(30));
+        fs.metadata("/dir/a.txt")?;
+        assert_eq!(fs.stats().hits, 1);
+        assert_eq!(fs.stats().misses, 2);
+        Ok(())
+    }
+}
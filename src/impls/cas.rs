@@ -0,0 +1,1089 @@
+//! A content-addressed file system, storing each distinct file content only once
+
+use crate::error::VfsErrorKind;
+use crate::{
+    FileSystem, SeekAndRead, SeekAndWrite, VfsError, VfsFileType, VfsMetadata, VfsPath, VfsResult,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MANIFEST: &str = "manifest";
+const MANIFEST_TEMP: &str = "manifest.tmp";
+const MANIFEST_HEADER: &str = "vfs-cas-manifest 1";
+const JOURNAL: &str = "journal";
+const JOURNAL_HEADER: &str = "vfs-cas-journal 1";
+/// The number of journaled changes below which the manifest is not rewritten, however small it is
+const MIN_COMPACTION_CHANGES: usize = 1024;
+
+/// A content-addressed file system storing its data in a directory of another filesystem
+///
+/// File contents are stored as blobs named by their SHA-256 hash below `blobs/` in the store,
+/// so identical files are only stored once. The directory tree is kept in a separate `manifest` file
+/// in the store. Modifications are appended to a `journal` file, which is merged into the manifest
+/// when the store is opened or once it holds more changes than the manifest has entries.
+///
+/// Copying and moving files and directories only changes the manifest. Blobs are removed as soon as
+/// they are no longer referenced by the persisted state, so a failed modification leaves the store intact.
+///
+/// Files are written to memory and stored when flushed or dropped, similar to [`MemoryFS`](crate::MemoryFS),
+/// so a created file only appears once it is flushed or dropped.
+/// Errors while storing a dropped file are ignored, call `flush()` before dropping it to see them.
+///
+/// Requires the `cas-fs` feature.
+///
+/// ```
+/// # use vfs::{CasFS, MemoryFS, VfsError, VfsPath};
+/// let store: VfsPath = MemoryFS::new().into();
+/// let root: VfsPath = CasFS::new(store.clone())?.into();
+///
+/// root.join("a.txt")?.create_file()?.write_all(b"Hello World")?;
+/// root.join("b.txt")?.create_file()?.write_all(b"Hello World")?;
+/// root.join("a.txt")?.copy_file(&root.join("c.txt")?)?;
+///
+/// // The store can be opened again later
+/// let reopened: VfsPath = CasFS::new(store)?.into();
+/// assert_eq!(reopened.join("c.txt")?.read_to_string()?, "Hello World");
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CasFS {
+    inner: Arc<CasInner>,
+}
+
+#[derive(Debug)]
+struct CasInner {
+    store: VfsPath,
+    state: RwLock<CasState>,
+}
+
+#[derive(Debug, Default)]
+struct CasState {
+    entries: BTreeMap<String, Entry>,
+    /// Number of files referencing each blob
+    references: HashMap<String, usize>,
+    /// Blobs that lost their last reference, removed once the modification is persisted
+    released: Vec<String>,
+    /// The previous entries of the paths changed by the current modification, to undo it on failure
+    undo: Vec<(String, Option<Entry>)>,
+    /// Number of changes appended to the journal since the manifest was written
+    journaled: usize,
+    /// Whether the journal may be incomplete or belong to another manifest, so the manifest must be
+    /// written before appending to it again
+    stale_journal: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Times {
+    created: Option<SystemTime>,
+    modified: Option<SystemTime>,
+    accessed: Option<SystemTime>,
+}
+
+impl Times {
+    fn now() -> Self {
+        let now = Some(SystemTime::now());
+        Times {
+            created: now,
+            modified: now,
+            accessed: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Directory {
+        times: Times,
+    },
+    File {
+        hash: String,
+        len: u64,
+        times: Times,
+    },
+}
+
+impl Entry {
+    fn times_mut(&mut self) -> &mut Times {
+        match self {
+            Entry::Directory { times } | Entry::File { times, .. } => times,
+        }
+    }
+}
+
+impl CasFS {
+    /// Opens the content-addressed filesystem stored in the given directory, creating it if necessary
+    pub fn new(store: VfsPath) -> VfsResult<Self> {
+        store.join("blobs")?.create_dir_all()?;
+        let mut manifest = store.join(MANIFEST)?;
+        if !manifest.exists()? {
+            // A crash while replacing the manifest may leave only the new version behind
+            manifest = store.join(MANIFEST_TEMP)?;
+        }
+        let mut state = CasState {
+            stale_journal: true,
+            ..CasState::default()
+        };
+        if manifest.exists()? {
+            let content = manifest.read_to_string()?;
+            parse_manifest(&mut state, &content).map_err(|err| err.with_path(manifest.as_str()))?;
+            let journal = store.join(JOURNAL)?;
+            if journal.exists()? {
+                replay_journal(
+                    &mut state,
+                    &journal.read_to_string()?,
+                    &hex_digest(content.as_bytes()),
+                )
+                .map_err(|err| err.with_path(journal.as_str()))?;
+            }
+        }
+        let inner = CasInner {
+            store,
+            state: RwLock::new(CasState::default()),
+        };
+        if state.journaled > 0 || state.stale_journal {
+            inner.compact(&mut state)?;
+        }
+        inner.remove_released(&mut state);
+        *inner.state.write().unwrap() = state;
+        Ok(CasFS {
+            inner: Arc::new(inner),
+        })
+    }
+}
+
+impl CasInner {
+    fn blob_path(&self, hash: &str) -> VfsResult<VfsPath> {
+        self.store.join(format!("blobs/{}/{}", &hash[..2], hash))
+    }
+
+    /// Stores the content if there is no blob with the same hash yet and returns the hash
+    fn store_blob(&self, state: &CasState, content: &[u8]) -> VfsResult<String> {
+        let hash = hex_digest(content);
+        let blob = self.blob_path(&hash)?;
+        if !state.references.contains_key(&hash) && !blob.exists()? {
+            blob.parent().create_dir_all()?;
+            let temp = blob.parent().join(format!("{hash}.tmp"))?;
+            temp.create_file()?.write_all(content)?;
+            temp.move_file(&blob)?;
+        }
+        Ok(hash)
+    }
+
+    /// Writes the manifest to the store, replacing the previous one, and starts a new journal for it
+    ///
+    /// The journal names the manifest it belongs to, so after a crash between both steps
+    /// the old journal is not replayed on top of the new manifest.
+    fn compact(&self, state: &mut CasState) -> VfsResult<()> {
+        state.stale_journal = true;
+        let manifest = format_manifest(state);
+        let temp = self.store.join(MANIFEST_TEMP)?;
+        let mut file = temp.create_file()?;
+        file.write_all(manifest.as_bytes())?;
+        file.flush()?;
+        drop(file);
+        temp.replace_file(&self.store.join(MANIFEST)?)?;
+        let mut journal = self.store.join(JOURNAL)?.create_file()?;
+        writeln!(
+            journal,
+            "{JOURNAL_HEADER}\t{}",
+            hex_digest(manifest.as_bytes())
+        )?;
+        journal.flush()?;
+        state.journaled = 0;
+        state.stale_journal = false;
+        Ok(())
+    }
+
+    /// Persists the changes of the current modification by appending them to the journal
+    ///
+    /// The manifest is written instead if the journal is stale, and after appending once the journal
+    /// holds more changes than the manifest has entries.
+    fn persist(&self, state: &mut CasState) -> VfsResult<()> {
+        if state.undo.is_empty() {
+            return Ok(());
+        }
+        if state.stale_journal {
+            return self.compact(state);
+        }
+        let record = state.format_changes();
+        let appended = self
+            .store
+            .join(JOURNAL)?
+            .append_file()
+            .and_then(|mut journal| {
+                journal.write_all(record.as_bytes())?;
+                journal.flush()?;
+                Ok(())
+            });
+        if appended.is_err() {
+            // The journal may end with a partial record now, which must not be followed by others
+            state.stale_journal = true;
+            return appended;
+        }
+        state.journaled += 1;
+        if state.journaled > state.entries.len().max(MIN_COMPACTION_CHANGES) {
+            // The modification is persisted already, a failed compaction is retried with the next one
+            let _ = self.compact(state);
+        }
+        Ok(())
+    }
+
+    /// Applies a modification to the state, which is undone if it fails or cannot be persisted
+    ///
+    /// Blobs no longer referenced are removed afterwards, so a failure leaves both the state and the store intact.
+    fn update<T>(&self, modify: impl FnOnce(&mut CasState) -> VfsResult<T>) -> VfsResult<T> {
+        let mut guard = self.state.write().unwrap();
+        let state = &mut *guard;
+        let result = match modify(state) {
+            Ok(result) => self.persist(state).map(|()| result),
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            state.rollback();
+        }
+        state.undo.clear();
+        self.remove_released(state);
+        result
+    }
+
+    /// Removes the released blobs which are still unreferenced
+    fn remove_released(&self, state: &mut CasState) {
+        for hash in std::mem::take(&mut state.released) {
+            if state.references.contains_key(&hash) {
+                continue;
+            }
+            // The modification is complete, a blob failing to be removed only wastes space
+            if let Ok(blob) = self.blob_path(&hash) {
+                let _ = blob.remove_file();
+            }
+        }
+    }
+
+    /// Stores the content as the file at the given path
+    fn commit(&self, path: &str, content: &[u8]) -> VfsResult<()> {
+        self.update(|state| {
+            let hash = self.store_blob(state, content)?;
+            let times = match state.entries.get(path) {
+                Some(Entry::File { times, .. }) => Times {
+                    modified: Some(SystemTime::now()),
+                    ..*times
+                },
+                _ => Times::now(),
+            };
+            let entry = Entry::File {
+                hash,
+                len: content.len() as u64,
+                times,
+            };
+            state.insert(path, entry);
+            Ok(())
+        })
+    }
+}
+
+impl CasState {
+    /// Sets or removes an entry, updating the reference counts, and returns the previous entry
+    fn replace(&mut self, path: &str, entry: Option<Entry>) -> Option<Entry> {
+        if let Some(Entry::File { hash, .. }) = &entry {
+            *self.references.entry(hash.clone()).or_default() += 1;
+        }
+        let previous = match entry {
+            Some(entry) => self.entries.insert(path.to_string(), entry),
+            None => self.entries.remove(path),
+        };
+        if let Some(Entry::File { hash, .. }) = &previous {
+            let references = self.references.entry(hash.clone()).or_default();
+            *references = references.saturating_sub(1);
+            if *references == 0 {
+                self.references.remove(hash);
+                self.released.push(hash.clone());
+            }
+        }
+        previous
+    }
+
+    /// Inserts an entry as part of the current modification
+    fn insert(&mut self, path: &str, entry: Entry) {
+        let previous = self.replace(path, Some(entry));
+        self.undo.push((path.to_string(), previous));
+    }
+
+    /// Removes an entry as part of the current modification
+    fn remove(&mut self, path: &str) {
+        let previous = self.replace(path, None);
+        self.undo.push((path.to_string(), previous));
+    }
+
+    /// Undoes the changes of the current modification
+    fn rollback(&mut self) {
+        while let Some((path, previous)) = self.undo.pop() {
+            self.replace(&path, previous);
+        }
+    }
+
+    /// Formats the changes of the current modification as a journal record
+    ///
+    /// Each changed path gets a manifest line with its new entry, or `r <path>` if it was removed.
+    /// The record ends with a `c` line, so a partially written record is ignored when replaying.
+    fn format_changes(&self) -> String {
+        let mut record = String::new();
+        let mut changed = HashSet::new();
+        for (path, _) in &self.undo {
+            if !changed.insert(path) {
+                continue;
+            }
+            match self.entries.get(path) {
+                Some(entry) => format_entry(&mut record, path, entry),
+                None => {
+                    let _ = writeln!(record, "r\t{}", escape_path(path));
+                }
+            }
+        }
+        record.push_str("c\n");
+        record
+    }
+
+    fn get(&self, path: &str) -> VfsResult<&Entry> {
+        self.entries
+            .get(path)
+            .ok_or_else(|| VfsErrorKind::FileNotFound.into())
+    }
+
+    fn file(&self, path: &str) -> VfsResult<&str> {
+        match self.get(path)? {
+            Entry::File { hash, .. } => Ok(hash),
+            Entry::Directory { .. } => Err(VfsErrorKind::Other("Not a file".into()).into()),
+        }
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        path.is_empty() || matches!(self.entries.get(path), Some(Entry::Directory { .. }))
+    }
+
+    fn ensure_has_parent(&self, path: &str) -> VfsResult<()> {
+        let parent = &path[..path.rfind('/').unwrap_or(0)];
+        if !self.is_dir(parent) {
+            return Err(VfsErrorKind::Other("Parent path does not exist".into()).into());
+        }
+        Ok(())
+    }
+
+    fn ensure_not_exists(&self, path: &str) -> VfsResult<()> {
+        match self.entries.get(path) {
+            Some(Entry::File { .. }) => Err(VfsErrorKind::FileExists.into()),
+            _ if self.is_dir(path) => Err(VfsErrorKind::DirectoryExists.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The paths of all entries below the given directory
+    fn descendants(&self, path: &str) -> Vec<String> {
+        let prefix = format!("{path}/");
+        self.entries
+            .range(prefix.clone()..)
+            .take_while(|(candidate, _)| candidate.starts_with(&prefix))
+            .map(|(candidate, _)| candidate.clone())
+            .collect()
+    }
+}
+
+impl FileSystem for CasFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let state = self.inner.state.read().unwrap();
+        if !state.is_dir(path) {
+            state.get(path)?;
+            return Err(VfsErrorKind::Other("Not a directory".into()).into());
+        }
+        let prefix = format!("{path}/");
+        let entries: Vec<String> = state
+            .descendants(path)
+            .into_iter()
+            .map(|candidate| candidate[prefix.len()..].to_string())
+            .filter(|name| !name.contains('/'))
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.inner.update(|state| {
+            state.ensure_has_parent(path)?;
+            state.ensure_not_exists(path)?;
+            let entry = Entry::Directory {
+                times: Times::now(),
+            };
+            state.insert(path, entry);
+            Ok(())
+        })
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let state = self.inner.state.read().unwrap();
+        let hash = state.file(path)?;
+        self.inner.blob_path(hash)?.open_file()
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        {
+            let state = self.inner.state.read().unwrap();
+            state.ensure_has_parent(path)?;
+            if state.is_dir(path) {
+                return Err(VfsErrorKind::DirectoryExists.into());
+            }
+        }
+        // The empty file is committed when flushed or dropped, unless content is written before
+        Ok(Box::new(WritableFile {
+            content: Cursor::new(vec![]),
+            destination: path.to_string(),
+            fs: self.inner.clone(),
+            dirty: true,
+        }))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let mut content = vec![];
+        self.open_file(path)?.read_to_end(&mut content)?;
+        let mut content = Cursor::new(content);
+        content.seek(SeekFrom::End(0))?;
+        Ok(Box::new(WritableFile {
+            content,
+            destination: path.to_string(),
+            fs: self.inner.clone(),
+            dirty: false,
+        }))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let state = self.inner.state.read().unwrap();
+        if path.is_empty() {
+            return Ok(VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                created: None,
+                modified: None,
+                accessed: None,
+            });
+        }
+        Ok(match state.get(path)? {
+            Entry::Directory { times } => VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                created: times.created,
+                modified: times.modified,
+                accessed: times.accessed,
+            },
+            Entry::File { len, times, .. } => VfsMetadata {
+                file_type: VfsFileType::File,
+                len: *len,
+                created: times.created,
+                modified: times.modified,
+                accessed: times.accessed,
+            },
+        })
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.set_times(path, |times| times.created = Some(time))
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.set_times(path, |times| times.modified = Some(time))
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.set_times(path, |times| times.accessed = Some(time))
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        let state = self.inner.state.read().unwrap();
+        Ok(path.is_empty() || state.entries.contains_key(path))
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.inner.update(|state| {
+            state.file(path)?;
+            state.remove(path);
+            Ok(())
+        })
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.inner.update(|state| {
+            match state.get(path)? {
+                Entry::Directory { .. } => {}
+                Entry::File { .. } => {
+                    return Err(VfsErrorKind::Other("Not a directory".into()).into())
+                }
+            }
+            if !state.descendants(path).is_empty() {
+                return Err(VfsErrorKind::Other("Directory to remove is not empty".into()).into());
+            }
+            state.remove(path);
+            Ok(())
+        })
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.inner.update(|state| {
+            state.ensure_has_parent(dest)?;
+            let (hash, len) = match state.get(src)? {
+                Entry::File { hash, len, .. } => (hash.clone(), *len),
+                Entry::Directory { .. } => {
+                    return Err(VfsErrorKind::Other("Not a file".into()).into())
+                }
+            };
+            if state.is_dir(dest) {
+                return Err(VfsErrorKind::DirectoryExists.into());
+            }
+            let entry = Entry::File {
+                hash,
+                len,
+                times: Times::now(),
+            };
+            state.insert(dest, entry);
+            Ok(())
+        })
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.inner.update(|state| {
+            state.ensure_has_parent(dest)?;
+            state.file(src)?;
+            state.ensure_not_exists(dest)?;
+            let entry = state.get(src)?.clone();
+            state.insert(dest, entry);
+            state.remove(src);
+            Ok(())
+        })
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.inner.update(|state| {
+            state.ensure_has_parent(dest)?;
+            if !state.is_dir(src) || src.is_empty() {
+                state.get(src)?;
+                return Err(VfsErrorKind::Other("Not a directory".into()).into());
+            }
+            if dest.starts_with(&format!("{src}/")) {
+                return Err(
+                    VfsErrorKind::Other("Cannot move a directory into itself".into()).into(),
+                );
+            }
+            state.ensure_not_exists(dest)?;
+            let mut paths = state.descendants(src);
+            paths.push(src.to_string());
+            for path in paths {
+                let entry = state.get(&path)?.clone();
+                state.insert(&format!("{dest}{}", &path[src.len()..]), entry);
+                state.remove(&path);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl CasFS {
+    fn set_times(&self, path: &str, update: impl FnOnce(&mut Times)) -> VfsResult<()> {
+        self.inner.update(|state| {
+            let mut entry = state.get(path)?.clone();
+            update(entry.times_mut());
+            state.insert(path, entry);
+            Ok(())
+        })
+    }
+}
+
+struct WritableFile {
+    content: Cursor<Vec<u8>>,
+    destination: String,
+    fs: Arc<CasInner>,
+    dirty: bool,
+}
+
+impl Seek for WritableFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.content.seek(pos)
+    }
+}
+
+impl Write for WritableFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.dirty = true;
+        self.content.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.fs
+            .commit(&self.destination, self.content.get_ref())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for WritableFile {
+    fn drop(&mut self) {
+        // Errors can not be reported from here, callers wanting to see them flush before dropping
+        let _ = self.flush();
+    }
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    match time.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+        Some(duration) => format!("{}.{:09}", duration.as_secs(), duration.subsec_nanos()),
+        None => "-".into(),
+    }
+}
+
+fn parse_time(value: &str) -> Option<Option<SystemTime>> {
+    if value == "-" {
+        return Some(None);
+    }
+    let (secs, nanos) = value.split_once('.')?;
+    let duration = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+    Some(Some(UNIX_EPOCH + duration))
+}
+
+fn escape_path(path: &str) -> String {
+    path.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape_path(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('t')) => result.push('\t'),
+            ('\\', Some('n')) => result.push('\n'),
+            ('\\', Some('\\')) => result.push('\\'),
+            _ => {
+                result.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    result
+}
+
+fn hex_digest(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .fold(String::with_capacity(64), |mut hash, byte| {
+            let _ = write!(hash, "{byte:02x}");
+            hash
+        })
+}
+
+/// Hashes are checked before being used in blob paths, so they cannot lead outside of the blob directory
+fn is_hash(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Formats an entry as a tab-separated line
+///
+/// Directories: `d <created> <modified> <accessed> <path>`,
+/// files: `f <created> <modified> <accessed> <hash> <len> <path>`
+fn format_entry(output: &mut String, path: &str, entry: &Entry) {
+    let (kind, times) = match entry {
+        Entry::Directory { times } => ("d", times),
+        Entry::File { times, .. } => ("f", times),
+    };
+    let _ = write!(
+        output,
+        "{kind}\t{}\t{}\t{}\t",
+        format_time(times.created),
+        format_time(times.modified),
+        format_time(times.accessed)
+    );
+    if let Entry::File { hash, len, .. } = entry {
+        let _ = write!(output, "{hash}\t{len}\t");
+    }
+    let _ = writeln!(output, "{}", escape_path(path));
+}
+
+/// Parses a line formatted by [`format_entry()`], returning `None` if it is malformed
+///
+/// Hashes other than 64 lowercase hexadecimal digits are rejected as integrity violations.
+fn parse_entry(line: &str) -> VfsResult<Option<(String, Entry)>> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let times = || -> Option<Times> {
+        Some(Times {
+            created: parse_time(fields[1])?,
+            modified: parse_time(fields[2])?,
+            accessed: parse_time(fields[3])?,
+        })
+    };
+    let (path, entry) = match fields.as_slice() {
+        ["d", _, _, _, path] => match times() {
+            Some(times) => (path, Entry::Directory { times }),
+            None => return Ok(None),
+        },
+        ["f", _, _, _, hash, len, path] => {
+            if !is_hash(hash) {
+                return Err(VfsErrorKind::IntegrityViolation.into());
+            }
+            match (len.parse(), times()) {
+                (Ok(len), Some(times)) => (
+                    path,
+                    Entry::File {
+                        hash: hash.to_string(),
+                        len,
+                        times,
+                    },
+                ),
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some((unescape_path(path), entry)))
+}
+
+/// Formats the manifest with one line per entry, see [`format_entry()`]
+fn format_manifest(state: &CasState) -> String {
+    let mut manifest = format!("{MANIFEST_HEADER}\n");
+    for (path, entry) in &state.entries {
+        format_entry(&mut manifest, path, entry);
+    }
+    manifest
+}
+
+fn parse_manifest(state: &mut CasState, manifest: &str) -> VfsResult<()> {
+    let invalid = |line: usize| -> VfsError {
+        VfsErrorKind::Other(format!("Invalid manifest in line {}", line + 1)).into()
+    };
+    let mut lines = manifest.lines().enumerate();
+    if lines.next().map(|(_, header)| header) != Some(MANIFEST_HEADER) {
+        return Err(invalid(0));
+    }
+    for (index, line) in lines {
+        let (path, entry) = parse_entry(line)
+            .map_err(|err| err.with_context(|| format!("Invalid hash in line {}", index + 1)))?
+            .ok_or_else(|| invalid(index))?;
+        state.replace(&path, Some(entry));
+    }
+    Ok(())
+}
+
+/// Applies the complete records of the journal, if it belongs to the manifest with the given hash
+///
+/// The journal is marked stale if it belongs to another manifest or ends with a partial record.
+fn replay_journal(state: &mut CasState, journal: &str, manifest_hash: &str) -> VfsResult<()> {
+    let invalid = |line: usize| -> VfsError {
+        VfsErrorKind::Other(format!("Invalid journal in line {}", line + 1)).into()
+    };
+    // Only lines ending with a newline were written completely
+    let mut lines: Vec<&str> = journal.split('\n').collect();
+    let partial = lines.pop().unwrap_or_default();
+    let mut lines = lines.into_iter().enumerate();
+    let header = format!("{JOURNAL_HEADER}\t{manifest_hash}");
+    if lines.next().map(|(_, line)| line) != Some(header.as_str()) {
+        return Ok(());
+    }
+    let mut record: Vec<(String, Option<Entry>)> = vec![];
+    for (index, line) in lines {
+        if line == "c" {
+            for (path, entry) in record.drain(..) {
+                state.replace(&path, entry);
+            }
+            state.journaled += 1;
+            continue;
+        }
+        let change = match line.strip_prefix("r\t") {
+            Some(path) => (unescape_path(path), None),
+            None => {
+                let (path, entry) = parse_entry(line)
+                    .map_err(|err| {
+                        err.with_context(|| format!("Invalid hash in line {}", index + 1))
+                    })?
+                    .ok_or_else(|| invalid(index))?;
+                (path, Some(entry))
+            }
+        };
+        record.push(change);
+    }
+    state.stale_journal = !record.is_empty() || !partial.is_empty();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::faulty::{Fault, FaultRule};
+    use crate::impls::tracing::TraceOperation;
+    use crate::{FaultyFS, MemoryFS, PhysicalFS};
+    test_vfs!({
+        let store: VfsPath = MemoryFS::new().into();
+        CasFS::new(store).unwrap()
+    });
+
+    fn blob_count(store: &VfsPath) -> VfsResult<usize> {
+        let mut count = 0;
+        for path in store.join("blobs")?.walk_dir()? {
+            if path?.is_file()? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    #[test]
+    fn deduplicates_identical_files() -> VfsResult<()> {
+        let store: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = CasFS::new(store.clone())?.into();
+        root.join("out")?.create_dir()?;
+        for name in ["a.o", "b.o", "c.o"] {
+            root.join("out")?
+                .join(name)?
+                .create_file()?
+                .write_all(b"object code")?;
+        }
+        root.join("out/other.o")?
+            .create_file()?
+            .write_all(b"other object code")?;
+        assert_eq!(blob_count(&store)?, 2);
+
+        root.join("out")?.copy_dir(&root.join("copy")?)?;
+        assert_eq!(blob_count(&store)?, 2);
+        assert_eq!(root.join("copy/b.o")?.read_to_string()?, "object code");
+
+        root.join("out")?.remove_dir_all()?;
+        assert_eq!(blob_count(&store)?, 2);
+        root.join("copy/other.o")?.remove_file()?;
+        assert_eq!(blob_count(&store)?, 1);
+        root.join("copy/a.o")?
+            .create_file()?
+            .write_all(b"changed")?;
+        assert_eq!(blob_count(&store)?, 2);
+        root.join("copy")?.remove_dir_all()?;
+        assert_eq!(blob_count(&store)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn failed_persist_keeps_state_and_blobs() -> VfsResult<()> {
+        let memory: VfsPath = MemoryFS::new().into();
+        // The second change journaled is the one removing the file
+        let store: VfsPath = FaultyFS::new(memory.clone())
+            .with_rule(
+                FaultRule::new(Fault::Error(VfsErrorKind::Other("disk full".into())))
+                    .on(TraceOperation::AppendFile)
+                    .matching("journal")
+                    .nth_call(2),
+            )
+            .into();
+        let root: VfsPath = CasFS::new(store)?.into();
+        let mut file = root.join("a.txt")?.create_file()?;
+        file.write_all(b"content")?;
+        file.flush()?;
+        drop(file);
+
+        assert!(root.join("a.txt")?.remove_file().is_err());
+        assert_eq!(root.join("a.txt")?.read_to_string()?, "content");
+        assert_eq!(blob_count(&memory)?, 1);
+        let reopened: VfsPath = CasFS::new(memory.clone())?.into();
+        assert_eq!(reopened.join("a.txt")?.read_to_string()?, "content");
+
+        // The next change rewrites the manifest, as the journal may be incomplete
+        root.join("a.txt")?.remove_file()?;
+        assert_eq!(blob_count(&memory)?, 0);
+        let reopened: VfsPath = CasFS::new(memory)?.into();
+        assert!(!reopened.join("a.txt")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn replays_complete_journal_records() -> VfsResult<()> {
+        let store: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = CasFS::new(store.clone())?.into();
+        root.join("a.txt")?.create_file()?.write_all(b"a")?;
+        root.join("dir")?.create_dir()?;
+        root.join("a.txt")?.move_file(&root.join("dir/b.txt")?)?;
+        let manifest = store.join(MANIFEST)?.read_to_string()?;
+        assert_eq!(manifest, format!("{MANIFEST_HEADER}\n"));
+        let journal = store.join(JOURNAL)?.read_to_string()?;
+        assert_eq!(journal.lines().filter(|line| *line == "c").count(), 3);
+        assert!(journal.contains("r\t/a.txt\n"));
+
+        // A record without its commit line was interrupted and is ignored
+        let hash = hex_digest(b"a");
+        write!(
+            store.join(JOURNAL)?.append_file()?,
+            "f\t-\t-\t-\t{hash}\t1\t/c.txt\nr\t/dir/b"
+        )?;
+        let root: VfsPath = CasFS::new(store.clone())?.into();
+        assert_eq!(root.join("dir/b.txt")?.read_to_string()?, "a");
+        assert!(!root.join("c.txt")?.exists()?);
+        assert_eq!(
+            store.join(JOURNAL)?.read_to_string()?,
+            format!(
+                "{JOURNAL_HEADER}\t{}\n",
+                hex_digest(store.join(MANIFEST)?.read_to_string()?.as_bytes())
+            )
+        );
+
+        // A journal belonging to an older manifest is ignored
+        store.join(JOURNAL)?.create_file()?.write_all(
+            format!(
+                "{JOURNAL_HEADER}\t{}\nr\t/dir\nc\n",
+                hex_digest(manifest.as_bytes())
+            )
+            .as_bytes(),
+        )?;
+        let root: VfsPath = CasFS::new(store)?.into();
+        assert_eq!(root.join("dir/b.txt")?.read_to_string()?, "a");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_hashes() -> VfsResult<()> {
+        for hash in [
+            "AB".repeat(32),
+            format!("../../{}", "a".repeat(58)),
+            "é".repeat(32),
+        ] {
+            let store: VfsPath = MemoryFS::new().into();
+            write!(
+                store.join(MANIFEST)?.create_file()?,
+                "{MANIFEST_HEADER}\nf\t-\t-\t-\t{hash}\t1\t/a.txt\n"
+            )?;
+            let error = CasFS::new(store).unwrap_err();
+            assert!(matches!(error.kind(), VfsErrorKind::IntegrityViolation));
+            assert_eq!(
+                error.to_string(),
+                "Invalid hash in line 2 for '/manifest': The data failed an integrity check"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn moves_do_not_replace_existing_entries() -> VfsResult<()> {
+        let store: VfsPath = MemoryFS::new().into();
+        let fs = CasFS::new(store.clone())?;
+        let root: VfsPath = fs.clone().into();
+        root.join("a.txt")?.create_file()?.write_all(b"a")?;
+        root.join("b.txt")?.create_file()?.write_all(b"b")?;
+        root.join("dir/sub")?.create_dir_all()?;
+        root.join("dir/sub/c.txt")?.create_file()?.write_all(b"c")?;
+        root.join("other")?.create_dir()?;
+
+        let error = fs.move_file("/a.txt", "/b.txt").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::FileExists));
+        let error = fs.move_file("/a.txt", "/dir").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::DirectoryExists));
+        let error = fs.move_dir("/other", "/dir").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::DirectoryExists));
+        let error = fs.move_dir("/other", "/b.txt").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::FileExists));
+
+        assert_eq!(root.join("b.txt")?.read_to_string()?, "b");
+        assert_eq!(root.join("dir/sub/c.txt")?.read_to_string()?, "c");
+        assert_eq!(blob_count(&store)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn reopens_existing_store() -> VfsResult<()> {
+        let store: VfsPath = MemoryFS::new().into();
+        {
+            let root: VfsPath = CasFS::new(store.clone())?.into();
+            root.join("dir\twith\\special\nchars")?.create_dir()?;
+            root.join("dir\twith\\special\nchars/file.txt")?
+                .create_file()?
+                .write_all(b"content")?;
+            root.join("empty.txt")?.create_file()?;
+            root.join("dir\twith\\special\nchars")?
+                .move_dir(&root.join("moved")?)?;
+        }
+        let root: VfsPath = CasFS::new(store)?.into();
+        let mut entries: Vec<_> = root.read_dir()?.map(|path| path.filename()).collect();
+        entries.sort();
+        assert_eq!(entries, vec!["empty.txt", "moved"]);
+        assert_eq!(root.join("moved/file.txt")?.read_to_string()?, "content");
+        assert_eq!(root.join("empty.txt")?.read_to_string()?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_manifest() -> VfsResult<()> {
+        let store: VfsPath = MemoryFS::new().into();
+        store
+            .join(MANIFEST)?
+            .create_file()?
+            .write_all(b"vfs-cas-manifest 1\nx\tbroken\n")?;
+        let error = CasFS::new(store).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "An error occurred for '/manifest': FileSystem error: Invalid manifest in line 2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_roundtrip() {
+        let mut state = CasState::default();
+        state.entries.insert(
+            "/dir".into(),
+            Entry::Directory {
+                times: Times {
+                    created: Some(UNIX_EPOCH + Duration::new(12, 345)),
+                    modified: None,
+                    accessed: None,
+                },
+            },
+        );
+        state.entries.insert(
+            "/dir/a\\b".into(),
+            Entry::File {
+                hash: "ab".repeat(32),
+                len: 42,
+                times: Times::default(),
+            },
+        );
+        let manifest = format_manifest(&state);
+        assert_eq!(
+            manifest,
+            format!(
+                "vfs-cas-manifest 1\nd\t12.000000345\t-\t-\t/dir\nf\t-\t-\t-\t{}\t42\t/dir/a\\\\b\n",
+                "ab".repeat(32)
+            )
+        );
+        let mut parsed = CasState::default();
+        parse_manifest(&mut parsed, &manifest).unwrap();
+        assert_eq!(format_manifest(&parsed), manifest);
+    }
+
+    #[test]
+    fn physical_store() -> VfsResult<()> {
+        let directory = std::env::temp_dir().join(format!("vfs-cas-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory)?;
+        let store: VfsPath = PhysicalFS::new(&directory).into();
+        let root: VfsPath = CasFS::new(store.clone())?.into();
+        root.join("a.txt")?.create_file()?.write_all(b"data")?;
+        root.join("a.txt")?.copy_file(&root.join("b.txt")?)?;
+        assert_eq!(blob_count(&store)?, 1);
+        assert_eq!(
+            CasFS::new(store)?.open_file("/b.txt").map(|mut file| {
+                let mut content = String::new();
+                file.read_to_string(&mut content).unwrap();
+                content
+            })?,
+            "data"
+        );
+        std::fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+}
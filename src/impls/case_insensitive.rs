@@ -0,0 +1,342 @@
+//! A case-insensitive view of another filesystem
+
+use crate::error::VfsErrorKind;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult};
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+/// A view of the directory tree at a particular path of another filesystem, resolving paths case-insensitively
+///
+/// Each path component is looked up in the directory listing of the inner filesystem, ignoring case.
+/// If an entry matches exactly it is always used. Otherwise, if several entries only differ by case,
+/// the lookup fails with [`VfsErrorKind::AmbiguousPath`].
+///
+/// New files and directories are created with the casing given by the caller.
+///
+/// Resolved paths are cached. The cache is kept up to date for modifications made through this filesystem,
+/// use [`CaseInsensitiveFS::clear_cache()`] after modifying the inner filesystem directly.
+///
+/// ```
+/// # use vfs::{CaseInsensitiveFS, MemoryFS, VfsError, VfsPath};
+/// let root: VfsPath = MemoryFS::new().into();
+/// root.join("Textures")?.create_dir()?;
+/// root.join("Textures/Grass.PNG")?.create_file()?;
+///
+/// let root: VfsPath = CaseInsensitiveFS::new(root).into();
+/// assert!(root.join("textures/grass.png")?.exists()?);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug)]
+pub struct CaseInsensitiveFS {
+    root: VfsPath,
+    /// Maps lowercased paths to the actual paths in the inner filesystem
+    cache: RwLock<HashMap<String, String>>,
+}
+
+impl CaseInsensitiveFS {
+    /// Create a new case-insensitive FileSystem with its root at the given virtual path
+    pub fn new(root: VfsPath) -> Self {
+        CaseInsensitiveFS {
+            root,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Forgets all cached path lookups
+    pub fn clear_cache(&self) {
+        self.cache.write().unwrap().clear();
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+
+    /// Resolves the path to the actual path in the inner filesystem
+    ///
+    /// Components that do not exist keep their casing, so the result can be used for creating new entries.
+    fn resolve(&self, path: &str) -> VfsResult<String> {
+        let mut resolved = String::new();
+        let mut components = path.split('/').skip(1);
+        while let Some(component) = components.next() {
+            let key = format!("{resolved}/{component}").to_lowercase();
+            if let Some(actual) = self.cache.read().unwrap().get(&key) {
+                resolved = actual.clone();
+                continue;
+            }
+            match self.lookup(&resolved, component)? {
+                Some((name, unique)) => {
+                    resolved = format!("{resolved}/{name}");
+                    // Exact matches among several candidates are not cached, since other spellings are ambiguous
+                    if unique {
+                        self.cache.write().unwrap().insert(key, resolved.clone());
+                    }
+                }
+                None => {
+                    resolved = format!("{resolved}/{component}");
+                    for component in components {
+                        resolved = format!("{resolved}/{component}");
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Looks up the entry in the given directory, returning its actual name and whether it is the only candidate
+    fn lookup(&self, dir: &str, name: &str) -> VfsResult<Option<(String, bool)>> {
+        let dir = self.path(dir)?;
+        if !dir.is_dir()? {
+            return Ok(None);
+        }
+        let lowercase = name.to_lowercase();
+        let mut candidates: Vec<String> = dir
+            .read_dir()?
+            .map(|entry| entry.filename())
+            .filter(|filename| filename.to_lowercase() == lowercase)
+            .collect();
+        let unique = candidates.len() == 1;
+        if candidates.iter().any(|candidate| candidate == name) {
+            return Ok(Some((name.to_string(), unique)));
+        }
+        match candidates.pop() {
+            Some(candidate) if candidates.is_empty() => Ok(Some((candidate, true))),
+            Some(_) => Err(VfsErrorKind::AmbiguousPath.into()),
+            None => Ok(None),
+        }
+    }
+
+    fn resolved_path(&self, path: &str) -> VfsResult<VfsPath> {
+        self.path(&self.resolve(path)?)
+    }
+
+    /// Removes the cached lookups of the path and everything below it
+    fn invalidate(&self, path: &str) {
+        let key = path.to_lowercase();
+        let prefix = format!("{key}/");
+        self.cache
+            .write()
+            .unwrap()
+            .retain(|cached, _| *cached != key && !cached.starts_with(&prefix));
+    }
+}
+
+impl FileSystem for CaseInsensitiveFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.resolved_path(path)?
+            .read_dir()
+            .map(|entries| Box::new(entries.map(|path| path.filename())) as Box<_>)
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.resolved_path(path)?.create_dir()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        self.resolved_path(path)?.open_file()
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.resolved_path(path)?.create_file()
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.resolved_path(path)?.append_file()
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.resolved_path(path)?.metadata()
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.resolved_path(path)?.set_creation_time(time)
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.resolved_path(path)?.set_modification_time(time)
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.resolved_path(path)?.set_access_time(time)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.resolved_path(path)?.exists()
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.resolved_path(path)?.remove_file()?;
+        self.invalidate(path);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.resolved_path(path)?.remove_dir()?;
+        self.invalidate(path);
+        Ok(())
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = self.resolved_path(dest)?;
+        self.resolved_path(src)?.copy_file(&dest)
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = self.resolved_path(dest)?;
+        self.resolved_path(src)?.move_file(&dest)?;
+        self.invalidate(src);
+        Ok(())
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = self.resolved_path(dest)?;
+        self.resolved_path(src)?.move_dir(&dest)?;
+        self.invalidate(src);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFS;
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        CaseInsensitiveFS::new(memory_root)
+    });
+
+    #[test]
+    fn resolves_case_insensitively() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        memory_root.join("Assets/Textures")?.create_dir_all()?;
+        memory_root
+            .join("Assets/Textures/Grass.PNG")?
+            .create_file()?
+            .write_all(b"grass")?;
+        let root: VfsPath = CaseInsensitiveFS::new(memory_root).into();
+
+        assert_eq!(
+            root.join("assets/TEXTURES/grass.png")?.read_to_string()?,
+            "grass"
+        );
+        assert!(root.join("ASSETS")?.is_dir()?);
+        assert!(!root.join("assets/grass.png")?.exists()?);
+        let entries: Vec<_> = root
+            .join("assets/textures")?
+            .read_dir()?
+            .map(|path| path.filename())
+            .collect();
+        assert_eq!(entries, vec!["Grass.PNG"]);
+        Ok(())
+    }
+
+    #[test]
+    fn creation_preserves_casing() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        memory_root.join("Maps")?.create_dir()?;
+        let root: VfsPath = CaseInsensitiveFS::new(memory_root.clone()).into();
+
+        root.join("maps/Level1")?.create_dir()?;
+        root.join("MAPS/level1/Intro.txt")?
+            .create_file()?
+            .write_all(b"intro")?;
+        assert_eq!(
+            memory_root
+                .join("Maps/Level1/Intro.txt")?
+                .read_to_string()?,
+            "intro"
+        );
+
+        // Existing entries are reused instead of creating differently cased duplicates
+        root.join("maps/level1/INTRO.TXT")?
+            .create_file()?
+            .write_all(b"replaced")?;
+        let entries: Vec<_> = memory_root
+            .join("Maps/Level1")?
+            .read_dir()?
+            .map(|path| path.filename())
+            .collect();
+        assert_eq!(entries, vec!["Intro.txt"]);
+        assert_eq!(
+            memory_root
+                .join("Maps/Level1/Intro.txt")?
+                .read_to_string()?,
+            "replaced"
+        );
+        assert!(matches!(
+            root.join("MAPS/LEVEL1")?.create_dir().unwrap_err().kind(),
+            VfsErrorKind::DirectoryExists
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous_paths() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        memory_root.join("readme.txt")?.create_file()?;
+        memory_root.join("README.txt")?.create_file()?;
+        let root: VfsPath = CaseInsensitiveFS::new(memory_root).into();
+
+        assert!(root.join("readme.txt")?.exists()?);
+        assert!(root.join("README.txt")?.exists()?);
+        let error = root.join("Readme.txt")?.exists().unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::AmbiguousPath));
+        let error = root.join("Readme.txt")?.metadata().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not get metadata for '/Readme.txt': The path matches several entries differing only by case"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cache_is_updated_on_modifications() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        memory_root.join("Dir/Sub")?.create_dir_all()?;
+        memory_root.join("Dir/Sub/File.txt")?.create_file()?;
+        let fs = CaseInsensitiveFS::new(memory_root.clone());
+        assert!(fs.exists("/dir/sub/file.txt")?);
+        assert_eq!(fs.cache.read().unwrap().len(), 3);
+
+        fs.move_dir("/dir", "/Other")?;
+        assert!(fs.cache.read().unwrap().is_empty());
+        assert!(!fs.exists("/dir/sub/file.txt")?);
+        assert!(fs.exists("/other/sub/file.txt")?);
+
+        // Changes to the inner filesystem require clearing the cache
+        memory_root
+            .join("Other")?
+            .move_dir(&memory_root.join("Moved")?)?;
+        fs.clear_cache();
+        assert!(fs.exists("/moved/SUB/FILE.TXT")?);
+        Ok(())
+    }
+
+    #[test]
+    fn physical_fs() -> VfsResult<()> {
+        let root: VfsPath =
+            CaseInsensitiveFS::new(crate::PhysicalFS::new("test/test_directory").into()).into();
+        assert_eq!(root.join("A/X/Y/Z")?.read_to_string()?, "zzz\n");
+        assert!(root.join("A.TXT.DIR/G.txt")?.is_file()?);
+        Ok(())
+    }
+
+    #[cfg(feature = "embedded-fs")]
+    #[test]
+    fn embedded_fs() -> VfsResult<()> {
+        #[derive(rust_embed::RustEmbed, Debug)]
+        #[folder = "test/test_directory"]
+        struct TestEmbed;
+
+        let embedded: VfsPath = crate::EmbeddedFS::<TestEmbed>::new().into();
+        let root: VfsPath = CaseInsensitiveFS::new(embedded).into();
+        assert!(root.join("A/D.TXT")?.is_file()?);
+        assert!(root.join("C")?.is_dir()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,718 @@
+//! A filesystem wrapper storing files compressed
+
+use crate::error::VfsErrorKind;
+use crate::impls::seek::resolve_seek;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsFileType, VfsMetadata, VfsPath, VfsResult};
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::SystemTime;
+
+const MAGIC: &[u8; 4] = b"VFSZ";
+const VERSION: u8 = 1;
+/// Magic, version, compression and chunk size
+const HEADER_LEN: u64 = 10;
+/// Index offset, chunk count, uncompressed length and magic
+const FOOTER_LEN: u64 = 28;
+
+/// The compression algorithm used for new files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip compression
+    Gzip,
+    /// Zstandard compression, requires the `compressed-fs-zstd` feature
+    #[cfg(feature = "compressed-fs-zstd")]
+    Zstd,
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            Compression::Gzip => 1,
+            #[cfg(feature = "compressed-fs-zstd")]
+            Compression::Zstd => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> io::Result<Self> {
+        match id {
+            1 => Ok(Compression::Gzip),
+            #[cfg(feature = "compressed-fs-zstd")]
+            2 => Ok(Compression::Zstd),
+            _ => Err(invalid_data("Unsupported compression")),
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "compressed-fs-zstd")]
+            Compression::Zstd => zstd::bulk::compress(data, 0),
+        }
+    }
+
+    fn decompress(self, data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        let mut result = Vec::with_capacity(len);
+        match self {
+            Compression::Gzip => {
+                flate2::read::GzDecoder::new(data).read_to_end(&mut result)?;
+            }
+            #[cfg(feature = "compressed-fs-zstd")]
+            Compression::Zstd => {
+                result = zstd::bulk::decompress(data, len)?;
+            }
+        }
+        if result.len() != len {
+            return Err(invalid_data("Unexpected chunk length"));
+        }
+        Ok(result)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A view of the directory tree at a particular path of another filesystem, storing all files compressed
+///
+/// Files are split into chunks which are compressed separately, followed by an index of the chunks.
+/// This allows seeking in files opened with `open_file()` by only decompressing the chunk containing
+/// the new position. The metadata reports the uncompressed length.
+///
+/// Files being written are compressed chunk by chunk. `flush()` makes the data written so far readable
+/// by writing the current chunk and the index, writing can continue afterwards. This is done as well
+/// when the handle is dropped, but errors are ignored then, call `flush()` before dropping it to see them.
+///
+/// **Seeking while writing is limited:** writers can only seek within the current chunk or forward,
+/// seeking back into complete chunks fails with [`std::io::ErrorKind::Unsupported`].
+///
+/// Requires the `compressed-fs` feature, Zstandard compression requires the `compressed-fs-zstd` feature.
+///
+/// ```
+/// # use vfs::{CompressedFS, MemoryFS, VfsError, VfsPath};
+/// use vfs::impls::compressed::Compression;
+///
+/// let inner: VfsPath = MemoryFS::new().into();
+/// let root: VfsPath = CompressedFS::new(inner.clone(), Compression::Gzip).into();
+///
+/// let text = "All work and no play makes Jack a dull boy. ".repeat(1000);
+/// root.join("corpus.txt")?.create_file()?.write_all(text.as_bytes())?;
+///
+/// assert_eq!(root.join("corpus.txt")?.metadata()?.len, text.len() as u64);
+/// assert!(inner.join("corpus.txt")?.metadata()?.len < text.len() as u64 / 10);
+/// assert_eq!(root.join("corpus.txt")?.read_to_string()?, text);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompressedFS {
+    root: VfsPath,
+    compression: Compression,
+    chunk_size: u32,
+}
+
+impl CompressedFS {
+    /// Create a new compressing FileSystem with its root at the given virtual path
+    ///
+    /// New files are compressed in chunks of 64 KiB.
+    pub fn new(root: VfsPath, compression: Compression) -> Self {
+        CompressedFS {
+            root,
+            compression,
+            chunk_size: 64 * 1024,
+        }
+    }
+
+    /// Sets the uncompressed size of the separately compressed chunks of new files
+    ///
+    /// Smaller chunks make seeking cheaper, larger chunks compress better.
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        assert!(chunk_size > 0, "Chunk size must not be zero");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+}
+
+impl FileSystem for CompressedFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.path(path)?
+            .read_dir()
+            .map(|entries| Box::new(entries.map(|path| path.filename())) as Box<_>)
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.create_dir()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let file = self.path(path)?.open_file()?;
+        Ok(Box::new(CompressedReader::new(file)?))
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.path(path)?.create_file()?;
+        Ok(Box::new(CompressedWriter::new(
+            file,
+            self.compression,
+            self.chunk_size,
+        )?))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        // Keep the complete chunks, continue with the decompressed last chunk
+        let inner = self.path(path)?;
+        let mut raw = vec![];
+        inner.open_file()?.read_to_end(&mut raw)?;
+        let mut reader = CompressedReader::new(io::Cursor::new(raw))?;
+        let complete_chunks = (reader.len / reader.chunk_size) as usize;
+        let mut last_chunk = vec![];
+        if complete_chunks < reader.chunk_count() {
+            reader.load_chunk(complete_chunks)?;
+            last_chunk = std::mem::take(&mut reader.current);
+        }
+        let raw = reader.inner.into_inner();
+
+        let mut writer = CompressedWriter::new(
+            inner.create_file()?,
+            reader.compression,
+            reader.chunk_size as u32,
+        )?;
+        for chunk in 0..complete_chunks {
+            let start = reader.offsets[chunk];
+            let end = reader.offsets[chunk + 1];
+            writer.write_compressed_chunk(&raw[start as usize..end as usize])?;
+        }
+        writer.buffer = last_chunk;
+        writer.position = writer.buffer_start + writer.buffer.len() as u64;
+        Ok(Box::new(writer))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let inner = self.path(path)?;
+        let mut metadata = inner.metadata()?;
+        if metadata.file_type == VfsFileType::File {
+            metadata.len = read_footer(&mut inner.open_file()?)?.len;
+        }
+        Ok(metadata)
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_creation_time(time)
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_modification_time(time)
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_access_time(time)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.path(path)
+            .map(|path| path.exists())
+            .unwrap_or(Ok(false))
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.remove_file()
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.remove_dir()
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.copy_file(&self.path(dest)?)
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_file(&self.path(dest)?)
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_dir(&self.path(dest)?)
+    }
+}
+
+struct Footer {
+    index_offset: u64,
+    chunk_count: u64,
+    len: u64,
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().expect("8 bytes"))
+}
+
+fn read_footer<R: Read + Seek + ?Sized>(file: &mut R) -> VfsResult<Footer> {
+    let invalid = || VfsErrorKind::Other("Not a compressed file".into());
+    let file_len = file.seek(SeekFrom::End(0))?;
+    if file_len < HEADER_LEN + FOOTER_LEN {
+        return Err(invalid().into());
+    }
+    let mut footer = [0; FOOTER_LEN as usize];
+    file.seek(SeekFrom::End(-(FOOTER_LEN as i64)))?;
+    file.read_exact(&mut footer)?;
+    if &footer[24..] != MAGIC {
+        return Err(invalid().into());
+    }
+    let footer = Footer {
+        index_offset: read_u64(&footer[0..8]),
+        chunk_count: read_u64(&footer[8..16]),
+        len: read_u64(&footer[16..24]),
+    };
+    let index_end = footer
+        .chunk_count
+        .checked_mul(8)
+        .and_then(|index_len| footer.index_offset.checked_add(index_len));
+    if footer.index_offset < HEADER_LEN || index_end != Some(file_len - FOOTER_LEN) {
+        return Err(invalid().into());
+    }
+    Ok(footer)
+}
+
+struct CompressedReader<R> {
+    inner: R,
+    compression: Compression,
+    chunk_size: u64,
+    /// The offsets of all chunks in the inner file, followed by the offset of the index
+    offsets: Vec<u64>,
+    len: u64,
+    position: u64,
+    current_chunk: Option<usize>,
+    current: Vec<u8>,
+}
+
+impl<R: Read + Seek> CompressedReader<R> {
+    fn new(mut inner: R) -> VfsResult<Self> {
+        let footer = read_footer(&mut inner)?;
+        let mut header = [0; HEADER_LEN as usize];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(VfsErrorKind::Other("Not a compressed file".into()).into());
+        }
+        let compression = Compression::from_id(header[5])?;
+        let chunk_size = u32::from_le_bytes(header[6..10].try_into().expect("4 bytes")) as u64;
+
+        let mut index = vec![0; footer.chunk_count as usize * 8];
+        inner.seek(SeekFrom::Start(footer.index_offset))?;
+        inner.read_exact(&mut index)?;
+        let mut offsets = Vec::with_capacity(footer.chunk_count as usize + 1);
+        let mut offset = HEADER_LEN;
+        for chunk_len in index.chunks(8) {
+            offsets.push(offset);
+            offset += read_u64(chunk_len);
+        }
+        offsets.push(offset);
+        // Files flushed while being written might have padding between the chunks and the index
+        if offset > footer.index_offset
+            || chunk_size == 0
+            || (footer.len + chunk_size - 1) / chunk_size != footer.chunk_count
+        {
+            return Err(VfsErrorKind::Other("Corrupt compressed file index".into()).into());
+        }
+        Ok(CompressedReader {
+            inner,
+            compression,
+            chunk_size,
+            offsets,
+            len: footer.len,
+            position: 0,
+            current_chunk: None,
+            current: vec![],
+        })
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    fn load_chunk(&mut self, chunk: usize) -> io::Result<()> {
+        if self.current_chunk == Some(chunk) {
+            return Ok(());
+        }
+        let start = self.offsets[chunk];
+        let mut compressed = vec![0; (self.offsets[chunk + 1] - start) as usize];
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(&mut compressed)?;
+        let chunk_start = chunk as u64 * self.chunk_size;
+        let len = self.chunk_size.min(self.len - chunk_start) as usize;
+        self.current = self.compression.decompress(&compressed, len)?;
+        self.current_chunk = Some(chunk);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for CompressedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk = (self.position / self.chunk_size) as usize;
+        self.load_chunk(chunk)?;
+        let offset = (self.position - chunk as u64 * self.chunk_size) as usize;
+        let amount = buf.len().min(self.current.len() - offset);
+        buf[..amount].copy_from_slice(&self.current[offset..offset + amount]);
+        self.position += amount as u64;
+        Ok(amount)
+    }
+}
+
+impl<R: Read + Seek> Seek for CompressedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = resolve_seek(pos, self.position, self.len)?;
+        Ok(self.position)
+    }
+}
+
+/// Writes a compressed file chunk by chunk
+///
+/// The complete chunks are written once. The current chunk, the index and the footer form the tail of
+/// the file, which is written on every flush after the complete chunks, overwriting the previous tail.
+struct CompressedWriter {
+    inner: Box<dyn SeekAndWrite + Send>,
+    compression: Compression,
+    chunk_size: u64,
+    /// Compressed lengths of the complete chunks
+    chunk_lengths: Vec<u64>,
+    /// The offset in the inner file after the complete chunks, where the tail starts
+    tail_start: u64,
+    /// The length of the inner file, which can not be truncated
+    inner_len: u64,
+    /// The uncompressed data of the current chunk
+    buffer: Vec<u8>,
+    /// The uncompressed offset of the current chunk
+    buffer_start: u64,
+    position: u64,
+    /// Whether the file changed since the tail was last written
+    dirty: bool,
+}
+
+impl CompressedWriter {
+    fn new(
+        mut inner: Box<dyn SeekAndWrite + Send>,
+        compression: Compression,
+        chunk_size: u32,
+    ) -> VfsResult<Self> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION, compression.id()])?;
+        inner.write_all(&chunk_size.to_le_bytes())?;
+        Ok(CompressedWriter {
+            inner,
+            compression,
+            chunk_size: chunk_size as u64,
+            chunk_lengths: vec![],
+            tail_start: HEADER_LEN,
+            inner_len: HEADER_LEN,
+            buffer: vec![],
+            buffer_start: 0,
+            position: 0,
+            dirty: true,
+        })
+    }
+
+    fn write_compressed_chunk(&mut self, compressed: &[u8]) -> io::Result<()> {
+        self.inner.write_all(compressed)?;
+        self.chunk_lengths.push(compressed.len() as u64);
+        self.tail_start += compressed.len() as u64;
+        self.inner_len = self.inner_len.max(self.tail_start);
+        self.buffer_start += self.chunk_size;
+        Ok(())
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        let compressed = self.compression.compress(&self.buffer)?;
+        self.write_compressed_chunk(&compressed)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes the current chunk, the index and the footer after the complete chunks
+    ///
+    /// This makes the data written so far readable, following writes continue the current chunk.
+    fn write_tail(&mut self) -> io::Result<()> {
+        let len = self.buffer_start + self.buffer.len() as u64;
+        let mut tail = vec![];
+        let mut chunk_count = self.chunk_lengths.len() as u64;
+        if !self.buffer.is_empty() {
+            tail = self.compression.compress(&self.buffer)?;
+            chunk_count += 1;
+        }
+        // A tail shorter than the previous one is padded before the index, to end with the footer
+        let index_len = chunk_count * 8;
+        let index_offset = (self.tail_start + tail.len() as u64)
+            .max(self.inner_len.saturating_sub(index_len + FOOTER_LEN));
+        let current_len = tail.len() as u64;
+        tail.resize((index_offset - self.tail_start) as usize, 0);
+        for chunk_len in &self.chunk_lengths {
+            tail.extend_from_slice(&chunk_len.to_le_bytes());
+        }
+        if !self.buffer.is_empty() {
+            tail.extend_from_slice(&current_len.to_le_bytes());
+        }
+        tail.extend_from_slice(&index_offset.to_le_bytes());
+        tail.extend_from_slice(&chunk_count.to_le_bytes());
+        tail.extend_from_slice(&len.to_le_bytes());
+        tail.extend_from_slice(MAGIC);
+
+        self.inner.seek(SeekFrom::Start(self.tail_start))?;
+        self.inner.write_all(&tail)?;
+        self.inner_len = self.tail_start + tail.len() as u64;
+        self.inner.seek(SeekFrom::Start(self.tail_start))?;
+        self.inner.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Write for CompressedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.dirty = true;
+        // Complete all chunks before the current position, filling gaps after seeking with zeros
+        while self.position >= self.buffer_start + self.chunk_size {
+            self.buffer.resize(self.chunk_size as usize, 0);
+            self.flush_chunk()?;
+        }
+        let offset = (self.position - self.buffer_start) as usize;
+        let amount = buf.len().min(self.chunk_size as usize - offset);
+        if self.buffer.len() < offset + amount {
+            self.buffer.resize(offset + amount, 0);
+        }
+        self.buffer[offset..offset + amount].copy_from_slice(&buf[..amount]);
+        self.position += amount as u64;
+        if self.buffer.len() as u64 == self.chunk_size
+            && self.position == self.buffer_start + self.chunk_size
+        {
+            self.flush_chunk()?;
+        }
+        Ok(amount)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.dirty {
+            true => self.write_tail(),
+            false => self.inner.flush(),
+        }
+    }
+}
+
+impl Seek for CompressedWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.buffer_start + self.buffer.len() as u64;
+        let target = resolve_seek(pos, self.position, len)?;
+        if target < self.buffer_start {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Seeking back into already compressed chunks is not supported",
+            ));
+        }
+        self.position = target;
+        Ok(self.position)
+    }
+}
+
+impl Drop for CompressedWriter {
+    fn drop(&mut self) {
+        // Errors can not be reported from here, callers wanting to see them flush before dropping
+        if self.dirty {
+            let _ = self.write_tail();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::faulty::{Fault, FaultRule};
+    use crate::{FaultyFS, MemoryFS};
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        CompressedFS::new(memory_root, Compression::Gzip).chunk_size(16)
+    });
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8).collect()
+    }
+
+    fn create_root(compression: Compression) -> VfsResult<(VfsPath, VfsPath)> {
+        let inner: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = CompressedFS::new(inner.clone(), compression)
+            .chunk_size(1000)
+            .into();
+        root.join("data.bin")?
+            .create_file()?
+            .write_all(&sample(10_500))?;
+        Ok((inner, root))
+    }
+
+    #[test]
+    fn roundtrip() -> VfsResult<()> {
+        let (inner, root) = create_root(Compression::Gzip)?;
+        let file = root.join("data.bin")?;
+        assert_eq!(file.metadata()?.len, 10_500);
+        let mut content = vec![];
+        file.open_file()?.read_to_end(&mut content)?;
+        assert_eq!(content, sample(10_500));
+        let mut raw = vec![];
+        inner.join("data.bin")?.open_file()?.read_to_end(&mut raw)?;
+        assert_eq!(&raw[..4], b"VFSZ");
+        Ok(())
+    }
+
+    #[test]
+    fn seekable_reads() -> VfsResult<()> {
+        let (_, root) = create_root(Compression::Gzip)?;
+        let expected = sample(10_500);
+        let mut file = root.join("data.bin")?.open_file()?;
+        let mut buffer = [0; 20];
+        for position in [9_990u64, 0, 4_321, 995] {
+            file.seek(SeekFrom::Start(position))?;
+            file.read_exact(&mut buffer)?;
+            assert_eq!(
+                &buffer[..],
+                &expected[position as usize..position as usize + 20]
+            );
+        }
+        assert_eq!(file.seek(SeekFrom::End(-5))?, 10_495);
+        let mut rest = vec![];
+        file.read_to_end(&mut rest)?;
+        assert_eq!(rest, &expected[10_495..]);
+        assert_eq!(file.seek(SeekFrom::End(10))?, 10_510);
+        assert_eq!(file.read(&mut buffer)?, 0);
+        assert!(file.seek(SeekFrom::Current(-20_000)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn append() -> VfsResult<()> {
+        let (_, root) = create_root(Compression::Gzip)?;
+        let file = root.join("data.bin")?;
+        file.append_file()?.write_all(&sample(1_000))?;
+        let mut expected = sample(10_500);
+        expected.extend(sample(1_000));
+        let mut content = vec![];
+        file.open_file()?.read_to_end(&mut content)?;
+        assert_eq!(content, expected);
+        assert_eq!(file.metadata()?.len, 11_500);
+        Ok(())
+    }
+
+    #[test]
+    fn seeking_while_writing() -> VfsResult<()> {
+        let inner: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = CompressedFS::new(inner, Compression::Gzip)
+            .chunk_size(4)
+            .into();
+        let file = root.join("file.txt")?;
+        {
+            let mut writer = file.create_file()?;
+            writer.write_all(b"abcdef")?;
+            writer.seek(SeekFrom::Current(-1))?;
+            writer.write_all(b"F")?;
+            assert_eq!(
+                writer.seek(SeekFrom::Start(2)).unwrap_err().kind(),
+                io::ErrorKind::Unsupported
+            );
+            writer.seek(SeekFrom::Start(10))?;
+            writer.write_all(b"z")?;
+        }
+        assert_eq!(file.read_to_string()?, "abcdeF\0\0\0\0z");
+        Ok(())
+    }
+
+    #[test]
+    fn flush_makes_data_readable_and_reports_errors() -> VfsResult<()> {
+        let (inner, root) = create_root(Compression::Gzip)?;
+        let path = root.join("flushed.bin")?;
+        let mut file = path.create_file()?;
+        let content = sample(2_500);
+        file.write_all(&content[..1_500])?;
+        file.flush()?;
+        assert_eq!(path.metadata()?.len, 1_500);
+        let mut read = vec![];
+        path.open_file()?.read_to_end(&mut read)?;
+        assert_eq!(read, &content[..1_500]);
+
+        file.write_all(&content[1_500..2_400])?;
+        file.flush()?;
+        // Better compressible data makes the tail shorter than the flushed one, which is padded
+        let raw_len = inner.join("flushed.bin")?.metadata()?.len;
+        file.seek(SeekFrom::Start(2_000))?;
+        file.write_all(&[0; 400])?;
+        file.flush()?;
+        assert_eq!(inner.join("flushed.bin")?.metadata()?.len, raw_len);
+        file.seek(SeekFrom::Start(2_000))?;
+        file.write_all(&content[2_000..])?;
+        drop(file);
+        let mut read = vec![];
+        path.open_file()?.read_to_end(&mut read)?;
+        assert_eq!(read, content);
+
+        let mut writer = io::BufWriter::with_capacity(7, path.append_file()?);
+        writeln!(writer, "line")?;
+        writer.flush()?;
+        writeln!(writer, "another line")?;
+        drop(writer);
+        let mut read = vec![];
+        path.open_file()?.read_to_end(&mut read)?;
+        assert_eq!(&read[2_500..], b"line\nanother line\n");
+
+        // The header fits, the compressed chunk does not
+        let full: VfsPath = FaultyFS::new(inner)
+            .with_rule(FaultRule::new(Fault::WriteLimit(12)))
+            .into();
+        let root: VfsPath = CompressedFS::new(full, Compression::Gzip).into();
+        let mut file = root.join("full.bin")?.create_file()?;
+        file.write_all(&sample(100))?;
+        assert!(file.flush().is_err());
+        drop(file);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_uncompressed_files() -> VfsResult<()> {
+        let inner: VfsPath = MemoryFS::new().into();
+        inner
+            .join("plain.txt")?
+            .create_file()?
+            .write_all(b"Not compressed at all, but long enough for a footer")?;
+        let root: VfsPath = CompressedFS::new(inner, Compression::Gzip).into();
+        let error = root.join("plain.txt")?.open_file().map(|_| ()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not open file for '/plain.txt': FileSystem error: Not a compressed file"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "compressed-fs-zstd")]
+    #[test]
+    fn zstd() -> VfsResult<()> {
+        let (inner, root) = create_root(Compression::Zstd)?;
+        let mut content = vec![];
+        root.join("data.bin")?
+            .open_file()?
+            .read_to_end(&mut content)?;
+        assert_eq!(content, sample(10_500));
+
+        // Files are readable regardless of the compression configured for new files
+        let gzip_root: VfsPath = CompressedFS::new(inner, Compression::Gzip).into();
+        assert_eq!(gzip_root.join("data.bin")?.metadata()?.len, 10_500);
+        Ok(())
+    }
+}
@@ -0,0 +1,945 @@
+//! A filesystem wrapper encrypting all files
+
+use crate::error::VfsErrorKind;
+use crate::impls::seek::resolve_seek;
+use crate::{
+    FileSystem, SeekAndRead, SeekAndWrite, VfsError, VfsFileType, VfsMetadata, VfsPath, VfsResult,
+};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use sha2::{Digest, Sha256};
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+const MAGIC: &[u8; 4] = b"VFSE";
+const VERSION: u8 = 1;
+/// Magic, version, chunk size and nonce prefix
+const HEADER_LEN: u64 = 28;
+const NONCE_PREFIX_LEN: usize = 19;
+const TAG_LEN: u64 = 16;
+/// The number of times the last chunk was sealed, stored after it
+const SEAL_LEN: u64 = 4;
+
+fn integrity_violation() -> VfsError {
+    VfsErrorKind::IntegrityViolation.into()
+}
+
+/// Wraps an integrity violation for returning it from file handles, it is unwrapped again when converted to a [`VfsError`]
+fn integrity_violation_io() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, integrity_violation())
+}
+
+struct Keys {
+    content: XChaCha20Poly1305,
+    names: ChaCha20Poly1305,
+    name_nonce_key: [u8; 32],
+}
+
+impl Keys {
+    fn new(key: &[u8; 32]) -> Self {
+        let derive = |purpose: &str| -> [u8; 32] {
+            let mut hasher = Sha256::new();
+            hasher.update(b"vfs-encrypted-fs ");
+            hasher.update(purpose.as_bytes());
+            hasher.update(key);
+            hasher.finalize().into()
+        };
+        Keys {
+            content: XChaCha20Poly1305::new(&derive("content").into()),
+            names: ChaCha20Poly1305::new(&derive("names").into()),
+            name_nonce_key: derive("name nonces"),
+        }
+    }
+
+    /// Encrypts a file name deterministically, so that it can be looked up again
+    fn encrypt_name(&self, name: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.name_nonce_key);
+        hasher.update(name.as_bytes());
+        let nonce = &hasher.finalize()[..12];
+        let ciphertext = self
+            .names
+            .encrypt(nonce.into(), name.as_bytes())
+            .expect("Encrypting a file name cannot fail");
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(ciphertext);
+        base32::encode(&encrypted)
+    }
+
+    fn decrypt_name(&self, encrypted: &str) -> VfsResult<String> {
+        let encrypted = base32::decode(encrypted).ok_or_else(integrity_violation)?;
+        if encrypted.len() < 12 {
+            return Err(integrity_violation());
+        }
+        let (nonce, ciphertext) = encrypted.split_at(12);
+        let name = self
+            .names
+            .decrypt(nonce.into(), ciphertext)
+            .map_err(|_| integrity_violation())?;
+        String::from_utf8(name).map_err(|_| integrity_violation())
+    }
+}
+
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Keys { .. }")
+    }
+}
+
+/// A view of the directory tree at a particular path of another filesystem, encrypting all files
+///
+/// Files are encrypted with XChaCha20-Poly1305 using a random nonce per file. They are split into chunks
+/// which are authenticated separately, so seeking in files opened with `open_file()` only decrypts the
+/// chunk containing the new position. Modified, reordered or truncated data is reported as
+/// [`VfsErrorKind::IntegrityViolation`] instead of returning garbage.
+///
+/// File and directory names are only encrypted if enabled with [`EncryptedFS::encrypt_file_names()`].
+/// Name encryption is deterministic, so equal names are recognizable in the encrypted tree.
+/// The directory structure, timestamps and approximate file sizes are not hidden.
+///
+/// Files being written are encrypted chunk by chunk. `flush()` makes the data written so far readable by
+/// sealing the current chunk as the last one, writing can continue afterwards. Every seal of a chunk uses a
+/// nonce of its own. This is done as well when the handle is dropped, but errors are ignored then, call
+/// `flush()` before dropping it to see them.
+///
+/// **Seeking while writing is limited:** writers can only seek within the current chunk or forward,
+/// seeking back into complete chunks fails with [`std::io::ErrorKind::Unsupported`], as they can not
+/// be encrypted again without reusing their nonce.
+///
+/// Requires the `encrypted-fs` feature.
+///
+/// ```
+/// # use vfs::{EncryptedFS, MemoryFS, VfsError, VfsPath};
+/// # use std::io::Read;
+/// let key = [7; 32]; // Use a randomly generated or derived key
+/// let inner: VfsPath = MemoryFS::new().into();
+/// let root: VfsPath = EncryptedFS::new(inner.clone(), key)
+///     .encrypt_file_names(true)
+///     .into();
+///
+/// root.join("secret.txt")?.create_file()?.write_all(b"Attack at dawn")?;
+/// assert_eq!(root.join("secret.txt")?.read_to_string()?, "Attack at dawn");
+///
+/// let stored = inner.read_dir()?.next().unwrap();
+/// assert_ne!(stored.filename(), "secret.txt");
+/// let mut stored_data = vec![];
+/// stored.open_file()?.read_to_end(&mut stored_data)?;
+/// assert!(!stored_data.windows(4).any(|window| window == b"dawn"));
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Clone)]
+pub struct EncryptedFS {
+    root: VfsPath,
+    keys: Arc<Keys>,
+    chunk_size: u32,
+    encrypt_file_names: bool,
+}
+
+impl fmt::Debug for EncryptedFS {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFS")
+            .field("root", &self.root)
+            .field("chunk_size", &self.chunk_size)
+            .field("encrypt_file_names", &self.encrypt_file_names)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptedFS {
+    /// Create a new encrypting FileSystem with its root at the given virtual path
+    ///
+    /// The key should be generated randomly or derived from a password with a proper key derivation function.
+    /// New files are encrypted in chunks of 64 KiB.
+    pub fn new(root: VfsPath, key: [u8; 32]) -> Self {
+        EncryptedFS {
+            root,
+            keys: Arc::new(Keys::new(&key)),
+            chunk_size: 64 * 1024,
+            encrypt_file_names: false,
+        }
+    }
+
+    /// Sets the size of the separately authenticated chunks of new files
+    ///
+    /// Smaller chunks make seeking cheaper, but add 16 bytes of overhead each.
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        assert!(chunk_size > 0, "Chunk size must not be zero");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets whether file and directory names are encrypted as well
+    ///
+    /// This must match the setting the existing files were written with.
+    pub fn encrypt_file_names(mut self, encrypt_file_names: bool) -> Self {
+        self.encrypt_file_names = encrypt_file_names;
+        self
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        if !self.encrypt_file_names {
+            return self.root.join(&path[1..]);
+        }
+        let mut result = self.root.clone();
+        for component in path[1..].split('/') {
+            if component.is_empty() || component == "." || component == ".." {
+                return Err(VfsErrorKind::InvalidPath.into());
+            }
+            result = result.join(self.keys.encrypt_name(component))?;
+        }
+        Ok(result)
+    }
+}
+
+impl FileSystem for EncryptedFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let entries = self.path(path)?.read_dir()?;
+        if !self.encrypt_file_names {
+            return Ok(Box::new(entries.map(|path| path.filename())));
+        }
+        let names = entries
+            .map(|path| self.keys.decrypt_name(&path.filename()))
+            .collect::<VfsResult<Vec<_>>>()?;
+        Ok(Box::new(names.into_iter()))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.create_dir()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let file = self.path(path)?.open_file()?;
+        Ok(Box::new(EncryptedReader::new(file, self.keys.clone())?))
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.path(path)?.create_file()?;
+        Ok(Box::new(EncryptedWriter::new(
+            file,
+            self.keys.clone(),
+            self.chunk_size,
+        )?))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        // Chunks must never be encrypted twice with the same nonce, so the file is re-encrypted with a new one
+        let inner = self.path(path)?;
+        let mut raw = vec![];
+        inner.open_file()?.read_to_end(&mut raw)?;
+        let mut reader = EncryptedReader::new(io::Cursor::new(raw), self.keys.clone())?;
+        let chunk_size = reader.chunk_size as u32;
+        let mut writer = EncryptedWriter::new(inner.create_file()?, self.keys.clone(), chunk_size)?;
+        io::copy(&mut reader, &mut writer)?;
+        Ok(Box::new(writer))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let mut metadata = self.path(path)?.metadata()?;
+        if metadata.file_type == VfsFileType::File {
+            let mut header = [0; HEADER_LEN as usize];
+            self.path(path)?.open_file()?.read_exact(&mut header)?;
+            let (chunk_size, _) = parse_header(&header)?;
+            metadata.len = Layout::new(metadata.len, chunk_size)?.len;
+        }
+        Ok(metadata)
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_creation_time(time)
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_modification_time(time)
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_access_time(time)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.path(path)
+            .map(|path| path.exists())
+            .unwrap_or(Ok(false))
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.remove_file()
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.remove_dir()
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.copy_file(&self.path(dest)?)
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_file(&self.path(dest)?)
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_dir(&self.path(dest)?)
+    }
+}
+
+/// Returns the chunk size and nonce prefix of a file header
+fn parse_header(header: &[u8; HEADER_LEN as usize]) -> VfsResult<(u64, [u8; NONCE_PREFIX_LEN])> {
+    if &header[..4] != MAGIC || header[4] != VERSION {
+        return Err(integrity_violation());
+    }
+    let chunk_size = u32::from_le_bytes(header[5..9].try_into().expect("4 bytes")) as u64;
+    if chunk_size == 0 {
+        return Err(integrity_violation());
+    }
+    Ok((chunk_size, header[9..].try_into().expect("19 bytes")))
+}
+
+/// Returns the nonce of a chunk, the last chunk is distinguished by the number of its seal
+fn chunk_nonce(
+    prefix: &[u8; NONCE_PREFIX_LEN],
+    chunk: u64,
+    seal: Option<u32>,
+) -> io::Result<[u8; 24]> {
+    let chunk = u32::try_from(chunk)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Encrypted file too large"))?;
+    let mut nonce = [0; 24];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..23].copy_from_slice(&chunk.to_be_bytes());
+    if let Some(seal) = seal {
+        for (byte, seal_byte) in nonce.iter_mut().zip(seal.to_be_bytes()) {
+            *byte ^= seal_byte;
+        }
+        nonce[23] = 1;
+    }
+    Ok(nonce)
+}
+
+/// The chunks of an encrypted file
+///
+/// All chunks but the last one contain exactly `chunk_size` bytes, the last one contains less and is
+/// followed by the number of its seal.
+struct Layout {
+    chunk_count: u64,
+    len: u64,
+}
+
+impl Layout {
+    fn new(file_len: u64, chunk_size: u64) -> VfsResult<Self> {
+        let body_len = file_len
+            .checked_sub(HEADER_LEN + SEAL_LEN)
+            .ok_or_else(integrity_violation)?;
+        let stored_chunk_size = chunk_size + TAG_LEN;
+        if body_len % stored_chunk_size < TAG_LEN {
+            return Err(integrity_violation());
+        }
+        let chunk_count = body_len / stored_chunk_size + 1;
+        Ok(Layout {
+            chunk_count,
+            len: body_len - chunk_count * TAG_LEN,
+        })
+    }
+}
+
+struct EncryptedReader<R> {
+    inner: R,
+    keys: Arc<Keys>,
+    header: [u8; HEADER_LEN as usize],
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunk_size: u64,
+    layout: Layout,
+    /// The number of the seal of the last chunk
+    seal: u32,
+    position: u64,
+    current_chunk: Option<u64>,
+    current: Vec<u8>,
+}
+
+impl<R: Read + Seek> EncryptedReader<R> {
+    fn new(mut inner: R, keys: Arc<Keys>) -> VfsResult<Self> {
+        let file_len = inner.seek(SeekFrom::End(0))?;
+        if file_len < HEADER_LEN + SEAL_LEN {
+            return Err(integrity_violation());
+        }
+        let mut header = [0; HEADER_LEN as usize];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut header)?;
+        let (chunk_size, nonce_prefix) = parse_header(&header)?;
+        let layout = Layout::new(file_len, chunk_size)?;
+        let mut seal = [0; SEAL_LEN as usize];
+        inner.seek(SeekFrom::Start(file_len - SEAL_LEN))?;
+        inner.read_exact(&mut seal)?;
+        let mut reader = EncryptedReader {
+            inner,
+            keys,
+            header,
+            nonce_prefix,
+            chunk_size,
+            layout,
+            seal: u32::from_le_bytes(seal),
+            position: 0,
+            current_chunk: None,
+            current: vec![],
+        };
+        // Authenticating the last chunk detects truncated files and makes the length trustworthy
+        reader.load_chunk(reader.layout.chunk_count - 1)?;
+        Ok(reader)
+    }
+
+    fn load_chunk(&mut self, chunk: u64) -> io::Result<()> {
+        if self.current_chunk == Some(chunk) {
+            return Ok(());
+        }
+        let last = chunk == self.layout.chunk_count - 1;
+        let stored_len = if last {
+            self.layout.len - chunk * self.chunk_size + TAG_LEN
+        } else {
+            self.chunk_size + TAG_LEN
+        };
+        let mut ciphertext = vec![0; stored_len as usize];
+        self.inner.seek(SeekFrom::Start(
+            HEADER_LEN + chunk * (self.chunk_size + TAG_LEN),
+        ))?;
+        self.inner.read_exact(&mut ciphertext)?;
+        let nonce = chunk_nonce(&self.nonce_prefix, chunk, last.then_some(self.seal))?;
+        let payload = Payload {
+            msg: &ciphertext,
+            aad: &self.header,
+        };
+        self.current = self
+            .keys
+            .content
+            .decrypt((&nonce).into(), payload)
+            .map_err(|_| integrity_violation_io())?;
+        self.current_chunk = Some(chunk);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for EncryptedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.layout.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk = self.position / self.chunk_size;
+        self.load_chunk(chunk)?;
+        let offset = (self.position - chunk * self.chunk_size) as usize;
+        let amount = buf.len().min(self.current.len() - offset);
+        buf[..amount].copy_from_slice(&self.current[offset..offset + amount]);
+        self.position += amount as u64;
+        Ok(amount)
+    }
+}
+
+impl<R: Read + Seek> Seek for EncryptedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = resolve_seek(pos, self.position, self.layout.len)?;
+        Ok(self.position)
+    }
+}
+
+/// Writes an encrypted file chunk by chunk
+///
+/// The complete chunks are written once. The current chunk is sealed as the last one on every flush,
+/// overwriting its previous seal.
+struct EncryptedWriter {
+    inner: Box<dyn SeekAndWrite + Send>,
+    keys: Arc<Keys>,
+    header: [u8; HEADER_LEN as usize],
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunk_size: u64,
+    /// The index of the current chunk
+    chunk: u64,
+    /// The plain data of the current chunk
+    buffer: Vec<u8>,
+    position: u64,
+    /// The number of seals of last chunks so far, each needs a nonce of its own
+    seals: u32,
+    /// Whether the file changed since the current chunk was last sealed
+    dirty: bool,
+}
+
+impl EncryptedWriter {
+    fn new(
+        mut inner: Box<dyn SeekAndWrite + Send>,
+        keys: Arc<Keys>,
+        chunk_size: u32,
+    ) -> VfsResult<Self> {
+        let mut nonce_prefix = [0; NONCE_PREFIX_LEN];
+        getrandom::getrandom(&mut nonce_prefix)
+            .map_err(|err| VfsErrorKind::Other(format!("Could not generate nonce: {err}")))?;
+        let mut header = [0; HEADER_LEN as usize];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = VERSION;
+        header[5..9].copy_from_slice(&chunk_size.to_le_bytes());
+        header[9..].copy_from_slice(&nonce_prefix);
+        inner.write_all(&header)?;
+        Ok(EncryptedWriter {
+            inner,
+            keys,
+            header,
+            nonce_prefix,
+            chunk_size: chunk_size as u64,
+            chunk: 0,
+            buffer: vec![],
+            position: 0,
+            seals: 0,
+            dirty: true,
+        })
+    }
+
+    fn buffer_start(&self) -> u64 {
+        self.chunk * self.chunk_size
+    }
+
+    fn encrypt_chunk(&self, seal: Option<u32>) -> io::Result<Vec<u8>> {
+        let nonce = chunk_nonce(&self.nonce_prefix, self.chunk, seal)?;
+        let payload = Payload {
+            msg: &self.buffer,
+            aad: &self.header,
+        };
+        self.keys
+            .content
+            .encrypt((&nonce).into(), payload)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Encryption failed"))
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        let ciphertext = self.encrypt_chunk(None)?;
+        self.inner.write_all(&ciphertext)?;
+        self.buffer.clear();
+        self.chunk += 1;
+        Ok(())
+    }
+
+    /// Seals the current chunk as the last one, followed by the number of the seal
+    ///
+    /// This makes the data written so far readable, following writes continue the current chunk.
+    fn seal(&mut self) -> io::Result<()> {
+        // The last chunk must contain less than a full chunk
+        if self.buffer.len() as u64 == self.chunk_size {
+            self.flush_chunk()?;
+        }
+        let seal = self.seals;
+        self.seals = seal.checked_add(1).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Other, "Encrypted file flushed too often")
+        })?;
+        let mut sealed = self.encrypt_chunk(Some(seal))?;
+        sealed.extend_from_slice(&seal.to_le_bytes());
+        let start = HEADER_LEN + self.chunk * (self.chunk_size + TAG_LEN);
+        self.inner.write_all(&sealed)?;
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Write for EncryptedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.dirty = true;
+        // Complete all chunks before the current position, filling gaps after seeking with zeros
+        while self.position >= self.buffer_start() + self.chunk_size {
+            self.buffer.resize(self.chunk_size as usize, 0);
+            self.flush_chunk()?;
+        }
+        let offset = (self.position - self.buffer_start()) as usize;
+        let amount = buf.len().min(self.chunk_size as usize - offset);
+        if self.buffer.len() < offset + amount {
+            self.buffer.resize(offset + amount, 0);
+        }
+        self.buffer[offset..offset + amount].copy_from_slice(&buf[..amount]);
+        self.position += amount as u64;
+        // The last chunk must contain less than a full chunk, so full chunks can always be written
+        if self.buffer.len() as u64 == self.chunk_size
+            && self.position == self.buffer_start() + self.chunk_size
+        {
+            self.flush_chunk()?;
+        }
+        Ok(amount)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.dirty {
+            true => self.seal(),
+            false => self.inner.flush(),
+        }
+    }
+}
+
+impl Seek for EncryptedWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.buffer_start() + self.buffer.len() as u64;
+        let target = resolve_seek(pos, self.position, len)?;
+        if target < self.buffer_start() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Seeking back into already encrypted chunks is not supported",
+            ));
+        }
+        self.position = target;
+        Ok(self.position)
+    }
+}
+
+impl Drop for EncryptedWriter {
+    fn drop(&mut self) {
+        // Errors can not be reported from here, callers wanting to see them flush before dropping
+        if self.dirty {
+            let _ = self.seal();
+        }
+    }
+}
+
+/// Lowercase base32 without padding, suitable for case-insensitive file systems
+mod base32 {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+    pub(super) fn encode(data: &[u8]) -> String {
+        let mut result = String::with_capacity((data.len() * 8 + 4) / 5);
+        let mut buffer = 0u16;
+        let mut bits = 0;
+        for byte in data {
+            buffer = (buffer << 8) | *byte as u16;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                result.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+            }
+        }
+        if bits > 0 {
+            result.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+        }
+        result
+    }
+
+    pub(super) fn decode(text: &str) -> Option<Vec<u8>> {
+        let mut result = Vec::with_capacity(text.len() * 5 / 8);
+        let mut buffer = 0u16;
+        let mut bits = 0;
+        for character in text.bytes() {
+            let value = ALPHABET.iter().position(|c| *c == character)? as u16;
+            buffer = (buffer << 5) | value;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                result.push((buffer >> bits) as u8);
+            }
+        }
+        // Leftover bits must be zero padding
+        if bits >= 5 || buffer & ((1 << bits) - 1) != 0 {
+            return None;
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::faulty::{Fault, FaultRule};
+    use crate::{FaultyFS, MemoryFS};
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        EncryptedFS::new(memory_root, [42; 32]).chunk_size(16)
+    });
+
+    mod encrypted_names {
+        use super::*;
+        test_vfs!({
+            let memory_root: VfsPath = MemoryFS::new().into();
+            EncryptedFS::new(memory_root, [42; 32]).encrypt_file_names(true)
+        });
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8).collect()
+    }
+
+    fn create_root() -> VfsResult<(VfsPath, VfsPath)> {
+        let inner: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = EncryptedFS::new(inner.clone(), [1; 32])
+            .chunk_size(1000)
+            .into();
+        root.join("data.bin")?
+            .create_file()?
+            .write_all(&sample(10_500))?;
+        Ok((inner, root))
+    }
+
+    fn raw_content(path: &VfsPath) -> VfsResult<Vec<u8>> {
+        let mut raw = vec![];
+        path.open_file()?.read_to_end(&mut raw)?;
+        Ok(raw)
+    }
+
+    fn read_content(path: &VfsPath) -> VfsResult<Vec<u8>> {
+        let mut content = vec![];
+        path.open_file()?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    #[test]
+    fn roundtrip() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        let file = root.join("data.bin")?;
+        assert_eq!(file.metadata()?.len, 10_500);
+        assert_eq!(read_content(&file)?, sample(10_500));
+        let raw = raw_content(&inner.join("data.bin")?)?;
+        assert_eq!(
+            raw.len() as u64,
+            HEADER_LEN + 10_500 + 11 * TAG_LEN + SEAL_LEN
+        );
+        assert!(!raw.windows(100).any(|window| window == &sample(100)[..]));
+        Ok(())
+    }
+
+    #[test]
+    fn files_use_different_nonces() -> VfsResult<()> {
+        let inner: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = EncryptedFS::new(inner.clone(), [1; 32]).into();
+        root.join("a.txt")?.create_file()?.write_all(b"same")?;
+        root.join("b.txt")?.create_file()?.write_all(b"same")?;
+        assert_ne!(
+            raw_content(&inner.join("a.txt")?)?,
+            raw_content(&inner.join("b.txt")?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn seekable_reads() -> VfsResult<()> {
+        let (_, root) = create_root()?;
+        let expected = sample(10_500);
+        let mut file = root.join("data.bin")?.open_file()?;
+        let mut buffer = [0; 20];
+        for position in [9_990u64, 0, 4_321, 995] {
+            file.seek(SeekFrom::Start(position))?;
+            file.read_exact(&mut buffer)?;
+            let position = position as usize;
+            assert_eq!(&buffer[..], &expected[position..position + 20]);
+        }
+        assert_eq!(file.seek(SeekFrom::End(-5))?, 10_495);
+        let mut rest = vec![];
+        file.read_to_end(&mut rest)?;
+        assert_eq!(rest, &expected[10_495..]);
+        assert!(file.seek(SeekFrom::Current(-20_000)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn append() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        let header_before = raw_content(&inner.join("data.bin")?)?[..HEADER_LEN as usize].to_vec();
+        let file = root.join("data.bin")?;
+        file.append_file()?.write_all(&sample(1_000))?;
+        let mut expected = sample(10_500);
+        expected.extend(sample(1_000));
+        assert_eq!(read_content(&file)?, expected);
+        assert_eq!(file.metadata()?.len, 11_500);
+        let header_after = raw_content(&inner.join("data.bin")?)?[..HEADER_LEN as usize].to_vec();
+        assert_ne!(header_before, header_after);
+        Ok(())
+    }
+
+    fn tamper(inner: &VfsPath, tamper: impl FnOnce(&mut Vec<u8>)) -> VfsResult<()> {
+        let file = inner.join("data.bin")?;
+        let mut raw = raw_content(&file)?;
+        tamper(&mut raw);
+        file.create_file()?.write_all(&raw)?;
+        Ok(())
+    }
+
+    fn assert_integrity_violation<T>(result: VfsResult<T>) {
+        match result {
+            Err(err) => assert!(
+                matches!(err.kind(), VfsErrorKind::IntegrityViolation),
+                "Unexpected error: {err}"
+            ),
+            Ok(_) => panic!("Tampering was not detected"),
+        }
+    }
+
+    #[test]
+    fn flush_makes_data_readable_and_reports_errors() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        let path = root.join("flushed.bin")?;
+        let mut file = path.create_file()?;
+        let content = sample(2_500);
+        file.write_all(&content[..1_500])?;
+        file.flush()?;
+        assert_eq!(path.metadata()?.len, 1_500);
+        assert_eq!(read_content(&path)?, &content[..1_500]);
+
+        // Writes after a flush continue the file, also within the sealed chunk
+        file.write_all(&content[1_500..1_900])?;
+        file.seek(SeekFrom::Start(1_100))?;
+        file.write_all(&content[1_100..1_200])?;
+        file.flush()?;
+        assert_eq!(read_content(&path)?, &content[..1_900]);
+        file.seek(SeekFrom::Start(1_900))?;
+        file.write_all(&content[1_900..2_000])?;
+        file.flush()?;
+        assert_eq!(read_content(&path)?, &content[..2_000]);
+        assert_eq!(
+            file.seek(SeekFrom::Start(1_999)).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        file.write_all(&content[2_000..])?;
+        drop(file);
+        assert_eq!(read_content(&path)?, content);
+
+        let mut writer = io::BufWriter::with_capacity(7, path.append_file()?);
+        writeln!(writer, "line")?;
+        writer.flush()?;
+        writeln!(writer, "another line")?;
+        drop(writer);
+        assert_eq!(&read_content(&path)?[2_500..], b"line\nanother line\n");
+
+        // The header fits, the encrypted chunk does not
+        let full: VfsPath = FaultyFS::new(inner)
+            .with_rule(FaultRule::new(Fault::WriteLimit(HEADER_LEN + 8)))
+            .into();
+        let root: VfsPath = EncryptedFS::new(full, [1; 32]).into();
+        let mut file = root.join("full.bin")?.create_file()?;
+        file.write_all(&sample(100))?;
+        assert!(file.flush().is_err());
+        drop(file);
+        Ok(())
+    }
+
+    #[test]
+    fn resealing_does_not_reuse_nonces() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        let mut file = root.join("sealed.bin")?.create_file()?;
+        file.write_all(b"aaaa")?;
+        file.flush()?;
+        let first = raw_content(&inner.join("sealed.bin")?)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(b"bbbb")?;
+        file.flush()?;
+        let second = raw_content(&inner.join("sealed.bin")?)?;
+        assert_eq!(first.len(), second.len());
+        let keystream_reused = (0..4).all(|index| {
+            let offset = HEADER_LEN as usize + index;
+            first[offset] ^ second[offset] == b'a' ^ b'b'
+        });
+        assert!(!keystream_reused);
+        assert_eq!(&second[second.len() - 4..], &1u32.to_le_bytes());
+        drop(file);
+        assert_eq!(root.join("sealed.bin")?.read_to_string()?, "bbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn detects_modified_chunks() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        tamper(&inner, |raw| raw[HEADER_LEN as usize + 1500] ^= 1)?;
+        let file = root.join("data.bin")?;
+        let mut reader = file.open_file()?;
+        let mut buffer = [0; 10];
+        reader.read_exact(&mut buffer)?;
+        reader.seek(SeekFrom::Start(1200))?;
+        assert_integrity_violation(reader.read_exact(&mut buffer).map_err(VfsError::from));
+        assert_integrity_violation(file.read_to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn detects_swapped_chunks() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        tamper(&inner, |raw| {
+            let (first, second) = raw[HEADER_LEN as usize..].split_at_mut(1016);
+            first.swap_with_slice(&mut second[..1016]);
+        })?;
+        assert_integrity_violation(read_content(&root.join("data.bin")?));
+        Ok(())
+    }
+
+    #[test]
+    fn detects_truncation() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        tamper(&inner, |raw| raw.truncate(raw.len() - 516))?;
+        assert_integrity_violation(root.join("data.bin")?.open_file());
+        tamper(&inner, |raw| raw.truncate(raw.len() - 10))?;
+        assert_integrity_violation(root.join("data.bin")?.open_file());
+        Ok(())
+    }
+
+    #[test]
+    fn detects_modified_header() -> VfsResult<()> {
+        let (inner, root) = create_root()?;
+        tamper(&inner, |raw| raw[20] ^= 1)?;
+        assert_integrity_violation(root.join("data.bin")?.open_file());
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_key() -> VfsResult<()> {
+        let (inner, _) = create_root()?;
+        let root: VfsPath = EncryptedFS::new(inner, [2; 32]).into();
+        assert_integrity_violation(root.join("data.bin")?.open_file());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_names() -> VfsResult<()> {
+        let inner: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = EncryptedFS::new(inner.clone(), [1; 32])
+            .encrypt_file_names(true)
+            .into();
+        root.join("documents/taxes")?.create_dir_all()?;
+        root.join("documents/taxes/2024.txt")?
+            .create_file()?
+            .write_all(b"refund")?;
+
+        let stored: Vec<String> = inner
+            .walk_dir()?
+            .map(|path| path.map(|path| path.as_str().to_string()))
+            .collect::<VfsResult<_>>()?;
+        assert_eq!(stored.len(), 3);
+        assert!(stored.iter().all(|path| !path.contains("documents")
+            && !path.contains("taxes")
+            && !path.contains("2024")));
+
+        let names: Vec<String> = root
+            .join("documents/taxes")?
+            .read_dir()?
+            .map(|path| path.filename())
+            .collect();
+        assert_eq!(names, vec!["2024.txt"]);
+        assert_eq!(
+            root.join("documents/taxes/2024.txt")?.read_to_string()?,
+            "refund"
+        );
+
+        inner.join("stray.txt")?.create_file()?;
+        assert_integrity_violation(root.read_dir());
+        Ok(())
+    }
+
+    #[test]
+    fn base32_roundtrip() {
+        for len in 0..20 {
+            let data = sample(len);
+            let encoded = base32::encode(&data);
+            assert!(encoded
+                .bytes()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()));
+            assert_eq!(base32::decode(&encoded), Some(data));
+        }
+        assert_eq!(base32::encode(b"foobar"), "mzxw6ytboi");
+        assert_eq!(base32::decode("mzxw6ytboj"), None);
+        assert_eq!(base32::decode("MZXW6"), None);
+    }
+}
@@ -0,0 +1,554 @@
+//! A filesystem wrapper injecting faults, for testing error handling
+
+use crate::error::VfsErrorKind;
+use crate::glob::GlobPattern;
+use crate::impls::seek::resolve_seek;
+use crate::impls::tracing::TraceOperation;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult};
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// The fault injected by a [`FaultRule`]
+#[derive(Debug)]
+pub enum Fault {
+    /// The call fails with the given error
+    Error(VfsErrorKind),
+    /// The call is delayed by the given duration
+    Latency(Duration),
+    /// Files opened by `open_file()` end after the given number of bytes
+    TruncateRead(u64),
+    /// Each write to files opened by `create_file()` or `append_file()` writes at most the given number of bytes
+    ShortWrite(usize),
+    /// Writing to files opened by `create_file()` or `append_file()` fails after the given number of bytes,
+    /// simulating a full disk
+    ///
+    /// The error has the kind `io::ErrorKind::Other` and the message "No space left on device", as
+    /// `io::ErrorKind::StorageFull` is not available with the minimum supported Rust version.
+    /// Match on the message to detect it.
+    WriteLimit(u64),
+}
+
+/// A rule describing when a [`Fault`] is injected by a [`FaultyFS`]
+///
+/// By default a rule applies to all calls, use the builder methods to narrow it down.
+/// Calls are counted per rule, only counting the calls matching the operations and the pattern.
+#[derive(Debug)]
+pub struct FaultRule {
+    fault: Fault,
+    operations: Vec<TraceOperation>,
+    pattern: Option<GlobPattern>,
+    nth_call: Option<u64>,
+    probability: Option<f64>,
+}
+
+impl FaultRule {
+    /// Creates a rule injecting the given fault into all calls
+    pub fn new(fault: Fault) -> Self {
+        FaultRule {
+            fault,
+            operations: vec![],
+            pattern: None,
+            nth_call: None,
+            probability: None,
+        }
+    }
+
+    /// Only applies to calls of the given operation, can be used several times
+    pub fn on(mut self, operation: TraceOperation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Only applies to paths matching the given `.gitignore`-style glob pattern
+    ///
+    /// For copy and move operations the source path is matched.
+    pub fn matching(mut self, pattern: &str) -> Self {
+        self.pattern = Some(GlobPattern::new(pattern));
+        self
+    }
+
+    /// Only applies to the n-th matching call, starting at 1
+    pub fn nth_call(mut self, n: u64) -> Self {
+        self.nth_call = Some(n);
+        self
+    }
+
+    /// Only applies to matching calls with the given probability between 0.0 and 1.0
+    ///
+    /// The random choices are reproducible for a given seed, see [`FaultyFS::with_seed()`].
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = Some(probability);
+        self
+    }
+
+    fn matches(&self, operation: TraceOperation, path: &str) -> bool {
+        (self.operations.is_empty() || self.operations.contains(&operation))
+            && self
+                .pattern
+                .as_ref()
+                .map_or(true, |pattern| pattern.matches(path, false))
+    }
+}
+
+/// A view of the directory tree at a particular path of another filesystem, injecting faults according to rules
+///
+/// All rules matching a call are applied in the order they were added.
+/// Latencies are applied before the call, the first error fails it.
+///
+/// ```
+/// # use vfs::{FaultyFS, MemoryFS, VfsError, VfsPath};
+/// # use vfs::error::VfsErrorKind;
+/// use vfs::impls::faulty::{Fault, FaultRule};
+/// use vfs::impls::tracing::TraceOperation;
+///
+/// let root: VfsPath = FaultyFS::new(MemoryFS::new().into())
+///     .with_rule(
+///         FaultRule::new(Fault::Error(VfsErrorKind::Other("disk full".into())))
+///             .on(TraceOperation::CreateFile)
+///             .nth_call(2),
+///     )
+///     .into();
+///
+/// assert!(root.join("first.txt")?.create_file().is_ok());
+/// assert!(root.join("second.txt")?.create_file().is_err());
+/// assert!(root.join("third.txt")?.create_file().is_ok());
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug)]
+pub struct FaultyFS {
+    root: VfsPath,
+    rules: Vec<FaultRule>,
+    state: Mutex<FaultState>,
+}
+
+#[derive(Debug)]
+struct FaultState {
+    /// Number of matching calls per rule
+    calls: Vec<u64>,
+    rng: SplitMix64,
+}
+
+/// The handle faults to apply to a file opened by a call
+#[derive(Debug, Default)]
+struct HandleFaults {
+    read_limit: Option<u64>,
+    max_write: Option<usize>,
+    write_limit: Option<u64>,
+}
+
+impl FaultyFS {
+    /// Create a new FileSystem with its root at the given virtual path, without any rules
+    pub fn new(root: VfsPath) -> Self {
+        FaultyFS {
+            root,
+            rules: vec![],
+            state: Mutex::new(FaultState {
+                calls: vec![],
+                rng: SplitMix64(0),
+            }),
+        }
+    }
+
+    /// Adds a rule
+    pub fn with_rule(mut self, rule: FaultRule) -> Self {
+        self.rules.push(rule);
+        self.state.get_mut().unwrap().calls.push(0);
+        self
+    }
+
+    /// Sets the seed for rules applied with a probability, the same seed results in the same failures
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.state.get_mut().unwrap().rng = SplitMix64(seed);
+        self
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+
+    /// Applies the matching rules, returning the faults for the handle returned by the call
+    fn inject(&self, operation: TraceOperation, path: &str) -> VfsResult<HandleFaults> {
+        let mut faults = HandleFaults::default();
+        let mut latency = Duration::ZERO;
+        let mut error = None;
+        {
+            let mut state = self.state.lock().unwrap();
+            for (index, rule) in self.rules.iter().enumerate() {
+                if !rule.matches(operation, path) {
+                    continue;
+                }
+                state.calls[index] += 1;
+                if rule.nth_call.map_or(false, |n| n != state.calls[index]) {
+                    continue;
+                }
+                if let Some(probability) = rule.probability {
+                    if state.rng.next_f64() >= probability {
+                        continue;
+                    }
+                }
+                match &rule.fault {
+                    Fault::Error(kind) => {
+                        error.get_or_insert_with(|| kind.duplicate());
+                    }
+                    Fault::Latency(duration) => latency += *duration,
+                    Fault::TruncateRead(limit) => faults.read_limit = Some(*limit),
+                    Fault::ShortWrite(max_write) => faults.max_write = Some(*max_write),
+                    Fault::WriteLimit(limit) => faults.write_limit = Some(*limit),
+                }
+            }
+        }
+        if !latency.is_zero() {
+            std::thread::sleep(latency);
+        }
+        match error {
+            Some(kind) => Err(kind.into()),
+            None => Ok(faults),
+        }
+    }
+}
+
+impl FileSystem for FaultyFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.inject(TraceOperation::ReadDir, path)?;
+        self.path(path)?
+            .read_dir()
+            .map(|entries| Box::new(entries.map(|path| path.filename())) as Box<_>)
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.inject(TraceOperation::CreateDir, path)?;
+        self.path(path)?.create_dir()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let faults = self.inject(TraceOperation::OpenFile, path)?;
+        let file = self.path(path)?.open_file()?;
+        Ok(match faults.read_limit {
+            Some(cutoff) => Box::new(TruncatedReader {
+                inner: file,
+                cutoff,
+                position: 0,
+            }),
+            None => file,
+        })
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let faults = self.inject(TraceOperation::CreateFile, path)?;
+        let file = self.path(path)?.create_file()?;
+        Ok(faulty_writer(file, faults))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let faults = self.inject(TraceOperation::AppendFile, path)?;
+        let file = self.path(path)?.append_file()?;
+        Ok(faulty_writer(file, faults))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.inject(TraceOperation::Metadata, path)?;
+        self.path(path)?.metadata()
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.inject(TraceOperation::SetCreationTime, path)?;
+        self.path(path)?.set_creation_time(time)
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.inject(TraceOperation::SetModificationTime, path)?;
+        self.path(path)?.set_modification_time(time)
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.inject(TraceOperation::SetAccessTime, path)?;
+        self.path(path)?.set_access_time(time)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.inject(TraceOperation::Exists, path)?;
+        self.path(path)?.exists()
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.inject(TraceOperation::RemoveFile, path)?;
+        self.path(path)?.remove_file()
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.inject(TraceOperation::RemoveDir, path)?;
+        self.path(path)?.remove_dir()
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.inject(TraceOperation::CopyFile, src)?;
+        self.path(src)?.copy_file(&self.path(dest)?)
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.inject(TraceOperation::MoveFile, src)?;
+        self.path(src)?.move_file(&self.path(dest)?)
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.inject(TraceOperation::MoveDir, src)?;
+        self.path(src)?.move_dir(&self.path(dest)?)
+    }
+}
+
+/// The SplitMix64 pseudo random number generator, small and good enough for fault schedules
+#[derive(Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A reader ending at `cutoff`, seeking from the end is relative to the truncated length
+struct TruncatedReader {
+    inner: Box<dyn SeekAndRead + Send>,
+    cutoff: u64,
+    position: u64,
+}
+
+impl Read for TruncatedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.cutoff.saturating_sub(self.position);
+        let len = buf.len().min(remaining.min(usize::MAX as u64) as usize);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for TruncatedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = match pos {
+            SeekFrom::End(_) => self.inner.seek(SeekFrom::End(0))?.min(self.cutoff),
+            _ => self.cutoff,
+        };
+        let target = match resolve_seek(pos, self.position, len) {
+            Ok(target) => target,
+            Err(err) => {
+                // Restore the position before failing like a regular seek would
+                self.inner.seek(SeekFrom::Start(self.position))?;
+                return Err(err);
+            }
+        };
+        self.position = self.inner.seek(SeekFrom::Start(target))?;
+        Ok(self.position)
+    }
+}
+
+fn faulty_writer(
+    file: Box<dyn SeekAndWrite + Send>,
+    faults: HandleFaults,
+) -> Box<dyn SeekAndWrite + Send> {
+    if faults.max_write.is_none() && faults.write_limit.is_none() {
+        return file;
+    }
+    Box::new(FaultyWriter {
+        inner: file,
+        max_write: faults.max_write,
+        remaining: faults.write_limit,
+    })
+}
+
+struct FaultyWriter {
+    inner: Box<dyn SeekAndWrite + Send>,
+    max_write: Option<usize>,
+    remaining: Option<u64>,
+}
+
+impl Write for FaultyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut len = buf.len();
+        if let Some(max_write) = self.max_write {
+            len = len.min(max_write);
+        }
+        if let Some(remaining) = self.remaining {
+            if remaining == 0 && !buf.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "No space left on device",
+                ));
+            }
+            len = len.min(remaining.min(usize::MAX as u64) as usize);
+        }
+        let written = self.inner.write(&buf[..len])?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= written as u64;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Seek for FaultyWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFS;
+    use std::time::Instant;
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        FaultyFS::new(memory_root)
+    });
+
+    fn create_root() -> VfsResult<VfsPath> {
+        let root: VfsPath = MemoryFS::new().into();
+        root.join("data.txt")?
+            .create_file()?
+            .write_all(b"Hello World")?;
+        Ok(root)
+    }
+
+    #[test]
+    fn fails_nth_call() -> VfsResult<()> {
+        let fs = FaultyFS::new(create_root()?).with_rule(
+            FaultRule::new(Fault::Error(VfsErrorKind::NotSupported))
+                .on(TraceOperation::OpenFile)
+                .nth_call(2),
+        );
+        assert!(fs.open_file("/data.txt").is_ok());
+        assert!(matches!(
+            fs.open_file("/data.txt").map(|_| ()).unwrap_err().kind(),
+            VfsErrorKind::NotSupported
+        ));
+        assert!(fs.open_file("/data.txt").is_ok());
+        assert!(fs.metadata("/data.txt").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn fails_matching_paths() -> VfsResult<()> {
+        let root: VfsPath = FaultyFS::new(create_root()?)
+            .with_rule(FaultRule::new(Fault::Error(VfsErrorKind::ReadOnly)).matching("*.lock"))
+            .into();
+        root.join("other.txt")?.create_file()?;
+        root.join("dir")?.create_dir()?;
+        let error = root
+            .join("dir/app.lock")?
+            .create_file()
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::ReadOnly));
+        let error = root
+            .join("app.lock")?
+            .create_file()
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not create file for '/app.lock': The filesystem is read-only"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn truncates_reads() -> VfsResult<()> {
+        let root: VfsPath = FaultyFS::new(create_root()?)
+            .with_rule(FaultRule::new(Fault::TruncateRead(5)))
+            .into();
+        assert_eq!(root.join("data.txt")?.read_to_string()?, "Hello");
+
+        let mut file = root.join("data.txt")?.open_file()?;
+        let mut content = String::new();
+        file.seek(SeekFrom::Start(2))?;
+        file.read_to_string(&mut content)?;
+        assert_eq!(content, "llo");
+        content.clear();
+        file.seek(SeekFrom::Start(1))?;
+        file.read_to_string(&mut content)?;
+        assert_eq!(content, "ello");
+        assert_eq!(file.seek(SeekFrom::End(-2))?, 3);
+        content.clear();
+        file.read_to_string(&mut content)?;
+        assert_eq!(content, "lo");
+        file.seek(SeekFrom::Start(7))?;
+        assert_eq!(file.read(&mut [0; 4])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn short_writes() -> VfsResult<()> {
+        let inner = create_root()?;
+        let root: VfsPath = FaultyFS::new(inner.clone())
+            .with_rule(FaultRule::new(Fault::ShortWrite(3)))
+            .into();
+        let mut file = root.join("short.txt")?.create_file()?;
+        assert_eq!(file.write(b"Hello World")?, 3);
+        // write_all retries until everything is written
+        file.write_all(b"lo World")?;
+        drop(file);
+        assert_eq!(inner.join("short.txt")?.read_to_string()?, "Hello World");
+        Ok(())
+    }
+
+    #[test]
+    fn disk_full() -> VfsResult<()> {
+        let root: VfsPath = FaultyFS::new(create_root()?)
+            .with_rule(FaultRule::new(Fault::WriteLimit(8)).matching("big.bin"))
+            .into();
+        let mut file = root.join("big.bin")?.create_file()?;
+        file.write_all(&[1; 8])?;
+        let error = file.write_all(&[1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert_eq!(error.to_string(), "No space left on device");
+        root.join("small.bin")?.create_file()?.write_all(&[1; 16])?;
+        Ok(())
+    }
+
+    #[test]
+    fn adds_latency() -> VfsResult<()> {
+        let fs = FaultyFS::new(create_root()?).with_rule(
+            FaultRule::new(Fault::Latency(Duration::from_millis(20))).on(TraceOperation::Exists),
+        );
+        let start = Instant::now();
+        assert!(fs.exists("/data.txt")?);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        Ok(())
+    }
+
+    #[test]
+    fn random_failures_are_reproducible() -> VfsResult<()> {
+        fn failures(seed: u64) -> VfsResult<Vec<bool>> {
+            let fs = FaultyFS::new(create_root()?)
+                .with_rule(
+                    FaultRule::new(Fault::Error(VfsErrorKind::NotSupported)).with_probability(0.5),
+                )
+                .with_seed(seed);
+            Ok((0..64).map(|_| fs.metadata("/data.txt").is_err()).collect())
+        }
+        let first = failures(42)?;
+        assert_eq!(first, failures(42)?);
+        assert_ne!(first, failures(43)?);
+        let count = first.iter().filter(|failed| **failed).count();
+        assert!((16..48).contains(&count), "{count} failures");
+        Ok(())
+    }
+}
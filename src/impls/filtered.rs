@@ -0,0 +1,460 @@
+//! A filtered view of another filesystem, hiding paths based on glob patterns
+
+use crate::error::VfsErrorKind;
+use crate::glob::GlobPattern;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsFileType, VfsMetadata, VfsPath, VfsResult};
+
+use std::time::SystemTime;
+
+/// A view of the directory tree at a particular path of another filesystem, exposing only paths passing the filter
+///
+/// Paths are filtered with `.gitignore`-style rules:
+///  * Exclude rules (and rules read via [`FilteredFS::ignore_rules()`]) hide matching files and directories.
+///    Later rules take precedence, rules starting with `!` make previously excluded paths visible again.
+///    Everything below an excluded directory is hidden as well.
+///  * If include patterns are given, only files matching at least one of them are visible.
+///    Include patterns do not apply to directories.
+///
+/// Patterns support `*`, `?`, `**` and character classes. Patterns containing a slash are anchored at the root
+/// of the view, others match the filename at any depth. A trailing slash restricts a pattern to directories.
+///
+/// Hidden paths do not show up in `read_dir()`, do not exist and report `VfsErrorKind::FileNotFound`.
+/// Writing to them is rejected with `VfsErrorKind::Excluded`. Directories can only be moved if all their entries
+/// are visible both before and after the move.
+///
+/// ```
+/// # use vfs::{FilteredFS, MemoryFS, VfsError, VfsPath};
+/// let root: VfsPath = MemoryFS::new().into();
+/// root.join("src")?.create_dir()?;
+/// root.join("src/lib.rs")?.create_file()?;
+/// root.join("target")?.create_dir()?;
+/// root.join("secrets")?.create_dir()?;
+///
+/// let filtered: VfsPath = FilteredFS::new(root)
+///     .ignore_rules("target/\n/secrets\n")
+///     .into();
+///
+/// let entries: Vec<_> = filtered.read_dir()?.map(|path| path.filename()).collect();
+/// assert_eq!(entries, vec!["src"]);
+/// assert!(filtered.join("src/lib.rs")?.exists()?);
+/// assert!(!filtered.join("secrets")?.exists()?);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct FilteredFS {
+    root: VfsPath,
+    includes: Vec<GlobPattern>,
+    rules: Vec<(GlobPattern, bool)>,
+}
+
+impl FilteredFS {
+    /// Create a new filtered FileSystem with its root at the given virtual path, without any rules
+    pub fn new(root: VfsPath) -> Self {
+        FilteredFS {
+            root,
+            includes: vec![],
+            rules: vec![],
+        }
+    }
+
+    /// Only shows files matching this pattern (or any other include pattern)
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.includes.push(GlobPattern::new(pattern));
+        self
+    }
+
+    /// Hides files and directories matching this pattern
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.rules.push((GlobPattern::new(pattern), true));
+        self
+    }
+
+    /// Adds rules in `.gitignore` syntax
+    ///
+    /// Each line is an exclude pattern, lines starting with `!` re-include paths,
+    /// empty lines and lines starting with `#` are ignored.
+    pub fn ignore_rules(mut self, rules: &str) -> Self {
+        for line in rules.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix('!') {
+                Some(pattern) => self.rules.push((GlobPattern::new(pattern), false)),
+                None => {
+                    let pattern = line.strip_prefix('\\').unwrap_or(line);
+                    self.rules.push((GlobPattern::new(pattern), true))
+                }
+            }
+        }
+        self
+    }
+
+    /// Adds the rules of an ignore file in `.gitignore` syntax, see [`FilteredFS::ignore_rules()`]
+    pub fn ignore_file(self, file: &VfsPath) -> VfsResult<Self> {
+        let rules = file.read_to_string()?;
+        Ok(self.ignore_rules(&rules))
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+
+    /// Checks the path itself (but not its parents) against the rules
+    fn matches_rules(&self, path: &str, is_dir: bool) -> bool {
+        let excluded = self
+            .rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| pattern.matches(path, is_dir))
+            .map(|(_, exclude)| *exclude)
+            .unwrap_or(false);
+        if excluded {
+            return false;
+        }
+        is_dir
+            || self.includes.is_empty()
+            || self
+                .includes
+                .iter()
+                .any(|pattern| pattern.matches(path, false))
+    }
+
+    /// Checks whether a path with the given file type is visible, including all of its parent directories
+    fn is_visible_as(&self, path: &str, file_type: VfsFileType) -> bool {
+        if path.is_empty() {
+            return true;
+        }
+        let mut end = 0;
+        while let Some(index) = path[end + 1..].find('/') {
+            end += index + 1;
+            if !self.matches_rules(&path[..end], true) {
+                return false;
+            }
+        }
+        self.matches_rules(path, file_type == VfsFileType::Directory)
+    }
+
+    /// Checks whether an existing path is visible, non-existing paths are checked as files
+    fn is_visible(&self, path: &str) -> VfsResult<bool> {
+        let inner = self.path(path)?;
+        let file_type = if inner.exists()? {
+            inner.metadata()?.file_type
+        } else {
+            VfsFileType::File
+        };
+        Ok(self.is_visible_as(path, file_type))
+    }
+
+    /// Returns the inner path, or FileNotFound if it is hidden
+    fn read_path(&self, path: &str) -> VfsResult<VfsPath> {
+        if !self.is_visible(path)? {
+            return Err(VfsErrorKind::FileNotFound.into());
+        }
+        self.path(path)
+    }
+
+    /// Returns the inner path, or an error if writing a path of this type is rejected by the filter
+    fn write_path(&self, path: &str, file_type: VfsFileType) -> VfsResult<VfsPath> {
+        if !self.is_visible_as(path, file_type) {
+            return Err(VfsErrorKind::Excluded.into());
+        }
+        self.path(path)
+    }
+}
+
+impl FileSystem for FilteredFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let mut entries = vec![];
+        for entry in self.read_path(path)?.read_dir()? {
+            let filename = entry.filename();
+            let file_type = entry.metadata()?.file_type;
+            if self.is_visible_as(&format!("{path}/{filename}"), file_type) {
+                entries.push(filename);
+            }
+        }
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.write_path(path, VfsFileType::Directory)?.create_dir()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        self.read_path(path)?.open_file()
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.write_path(path, VfsFileType::File)?.create_file()
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.read_path(path)?.append_file()
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.read_path(path)?.metadata()
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.read_path(path)?.set_creation_time(time)
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.read_path(path)?.set_modification_time(time)
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.read_path(path)?.set_access_time(time)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        let inner = self.path(path)?;
+        if !inner.exists()? {
+            return Ok(false);
+        }
+        Ok(self.is_visible_as(path, inner.metadata()?.file_type))
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.read_path(path)?.remove_file()
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.read_path(path)?.remove_dir()
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = self.write_path(dest, VfsFileType::File)?;
+        self.read_path(src)?.copy_file(&dest)
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest = self.write_path(dest, VfsFileType::File)?;
+        self.read_path(src)?.move_file(&dest)
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let dest_path = self.write_path(dest, VfsFileType::Directory)?;
+        let src_path = self.read_path(src)?;
+        // Hidden entries must not be moved along, and moved entries must stay visible
+        for entry in src_path.walk_dir()? {
+            let entry = entry?;
+            let relative = &entry.as_str()[src_path.as_str().len()..];
+            let file_type = entry.metadata()?.file_type;
+            if !self.is_visible_as(&format!("{src}{relative}"), file_type)
+                || !self.is_visible_as(&format!("{dest}{relative}"), file_type)
+            {
+                return Err(VfsErrorKind::Excluded.into());
+            }
+        }
+        src_path.move_dir(&dest_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryFS, PhysicalFS};
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        FilteredFS::new(memory_root).exclude("*.secret")
+    });
+
+    fn create_root() -> VfsResult<VfsPath> {
+        let root: VfsPath = MemoryFS::new().into();
+        for dir in ["src/impls", "target/debug", "secrets", "docs"] {
+            root.join(dir)?.create_dir_all()?;
+        }
+        for file in [
+            "Cargo.toml",
+            "src/lib.rs",
+            "src/impls/mod.rs",
+            "src/impls/generated.rs",
+            "target/debug/vfs.rlib",
+            "secrets/key.pem",
+            // a file, to check directory-only patterns
+            "docs/target",
+            "docs/index.md",
+            "debug.log",
+        ] {
+            root.join(file)?.create_file()?;
+        }
+        Ok(root)
+    }
+
+    fn walk(root: &VfsPath) -> VfsResult<Vec<String>> {
+        let mut paths = root
+            .walk_dir()?
+            .map(|path| path.map(|path| path.as_str().to_string()))
+            .collect::<VfsResult<Vec<_>>>()?;
+        paths.sort();
+        Ok(paths)
+    }
+
+    #[test]
+    fn exclude() -> VfsResult<()> {
+        let root: VfsPath = FilteredFS::new(create_root()?)
+            .exclude("target/")
+            .exclude("/secrets")
+            .exclude("*.log")
+            .into();
+        assert_eq!(
+            walk(&root)?,
+            vec![
+                "/Cargo.toml",
+                "/docs",
+                "/docs/index.md",
+                "/docs/target",
+                "/src",
+                "/src/impls",
+                "/src/impls/generated.rs",
+                "/src/impls/mod.rs",
+                "/src/lib.rs",
+            ]
+        );
+        assert!(!root.join("secrets/key.pem")?.exists()?);
+        assert!(!root.join("target")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn include() -> VfsResult<()> {
+        let root: VfsPath = FilteredFS::new(create_root()?)
+            .include("*.rs")
+            .include("/Cargo.toml")
+            .exclude("**/generated.rs")
+            .exclude("target/")
+            .into();
+        assert_eq!(
+            walk(&root)?,
+            vec![
+                "/Cargo.toml",
+                "/docs",
+                "/secrets",
+                "/src",
+                "/src/impls",
+                "/src/impls/mod.rs",
+                "/src/lib.rs",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_file_with_negation() -> VfsResult<()> {
+        let inner = create_root()?;
+        inner
+            .join(".gitignore")?
+            .create_file()?
+            .write_all(b"# build outputs\ntarget/\n\nsrc/impls/*\n!src/impls/mod.rs\n*.log\n")?;
+        let root: VfsPath = FilteredFS::new(inner.clone())
+            .ignore_file(&inner.join(".gitignore")?)?
+            .into();
+        assert_eq!(
+            walk(&root)?,
+            vec![
+                "/.gitignore",
+                "/Cargo.toml",
+                "/docs",
+                "/docs/index.md",
+                "/docs/target",
+                "/secrets",
+                "/secrets/key.pem",
+                "/src",
+                "/src/impls",
+                "/src/impls/mod.rs",
+                "/src/lib.rs",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hidden_paths_are_not_found() -> VfsResult<()> {
+        let root: VfsPath = FilteredFS::new(create_root()?).exclude("secrets").into();
+        let path = root.join("secrets/key.pem")?;
+        for error in [
+            path.open_file().map(|_| ()).unwrap_err(),
+            path.metadata().map(|_| ()).unwrap_err(),
+            root.join("secrets")?.read_dir().map(|_| ()).unwrap_err(),
+        ] {
+            assert!(
+                matches!(error.kind(), VfsErrorKind::FileNotFound),
+                "Unexpected error: {error}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn writes_to_hidden_paths_are_rejected() -> VfsResult<()> {
+        let inner = create_root()?;
+        let root: VfsPath = FilteredFS::new(inner.clone())
+            .exclude("secrets")
+            .exclude("*.log")
+            .into();
+        let error = root.join("new.log")?.create_file().map(|_| ()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not create file for '/new.log': The path is excluded by a filter"
+        );
+        assert!(matches!(error.kind(), VfsErrorKind::Excluded));
+        assert!(root.join("debug.log")?.append_file().is_err());
+        assert!(root.join("debug.log")?.remove_file().is_err());
+        assert!(root.join("secrets/new.txt")?.create_file().is_err());
+        assert!(root
+            .join("Cargo.toml")?
+            .move_file(&root.join("a.log")?)
+            .is_err());
+        assert!(root
+            .join("Cargo.toml")?
+            .copy_file(&root.join("secrets/Cargo.toml")?)
+            .is_err());
+        assert!(inner.join("debug.log")?.exists()?);
+        assert!(!inner.join("new.log")?.exists()?);
+        assert!(!inner.join("secrets/new.txt")?.exists()?);
+        assert!(!inner.join("secrets/Cargo.toml")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn move_dir_keeps_hidden_entries_in_place() -> VfsResult<()> {
+        let inner = create_root()?;
+        let root: VfsPath = FilteredFS::new(inner.clone()).exclude("*.log").into();
+        root.join("src")?.move_dir(&root.join("source")?)?;
+        assert!(inner.join("source/impls/mod.rs")?.exists()?);
+
+        inner.join("source/impls/trace.log")?.create_file()?;
+        let error = root
+            .join("source")?
+            .move_dir(&root.join("moved")?)
+            .unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::Excluded));
+        assert!(inner.join("source/impls/trace.log")?.exists()?);
+        assert!(!inner.join("moved")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn subtree_view() -> VfsResult<()> {
+        let physical_root: VfsPath = PhysicalFS::new("test/test_directory").into();
+        let root: VfsPath = FilteredFS::new(physical_root.join("a")?)
+            .exclude("y/")
+            .into();
+        assert_eq!(walk(&root)?, vec!["/d.txt", "/x"]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_readonly {
+    use super::*;
+    use crate::PhysicalFS;
+    test_vfs_readonly!({
+        FilteredFS::new(PhysicalFS::new("test/test_directory").into()).exclude("*.bak")
+    });
+}
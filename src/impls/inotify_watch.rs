@@ -0,0 +1,436 @@
+//! Watching a directory tree of the underlying OS file system with inotify
+
+use crate::error::VfsErrorKind;
+use crate::{VfsResult, WatchEvent};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BUFFER_SIZE: usize = 16 * 1024;
+/// How long the second half of a rename is waited for before the first half is taken as a move out of the tree
+const RENAME_TIMEOUT: Duration = Duration::from_millis(10);
+const RENAME_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A blocking iterator over the changes below a path of a physical filesystem
+///
+/// Directories created in or moved into a recursively watched tree are watched as well. Entries
+/// created in such a directory before its watch was added are reported by scanning it, so they
+/// might occasionally be reported twice.
+///
+/// If events were lost, because the event queue overflowed or reading it failed, the watched tree is
+/// scanned again: vanished directories are reported as removed and all present entries as created.
+/// The iteration only ends once nothing is watched anymore or reading fails again right after such a scan.
+pub(crate) struct InotifyWatch {
+    inotify: Inotify,
+    root: PathBuf,
+    path: String,
+    recursive: bool,
+    /// The virtual path of each watched file or directory
+    watches: HashMap<WatchDescriptor, String>,
+    buffer: Vec<u8>,
+    pending: VecDeque<WatchEvent>,
+    /// The first half of a rename whose second half was not read yet: cookie, path and whether it is a directory
+    moved_from: Option<(u32, String, bool)>,
+    /// Set if the last read failed
+    failed: bool,
+}
+
+/// A single event read from inotify, with the virtual path of its subject
+struct RawEvent {
+    wd: WatchDescriptor,
+    mask: EventMask,
+    cookie: u32,
+    path: Option<String>,
+}
+
+impl InotifyWatch {
+    /// Starts watching `path` of the physical filesystem rooted in `root`
+    pub(crate) fn new(root: &Path, path: &str, recursive: bool) -> VfsResult<Self> {
+        let mut watch = InotifyWatch {
+            inotify: Inotify::init()?,
+            root: root.to_path_buf(),
+            path: path.to_string(),
+            recursive,
+            watches: HashMap::new(),
+            buffer: vec![0; BUFFER_SIZE],
+            pending: VecDeque::new(),
+            moved_from: None,
+            failed: false,
+        };
+        let physical_path = watch.physical_path(path);
+        if !physical_path.exists() {
+            return Err(VfsErrorKind::FileNotFound.into());
+        }
+        if physical_path.is_dir() {
+            watch.add_directory(path, false)?;
+        } else {
+            let wd = watch
+                .inotify
+                .watches()
+                .add(&physical_path, WatchMask::CLOSE_WRITE | self_mask())?;
+            watch.watches.insert(wd, path.to_string());
+        }
+        Ok(watch)
+    }
+
+    fn physical_path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    /// Watches the given directory and, if recursive, its subdirectories
+    ///
+    /// If `report` is set, the entries already present are reported as created.
+    fn add_directory(&mut self, path: &str, report: bool) -> VfsResult<()> {
+        let mask = WatchMask::CREATE
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::DELETE
+            | WatchMask::MOVE
+            | self_mask()
+            | WatchMask::ONLYDIR;
+        let wd = match self.inotify.watches().add(self.physical_path(path), mask) {
+            Ok(wd) => wd,
+            // The directory vanished again before it could be watched
+            Err(err) if err.kind() == ErrorKind::NotFound && report => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        self.watches.insert(wd, path.to_string());
+        if !self.recursive && !report {
+            return Ok(());
+        }
+        let entries = match std::fs::read_dir(self.physical_path(path)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound && report => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let entry_path = format!("{}/{}", path, entry.file_name().to_string_lossy());
+            if report {
+                self.pending
+                    .push_back(WatchEvent::Created(entry_path.clone()));
+            }
+            if self.recursive && entry.file_type()?.is_dir() {
+                self.add_directory(&entry_path, report)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops watching the given directory and all directories below it
+    fn remove_directory(&mut self, path: &str) {
+        let prefix = format!("{path}/");
+        let removed: Vec<WatchDescriptor> = self
+            .watches
+            .iter()
+            .filter(|(_, watched)| *watched == path || watched.starts_with(&prefix))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in removed {
+            self.watches.remove(&wd);
+            // Fails if the directory is already gone, which removes the watch anyway
+            let _ = self.inotify.watches().remove(wd);
+        }
+    }
+
+    /// Updates the paths of the watched directories of a directory moved within the watched tree
+    fn rename_directory(&mut self, from: &str, to: &str) {
+        let prefix = format!("{from}/");
+        for watched in self.watches.values_mut() {
+            if watched == from || watched.starts_with(&prefix) {
+                *watched = format!("{}{}", to, &watched[from.len()..]);
+            }
+        }
+    }
+
+    /// Reads the queued events, without waiting for new ones unless `blocking` is set
+    fn read_events(&mut self, blocking: bool) -> io::Result<Vec<RawEvent>> {
+        let events = match blocking {
+            true => self.inotify.read_events_blocking(&mut self.buffer)?,
+            false => match self.inotify.read_events(&mut self.buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(vec![]),
+                Err(err) => return Err(err),
+            },
+        };
+        Ok(events
+            .filter_map(|event| {
+                let path = match self.watches.get(&event.wd) {
+                    Some(directory) => event
+                        .name
+                        .map(|name| format!("{}/{}", directory, name.to_string_lossy())),
+                    // An overflow of the queue is not reported for any watch
+                    None if event.mask.contains(EventMask::Q_OVERFLOW) => None,
+                    None => return None,
+                };
+                Some(RawEvent {
+                    wd: event.wd,
+                    mask: event.mask,
+                    cookie: event.cookie,
+                    path,
+                })
+            })
+            .collect())
+    }
+
+    /// Reads the events following an unpaired first half of a rename
+    ///
+    /// If its second half does not follow in time, the entry was moved out of the watched tree.
+    fn read_events_after_move(&mut self) -> io::Result<Vec<RawEvent>> {
+        let deadline = Instant::now() + RENAME_TIMEOUT;
+        loop {
+            let events = self.read_events(false)?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+            if Instant::now() >= deadline {
+                if let Some((_, from, from_dir)) = self.moved_from.take() {
+                    self.moved_out(from, from_dir);
+                }
+                return Ok(events);
+            }
+            thread::sleep(RENAME_POLL_INTERVAL);
+        }
+    }
+
+    fn process(&mut self, events: Vec<RawEvent>) {
+        for event in events {
+            if event.mask.contains(EventMask::Q_OVERFLOW) {
+                if let Some((_, from, from_dir)) = self.moved_from.take() {
+                    self.moved_out(from, from_dir);
+                }
+                self.rescan();
+                continue;
+            }
+            let is_dir = event.mask.contains(EventMask::ISDIR);
+            if let Some((cookie, from, from_dir)) = self.moved_from.take() {
+                if event.mask.contains(EventMask::MOVED_TO) && event.cookie == cookie {
+                    let to = event.path.unwrap_or_default();
+                    if from_dir {
+                        self.rename_directory(&from, &to);
+                    }
+                    self.pending.push_back(WatchEvent::Renamed { from, to });
+                    continue;
+                }
+                self.moved_out(from, from_dir);
+            }
+            let path = match event.path {
+                Some(path) => path,
+                None => {
+                    self.process_self_event(event.wd, event.mask);
+                    continue;
+                }
+            };
+            if event.mask.contains(EventMask::MOVED_FROM) {
+                self.moved_from = Some((event.cookie, path, is_dir));
+            } else if event
+                .mask
+                .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+            {
+                self.pending.push_back(WatchEvent::Created(path.clone()));
+                if is_dir && self.recursive {
+                    // Errors only mean that the new directory is not watched
+                    let _ = self.add_directory(&path, true);
+                }
+            } else if event.mask.contains(EventMask::CLOSE_WRITE) {
+                self.pending.push_back(WatchEvent::Modified(path));
+            } else if event.mask.contains(EventMask::DELETE) {
+                self.pending.push_back(WatchEvent::Removed(path));
+            }
+        }
+    }
+
+    /// Catches up with changes whose events were lost
+    ///
+    /// Watched directories that vanished are reported as removed, the entries present as created.
+    fn rescan(&mut self) {
+        let mut vanished: Vec<String> = self
+            .watches
+            .values()
+            .filter(|path| !self.physical_path(path).exists())
+            .cloned()
+            .collect();
+        vanished.sort();
+        for path in vanished.into_iter().rev() {
+            self.remove_directory(&path);
+            self.pending.push_back(WatchEvent::Removed(path));
+        }
+        let path = self.path.clone();
+        if !self.watches.values().any(|watched| *watched == path) {
+            return;
+        }
+        if self.physical_path(&path).is_dir() {
+            // Watching a directory again keeps its watch, errors only mean that some entries are not watched
+            let _ = self.add_directory(&path, true);
+        } else {
+            self.pending.push_back(WatchEvent::Modified(path));
+        }
+    }
+
+    fn moved_out(&mut self, path: String, is_dir: bool) {
+        if is_dir {
+            self.remove_directory(&path);
+        }
+        self.pending.push_back(WatchEvent::Removed(path));
+    }
+
+    /// Handles an event concerning a watched file or directory itself
+    fn process_self_event(&mut self, wd: WatchDescriptor, mask: EventMask) {
+        if mask.contains(EventMask::IGNORED) {
+            self.watches.remove(&wd);
+            return;
+        }
+        let path = match self.watches.get(&wd) {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let is_top = !self
+            .watches
+            .values()
+            .any(|watched| path.starts_with(&format!("{watched}/")));
+        if mask.contains(EventMask::CLOSE_WRITE) {
+            self.pending.push_back(WatchEvent::Modified(path));
+        } else if is_top && mask.intersects(EventMask::DELETE_SELF | EventMask::MOVE_SELF) {
+            // Changes to directories below are already reported by their parents
+            self.remove_directory(&path);
+            self.pending.push_back(WatchEvent::Removed(path));
+        }
+    }
+}
+
+fn self_mask() -> WatchMask {
+    WatchMask::DELETE_SELF | WatchMask::MOVE_SELF
+}
+
+impl Iterator for InotifyWatch {
+    type Item = WatchEvent;
+
+    fn next(&mut self) -> Option<WatchEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.watches.is_empty() {
+                let (_, from, from_dir) = self.moved_from.take()?;
+                self.moved_out(from, from_dir);
+                continue;
+            }
+            let events = match self.moved_from {
+                Some(_) => self.read_events_after_move(),
+                None => self.read_events(true),
+            };
+            match events {
+                Ok(events) => {
+                    self.failed = false;
+                    self.process(events);
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                // A failed read might have lost events, the watch ends if the next read fails as well
+                Err(_) if !self.failed => {
+                    self.failed = true;
+                    self.rescan();
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> VfsResult<PathBuf> {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn event(
+        watch: &InotifyWatch,
+        directory: &str,
+        mask: EventMask,
+        cookie: u32,
+        name: &str,
+    ) -> RawEvent {
+        let wd = watch
+            .watches
+            .iter()
+            .find(|(_, watched)| *watched == directory)
+            .map(|(wd, _)| wd.clone())
+            .unwrap();
+        RawEvent {
+            wd,
+            mask,
+            cookie,
+            path: Some(format!("{directory}/{name}")),
+        }
+    }
+
+    #[test]
+    fn renames_are_paired_across_reads() -> VfsResult<()> {
+        let dir = temp_dir()?;
+        std::fs::create_dir(dir.join("sub"))?;
+        let mut watch = InotifyWatch::new(&dir, "", true)?;
+        let moved_from = event(&watch, "", EventMask::MOVED_FROM, 7, "a.txt");
+        watch.process(vec![moved_from]);
+        assert_eq!(watch.pending.pop_front(), None);
+        let moved_to = event(&watch, "/sub", EventMask::MOVED_TO, 7, "b.txt");
+        watch.process(vec![moved_to]);
+        assert_eq!(
+            watch.pending.pop_front(),
+            Some(WatchEvent::Renamed {
+                from: "/a.txt".into(),
+                to: "/sub/b.txt".into()
+            })
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn unpaired_moves_leave_the_tree() -> VfsResult<()> {
+        let dir = temp_dir()?;
+        let mut watch = InotifyWatch::new(&dir, "", true)?;
+        let moved_from = event(&watch, "", EventMask::MOVED_FROM, 7, "a.txt");
+        watch.process(vec![moved_from]);
+        assert_eq!(watch.next(), Some(WatchEvent::Removed("/a.txt".into())));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn overflows_rescan_the_tree() -> VfsResult<()> {
+        let dir = temp_dir()?;
+        std::fs::create_dir_all(dir.join("gone/deep"))?;
+        std::fs::create_dir(dir.join("kept"))?;
+        let mut watch = InotifyWatch::new(&dir, "", true)?;
+        std::fs::remove_dir_all(dir.join("gone"))?;
+        std::fs::write(dir.join("kept/a.txt"), b"a")?;
+        let mut overflow = event(&watch, "", EventMask::Q_OVERFLOW, 0, "");
+        overflow.path = None;
+        watch.process(vec![overflow]);
+        assert_eq!(
+            watch.pending.drain(..).collect::<Vec<_>>(),
+            vec![
+                WatchEvent::Removed("/gone/deep".into()),
+                WatchEvent::Removed("/gone".into()),
+                WatchEvent::Created("/kept".into()),
+                WatchEvent::Created("/kept/a.txt".into()),
+            ]
+        );
+        assert_eq!(
+            watch
+                .watches
+                .values()
+                .filter(|path| path.starts_with("/gone"))
+                .count(),
+            0
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 //! An ephemeral in-memory file system, intended mainly for unit tests
 
 use crate::error::VfsErrorKind;
-use crate::{FileSystem, VfsFileType};
+use crate::watch::Watchers;
+use crate::{FileSystem, VfsFileType, WatchEvent};
 use crate::{SeekAndRead, VfsMetadata};
 use crate::{SeekAndWrite, VfsResult};
 use core::cmp;
@@ -35,11 +36,15 @@ impl MemoryFS {
         }
     }
 
+    /// Checks that the parent is a directory, so that native moves cannot put entries below a file
     fn ensure_has_parent(&self, path: &str) -> VfsResult<()> {
         let separator = path.rfind('/');
         if let Some(index) = separator {
-            if self.exists(&path[..index])? {
-                return Ok(());
+            let handle = self.handle.read().unwrap();
+            if let Some(parent) = handle.files.get(&path[..index]) {
+                if parent.file_type == VfsFileType::Directory {
+                    return Ok(());
+                }
             }
         }
         Err(VfsErrorKind::Other("Parent path does not exist".into()).into())
@@ -87,6 +92,9 @@ impl Write for WritableFile {
         };
 
         handle.files.insert(self.destination.clone(), new_file);
+        handle
+            .watchers
+            .emit(WatchEvent::Modified(self.destination.clone()));
         Ok(())
     }
 }
@@ -167,7 +175,8 @@ impl FileSystem for MemoryFS {
 
     fn create_dir(&self, path: &str) -> VfsResult<()> {
         self.ensure_has_parent(path)?;
-        let map = &mut self.handle.write().unwrap().files;
+        let mut handle = self.handle.write().unwrap();
+        let map = &mut handle.files;
         let entry = map.entry(path.to_string());
         match entry {
             Entry::Occupied(file) => {
@@ -189,6 +198,7 @@ impl FileSystem for MemoryFS {
                 );
             }
         }
+        handle.watchers.emit(WatchEvent::Created(path.to_string()));
         Ok(())
     }
 
@@ -207,7 +217,8 @@ impl FileSystem for MemoryFS {
     fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
         self.ensure_has_parent(path)?;
         let content = Arc::new(Vec::<u8>::new());
-        self.handle.write().unwrap().files.insert(
+        let mut handle = self.handle.write().unwrap();
+        let previous = handle.files.insert(
             path.to_string(),
             MemoryFile {
                 file_type: VfsFileType::File,
@@ -217,6 +228,10 @@ impl FileSystem for MemoryFS {
                 accessed: Some(SystemTime::now()),
             },
         );
+        handle.watchers.emit(match previous {
+            Some(_) => WatchEvent::Modified(path.to_string()),
+            None => WatchEvent::Created(path.to_string()),
+        });
         let writer = WritableFile {
             content: Cursor::new(vec![]),
             destination: path.to_string(),
@@ -291,6 +306,7 @@ impl FileSystem for MemoryFS {
             .files
             .remove(path)
             .ok_or(VfsErrorKind::FileNotFound)?;
+        handle.watchers.emit(WatchEvent::Removed(path.to_string()));
         Ok(())
     }
 
@@ -303,12 +319,71 @@ impl FileSystem for MemoryFS {
             .files
             .remove(path)
             .ok_or(VfsErrorKind::FileNotFound)?;
+        handle.watchers.emit(WatchEvent::Removed(path.to_string()));
+        Ok(())
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.ensure_has_parent(dest)?;
+        let mut handle = self.handle.write().unwrap();
+        ensure_file(handle.files.get(src).ok_or(VfsErrorKind::FileNotFound)?)?;
+        ensure_not_exists(&handle.files, dest)?;
+        let file = handle.files.remove(src).expect("File checked above");
+        handle.files.insert(dest.to_string(), file);
+        handle.watchers.emit(WatchEvent::Renamed {
+            from: src.to_string(),
+            to: dest.to_string(),
+        });
         Ok(())
     }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.ensure_has_parent(dest)?;
+        if dest.starts_with(&format!("{src}/")) {
+            return Err(VfsErrorKind::Other("Cannot move a directory into itself".into()).into());
+        }
+        let mut handle = self.handle.write().unwrap();
+        let directory = handle.files.get(src).ok_or(VfsErrorKind::FileNotFound)?;
+        if directory.file_type != VfsFileType::Directory {
+            return Err(VfsErrorKind::Other("Not a directory".into()).into());
+        }
+        ensure_not_exists(&handle.files, dest)?;
+        let prefix = format!("{src}/");
+        let moved: Vec<String> = handle
+            .files
+            .keys()
+            .filter(|path| *path == src || path.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for path in moved {
+            let file = handle.files.remove(&path).expect("Path collected above");
+            handle
+                .files
+                .insert(format!("{dest}{}", &path[src.len()..]), file);
+        }
+        handle.watchers.emit(WatchEvent::Renamed {
+            from: src.to_string(),
+            to: dest.to_string(),
+        });
+        Ok(())
+    }
+
+    fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        let handle = self.handle.read().unwrap();
+        if !handle.files.contains_key(path) {
+            return Err(VfsErrorKind::FileNotFound.into());
+        }
+        Ok(handle.watchers.subscribe(path, recursive))
+    }
 }
 
 struct MemoryFsImpl {
     files: HashMap<String, MemoryFile>,
+    watchers: Watchers,
 }
 
 impl MemoryFsImpl {
@@ -325,7 +400,10 @@ impl MemoryFsImpl {
                 accessed: None,
             },
         );
-        Self { files }
+        Self {
+            files,
+            watchers: Watchers::default(),
+        }
     }
 }
 
@@ -346,6 +424,17 @@ fn ensure_file(file: &MemoryFile) -> VfsResult<()> {
     Ok(())
 }
 
+/// Native moves never replace an existing entry, like moves through a [`VfsPath`](crate::VfsPath)
+fn ensure_not_exists(files: &HashMap<String, MemoryFile>, path: &str) -> VfsResult<()> {
+    match files.get(path) {
+        Some(file) if file.file_type == VfsFileType::Directory => {
+            Err(VfsErrorKind::DirectoryExists.into())
+        }
+        Some(_) => Err(VfsErrorKind::FileExists.into()),
+        None => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -518,4 +607,110 @@ mod tests {
         read_handle.read_to_string(&mut string).unwrap();
         assert_eq!(string, "Testing 1Testing 2Testing 3");
     }
+    #[test]
+    fn watch_reports_mutations() -> VfsResult<()> {
+        let root = VfsPath::new(MemoryFS::new());
+        let events = root.watch(true)?;
+        root.join("a")?.create_dir()?;
+        root.join("a/b.txt")?.create_file()?.write_all(b"Hello")?;
+        root.join("a/b.txt")?.move_file(&root.join("a/c.txt")?)?;
+        root.join("a/c.txt")?.remove_file()?;
+        root.join("a")?.move_dir(&root.join("d")?)?;
+        root.join("d")?.remove_dir()?;
+        // The iterator ends once the filesystem is dropped
+        drop(root);
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![
+                WatchEvent::Created("/a".into()),
+                WatchEvent::Created("/a/b.txt".into()),
+                WatchEvent::Modified("/a/b.txt".into()),
+                WatchEvent::Renamed {
+                    from: "/a/b.txt".into(),
+                    to: "/a/c.txt".into()
+                },
+                WatchEvent::Removed("/a/c.txt".into()),
+                WatchEvent::Renamed {
+                    from: "/a".into(),
+                    to: "/d".into()
+                },
+                WatchEvent::Removed("/d".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn native_moves_report_renames_without_replacing() -> VfsResult<()> {
+        // Moving natively instead of copying is what lets watchers see a rename
+        let fs = MemoryFS::new();
+        fs.create_dir("/a")?;
+        fs.create_file("/a/b.txt")?.write_all(b"b")?;
+        fs.create_file("/c.txt")?.write_all(b"c")?;
+        fs.create_dir("/d")?;
+
+        let error = fs.move_file("/c.txt", "/a/b.txt").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::FileExists));
+        let error = fs.move_file("/c.txt", "/d").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::DirectoryExists));
+        let error = fs.move_dir("/a", "/d").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::DirectoryExists));
+        let error = fs.move_dir("/a", "/c.txt").unwrap_err();
+        assert!(matches!(error.kind(), VfsErrorKind::FileExists));
+        assert!(fs.move_file("/c.txt", "/a/b.txt/c.txt").is_err());
+        assert!(fs.move_dir("/d", "/c.txt/d").is_err());
+
+        let events = fs.watch("", true)?;
+        fs.move_dir("/a", "/d/a")?;
+        fs.move_file("/c.txt", "/d/a/c.txt")?;
+        let mut content = String::new();
+        fs.open_file("/d/a/b.txt")?.read_to_string(&mut content)?;
+        assert_eq!(content, "b");
+        assert!(!fs.exists("/a")?);
+        drop(fs);
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![
+                WatchEvent::Renamed {
+                    from: "/a".into(),
+                    to: "/d/a".into()
+                },
+                WatchEvent::Renamed {
+                    from: "/c.txt".into(),
+                    to: "/d/a/c.txt".into()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watch_non_recursive() -> VfsResult<()> {
+        let root = VfsPath::new(MemoryFS::new());
+        root.join("a/b")?.create_dir_all()?;
+        let events = root.join("a")?.watch(false)?;
+        root.join("a/b/deep.txt")?.create_file()?;
+        root.join("a/c.txt")?.create_file()?;
+        root.join("other.txt")?.create_file()?;
+        drop(root);
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![
+                WatchEvent::Created("/a/c.txt".into()),
+                WatchEvent::Modified("/a/c.txt".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watch_missing_path() -> VfsResult<()> {
+        let root = VfsPath::new(MemoryFS::new());
+        let error = root.join("foo")?.watch(false).err().expect("watch");
+        assert_eq!(
+            error.to_string(),
+            "Could not watch path for '/foo': The file or directory could not be found"
+        );
+        Ok(())
+    }
 }
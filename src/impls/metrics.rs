@@ -0,0 +1,454 @@
+//! A filesystem wrapper collecting metrics about all calls
+
+use crate::error::VfsErrorKind;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult};
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The kind of operation counted in a [`MetricsSnapshot`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MetricsOperation {
+    /// [`FileSystem::read_dir()`]
+    ReadDir,
+    /// [`FileSystem::create_dir()`]
+    CreateDir,
+    /// [`FileSystem::open_file()`]
+    OpenFile,
+    /// [`FileSystem::create_file()`]
+    CreateFile,
+    /// [`FileSystem::append_file()`]
+    AppendFile,
+    /// [`FileSystem::metadata()`]
+    Metadata,
+    /// [`FileSystem::set_creation_time()`]
+    SetCreationTime,
+    /// [`FileSystem::set_modification_time()`]
+    SetModificationTime,
+    /// [`FileSystem::set_access_time()`]
+    SetAccessTime,
+    /// [`FileSystem::exists()`]
+    Exists,
+    /// [`FileSystem::remove_file()`]
+    RemoveFile,
+    /// [`FileSystem::remove_dir()`]
+    RemoveDir,
+    /// [`FileSystem::copy_file()`]
+    CopyFile,
+    /// [`FileSystem::move_file()`]
+    MoveFile,
+    /// [`FileSystem::move_dir()`]
+    MoveDir,
+    /// Reading from a handle returned by `open_file()`, counted when the handle is dropped
+    Read,
+    /// Writing to a handle returned by `create_file()` or `append_file()`, counted when the handle is dropped
+    Write,
+}
+
+/// A histogram of call latencies
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// Number of recorded calls
+    pub count: u64,
+    /// Sum of all latencies
+    pub total: Duration,
+    /// The highest latency
+    pub max: Duration,
+    /// Number of calls per bucket, see [`LatencyHistogram::BUCKET_BOUNDS`]
+    ///
+    /// The last bucket counts all calls above the highest bound.
+    pub buckets: [u64; 8],
+}
+
+impl LatencyHistogram {
+    /// The inclusive upper bounds of the buckets
+    pub const BUCKET_BOUNDS: [Duration; 7] = [
+        Duration::from_micros(10),
+        Duration::from_micros(100),
+        Duration::from_millis(1),
+        Duration::from_millis(10),
+        Duration::from_millis(100),
+        Duration::from_secs(1),
+        Duration::from_secs(10),
+    ];
+
+    fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+        let bucket = Self::BUCKET_BOUNDS
+            .iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(Self::BUCKET_BOUNDS.len());
+        self.buckets[bucket] += 1;
+    }
+
+    /// The average latency, zero if no calls were recorded
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.total.as_nanos() / u128::from(self.count)) as u64)
+    }
+}
+
+/// The metrics collected by a [`MetricsFS`] at a point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Number of calls per operation
+    ///
+    /// Reading from and writing to file handles is counted once per handle, when it is dropped.
+    pub calls: BTreeMap<MetricsOperation, u64>,
+    /// Number of failed calls per error kind, e.g. `FileNotFound`
+    pub errors: BTreeMap<String, u64>,
+    /// Total number of bytes read from file handles
+    pub bytes_read: u64,
+    /// Total number of bytes written to file handles
+    pub bytes_written: u64,
+    /// Latencies per operation, for file handles the total time spent reading or writing
+    pub latencies: BTreeMap<MetricsOperation, LatencyHistogram>,
+}
+
+impl MetricsSnapshot {
+    /// Total number of calls
+    pub fn total_calls(&self) -> u64 {
+        self.calls.values().sum()
+    }
+
+    /// Total number of failed calls
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+impl MetricsSnapshot {
+    fn record(
+        &mut self,
+        operation: MetricsOperation,
+        error: Option<&VfsErrorKind>,
+        latency: Duration,
+    ) {
+        *self.calls.entry(operation).or_default() += 1;
+        if let Some(kind) = error {
+            *self.errors.entry(kind_name(kind).into()).or_default() += 1;
+        }
+        self.latencies.entry(operation).or_default().record(latency);
+    }
+}
+
+fn kind_name(kind: &VfsErrorKind) -> &'static str {
+    match kind {
+        VfsErrorKind::IoError(_) => "IoError",
+        #[cfg(feature = "async-vfs-core")]
+        VfsErrorKind::AsyncIoError(_) => "AsyncIoError",
+        VfsErrorKind::FileNotFound => "FileNotFound",
+        VfsErrorKind::InvalidPath => "InvalidPath",
+        VfsErrorKind::Other(_) => "Other",
+        VfsErrorKind::DirectoryExists => "DirectoryExists",
+        VfsErrorKind::FileExists => "FileExists",
+        VfsErrorKind::NotSupported => "NotSupported",
+        VfsErrorKind::CrossMount => "CrossMount",
+        VfsErrorKind::EscapesRoot => "EscapesRoot",
+        VfsErrorKind::ReadOnly => "ReadOnly",
+        VfsErrorKind::AmbiguousPath => "AmbiguousPath",
+        VfsErrorKind::IntegrityViolation => "IntegrityViolation",
+        VfsErrorKind::Excluded => "Excluded",
+        VfsErrorKind::Cancelled(_) => "Cancelled",
+    }
+}
+
+/// A view of the directory tree at a particular path of another filesystem, collecting metrics about all calls
+///
+/// Counts calls per operation, errors per error kind, bytes read and written through file handles,
+/// and keeps latency histograms per operation. Clones share the same metrics, so a clone can be kept
+/// for exporting them after converting the filesystem into a [`VfsPath`].
+///
+/// ```
+/// # use vfs::{MemoryFS, MetricsFS, VfsError, VfsPath};
+/// use vfs::impls::metrics::MetricsOperation;
+///
+/// let metrics = MetricsFS::new(MemoryFS::new().into());
+/// let root: VfsPath = metrics.clone().into();
+/// root.join("foo.txt")?.create_file()?.write_all(b"Hello")?;
+/// assert!(root.join("bar.txt")?.open_file().is_err());
+///
+/// let snapshot = metrics.snapshot();
+/// assert_eq!(snapshot.calls[&MetricsOperation::CreateFile], 1);
+/// assert_eq!(snapshot.errors["FileNotFound"], 1);
+/// assert_eq!(snapshot.bytes_written, 5);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetricsFS {
+    root: VfsPath,
+    metrics: Arc<Mutex<MetricsSnapshot>>,
+}
+
+impl MetricsFS {
+    /// Create a new FileSystem with its root at the given virtual path, collecting metrics about all calls
+    pub fn new(root: VfsPath) -> Self {
+        MetricsFS {
+            root,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Returns a copy of the current metrics
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// Resets all metrics to zero
+    pub fn reset(&self) {
+        *self.metrics.lock().unwrap() = MetricsSnapshot::default();
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+
+    fn measure<T>(
+        &self,
+        operation: MetricsOperation,
+        call: impl FnOnce() -> VfsResult<T>,
+    ) -> VfsResult<T> {
+        let start = Instant::now();
+        let result = call();
+        let latency = start.elapsed();
+        let error = result.as_ref().err().map(|error| error.kind());
+        self.metrics
+            .lock()
+            .unwrap()
+            .record(operation, error, latency);
+        result
+    }
+
+    fn handle<T>(&self, operation: MetricsOperation, inner: T) -> MeasuredHandle<T> {
+        MeasuredHandle {
+            inner,
+            metrics: self.metrics.clone(),
+            operation,
+            bytes: 0,
+            duration: Duration::ZERO,
+            error: None,
+        }
+    }
+}
+
+impl FileSystem for MetricsFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.measure(MetricsOperation::ReadDir, || {
+            self.path(path)?
+                .read_dir()
+                .map(|entries| Box::new(entries.map(|path| path.filename())) as Box<_>)
+        })
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.measure(MetricsOperation::CreateDir, || {
+            self.path(path)?.create_dir()
+        })
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let file = self.measure(MetricsOperation::OpenFile, || self.path(path)?.open_file())?;
+        Ok(Box::new(self.handle(MetricsOperation::Read, file)))
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.measure(MetricsOperation::CreateFile, || {
+            self.path(path)?.create_file()
+        })?;
+        Ok(Box::new(self.handle(MetricsOperation::Write, file)))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.measure(MetricsOperation::AppendFile, || {
+            self.path(path)?.append_file()
+        })?;
+        Ok(Box::new(self.handle(MetricsOperation::Write, file)))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.measure(MetricsOperation::Metadata, || self.path(path)?.metadata())
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.measure(MetricsOperation::SetCreationTime, || {
+            self.path(path)?.set_creation_time(time)
+        })
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.measure(MetricsOperation::SetModificationTime, || {
+            self.path(path)?.set_modification_time(time)
+        })
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.measure(MetricsOperation::SetAccessTime, || {
+            self.path(path)?.set_access_time(time)
+        })
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.measure(MetricsOperation::Exists, || self.path(path)?.exists())
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.measure(MetricsOperation::RemoveFile, || {
+            self.path(path)?.remove_file()
+        })
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.measure(MetricsOperation::RemoveDir, || {
+            self.path(path)?.remove_dir()
+        })
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.measure(MetricsOperation::CopyFile, || {
+            self.path(src)?.copy_file(&self.path(dest)?)
+        })
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.measure(MetricsOperation::MoveFile, || {
+            self.path(src)?.move_file(&self.path(dest)?)
+        })
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.measure(MetricsOperation::MoveDir, || {
+            self.path(src)?.move_dir(&self.path(dest)?)
+        })
+    }
+}
+
+/// A file handle counting the bytes transferred, recording them when dropped
+struct MeasuredHandle<T> {
+    inner: T,
+    metrics: Arc<Mutex<MetricsSnapshot>>,
+    operation: MetricsOperation,
+    bytes: u64,
+    duration: Duration,
+    error: Option<VfsErrorKind>,
+}
+
+impl<T> MeasuredHandle<T> {
+    fn track<R>(&mut self, call: impl FnOnce(&mut T) -> io::Result<R>) -> io::Result<R> {
+        let start = Instant::now();
+        let result = call(&mut self.inner);
+        self.duration += start.elapsed();
+        if let Err(error) = &result {
+            self.error.get_or_insert_with(|| {
+                VfsErrorKind::IoError(io::Error::new(error.kind(), error.to_string()))
+            });
+        }
+        result
+    }
+}
+
+impl<T: Read> Read for MeasuredHandle<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.track(|inner| inner.read(buf))?;
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Write> Write for MeasuredHandle<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.track(|inner| inner.write(buf))?;
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.track(|inner| inner.flush())
+    }
+}
+
+impl<T: Seek> Seek for MeasuredHandle<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.track(|inner| inner.seek(pos))
+    }
+}
+
+impl<T> Drop for MeasuredHandle<T> {
+    fn drop(&mut self) {
+        let mut metrics = self.metrics.lock().unwrap();
+        match self.operation {
+            MetricsOperation::Read => metrics.bytes_read += self.bytes,
+            _ => metrics.bytes_written += self.bytes,
+        }
+        metrics.record(self.operation, self.error.as_ref(), self.duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFS;
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        MetricsFS::new(memory_root)
+    });
+
+    #[test]
+    fn counts_calls_errors_and_bytes() -> VfsResult<()> {
+        let fs = MetricsFS::new(MemoryFS::new().into());
+        fs.create_dir("/dir")?;
+        fs.create_file("/dir/a.txt")?.write_all(b"Hello World")?;
+        let mut content = vec![];
+        fs.open_file("/dir/a.txt")?.read_to_end(&mut content)?;
+        fs.open_file("/dir/a.txt")?.read_exact(&mut [0; 5])?;
+        assert!(fs.open_file("/missing.txt").is_err());
+        assert!(fs.create_dir("/dir").is_err());
+
+        let snapshot = fs.snapshot();
+        assert_eq!(snapshot.calls[&MetricsOperation::CreateDir], 2);
+        assert_eq!(snapshot.calls[&MetricsOperation::OpenFile], 3);
+        assert_eq!(snapshot.calls[&MetricsOperation::Read], 2);
+        assert_eq!(snapshot.calls[&MetricsOperation::Write], 1);
+        assert_eq!(snapshot.total_calls(), 9);
+        assert_eq!(
+            snapshot.errors,
+            BTreeMap::from([
+                ("DirectoryExists".to_string(), 1),
+                ("FileNotFound".to_string(), 1)
+            ])
+        );
+        assert_eq!(snapshot.total_errors(), 2);
+        assert_eq!(snapshot.bytes_read, 16);
+        assert_eq!(snapshot.bytes_written, 11);
+        assert_eq!(snapshot.latencies[&MetricsOperation::OpenFile].count, 3);
+
+        fs.reset();
+        assert_eq!(fs.snapshot(), MetricsSnapshot::default());
+        Ok(())
+    }
+
+    #[test]
+    fn latency_histogram_buckets() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_micros(5));
+        histogram.record(Duration::from_micros(10));
+        histogram.record(Duration::from_millis(5));
+        histogram.record(Duration::from_secs(60));
+        assert_eq!(histogram.buckets, [2, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(histogram.count, 4);
+        assert_eq!(histogram.max, Duration::from_secs(60));
+        assert_eq!(
+            histogram.mean(),
+            (Duration::from_micros(15) + Duration::from_millis(5) + Duration::from_secs(60)) / 4
+        );
+        assert_eq!(LatencyHistogram::default().mean(), Duration::ZERO);
+    }
+}
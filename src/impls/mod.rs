@@ -1,8 +1,28 @@
 //! Virtual filesystem implementations
 
 pub mod altroot;
+pub mod caching;
+#[cfg(feature = "cas-fs")]
+pub mod cas;
+pub mod case_insensitive;
+#[cfg(feature = "compressed-fs")]
+pub mod compressed;
 #[cfg(feature = "embedded-fs")]
 pub mod embedded;
+#[cfg(feature = "encrypted-fs")]
+pub mod encrypted;
+pub mod faulty;
+pub mod filtered;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+pub(crate) mod inotify_watch;
 pub mod memory;
+pub mod metrics;
+pub mod mount;
 pub mod overlay;
 pub mod physical;
+pub mod readonly;
+#[cfg(feature = "sandboxed-fs")]
+pub mod sandboxed;
+pub(crate) mod seek;
+pub mod tracing;
+pub mod versioned;
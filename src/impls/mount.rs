@@ -0,0 +1,451 @@
+//! A file system composing several filesystems by mounting them at different paths
+
+use crate::error::VfsErrorKind;
+use crate::{
+    FileSystem, SeekAndRead, SeekAndWrite, VfsError, VfsFileType, VfsMetadata, VfsPath, VfsResult,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// A file system composing several filesystems into one namespace by mounting them at different paths
+///
+/// Each call is routed to the filesystem with the longest mount point matching the path.
+/// Mount points (and the directories leading up to them) show up as directories, even if they do not exist
+/// in the filesystem mounted above them.
+///
+/// Filesystems can be mounted and unmounted at runtime, clones of a `MountFS` share the same mount table.
+///
+/// Moving or copying between different mounts fails with [`VfsErrorKind::CrossMount`],
+/// which makes [`VfsPath`] fall back to copying the contents.
+///
+/// ```
+/// # use vfs::{MemoryFS, MountFS, PhysicalFS, VfsError, VfsPath};
+/// let mount_fs = MountFS::new();
+/// mount_fs.mount("/data", PhysicalFS::new("test/test_directory"))?;
+/// mount_fs.mount("/tmp", MemoryFS::new())?;
+/// let root: VfsPath = mount_fs.clone().into();
+///
+/// assert_eq!(root.join("data/a.txt")?.read_to_string()?, "a");
+/// root.join("tmp/scratch.txt")?.create_file()?;
+///
+/// mount_fs.unmount("/tmp")?;
+/// assert!(!root.join("tmp")?.exists()?);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MountFS {
+    mounts: Arc<RwLock<BTreeMap<String, VfsPath>>>,
+}
+
+impl MountFS {
+    /// Create a new mount filesystem without any mounts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mounts the given filesystem at `mount_point`
+    ///
+    /// The mount point `"/"` (or `""`) mounts the filesystem at the root.
+    /// Returns an error if there is already a filesystem mounted at this mount point.
+    pub fn mount(&self, mount_point: &str, filesystem: impl Into<VfsPath>) -> VfsResult<()> {
+        let mount_point = normalize_mount_point(mount_point)?;
+        let mut mounts = self.mounts.write().unwrap();
+        if mounts.contains_key(&mount_point) {
+            return Err(VfsError::from(VfsErrorKind::Other(
+                "Mount point is already in use".into(),
+            ))
+            .with_path(mount_point));
+        }
+        mounts.insert(mount_point, filesystem.into());
+        Ok(())
+    }
+
+    /// Unmounts the filesystem at `mount_point`, returning it
+    pub fn unmount(&self, mount_point: &str) -> VfsResult<VfsPath> {
+        let mount_point = normalize_mount_point(mount_point)?;
+        self.mounts
+            .write()
+            .unwrap()
+            .remove(&mount_point)
+            .ok_or_else(|| {
+                VfsError::from(VfsErrorKind::Other("Nothing is mounted here".into()))
+                    .with_path(mount_point)
+            })
+    }
+
+    /// Returns all current mount points in sorted order, the root mount point is `""`
+    pub fn mount_points(&self) -> Vec<String> {
+        self.mounts.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Returns the mount point and the path in the mounted filesystem for the given path
+    fn resolve(&self, path: &str) -> VfsResult<(String, VfsPath)> {
+        let mounts = self.mounts.read().unwrap();
+        let (mount_point, root) = mounts
+            .iter()
+            .filter(|(mount_point, _)| is_below(path, mount_point))
+            .max_by_key(|(mount_point, _)| mount_point.len())
+            .ok_or(VfsErrorKind::FileNotFound)?;
+        let rest = &path[mount_point.len()..];
+        Ok((
+            mount_point.clone(),
+            root.join(rest.trim_start_matches('/'))?,
+        ))
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        self.resolve(path).map(|(_, path)| path)
+    }
+
+    /// Returns true if the path is a mount point or a directory leading up to one
+    fn is_mount_directory(&self, path: &str) -> bool {
+        path.is_empty()
+            || self
+                .mounts
+                .read()
+                .unwrap()
+                .keys()
+                .any(|mount_point| is_below(mount_point, path))
+    }
+
+    /// Returns the names of the mount points (or directories leading up to them) directly below the given path
+    fn mount_children(&self, path: &str) -> BTreeSet<String> {
+        let prefix = format!("{path}/");
+        self.mounts
+            .read()
+            .unwrap()
+            .keys()
+            .filter_map(|mount_point| mount_point.strip_prefix(&prefix))
+            .map(|rest| rest.split('/').next().unwrap_or(rest).to_string())
+            .collect()
+    }
+
+    fn ensure_not_mount_directory(&self, path: &str) -> VfsResult<()> {
+        if self.is_mount_directory(path) {
+            return Err(VfsErrorKind::Other("Path is a mount point".into()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if path is equal to or below the given directory
+fn is_below(path: &str, directory: &str) -> bool {
+    match path.strip_prefix(directory) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn normalize_mount_point(mount_point: &str) -> VfsResult<String> {
+    let mut normalized = String::with_capacity(mount_point.len() + 1);
+    for component in mount_point
+        .split('/')
+        .filter(|component| !component.is_empty())
+    {
+        if component == "." || component == ".." {
+            return Err(VfsError::from(VfsErrorKind::InvalidPath).with_path(mount_point));
+        }
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    Ok(normalized)
+}
+
+impl FileSystem for MountFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let mut entries = self.mount_children(path);
+        let mounted_path = self.path(path).ok();
+        match mounted_path {
+            Some(mounted_path) if mounted_path.exists()? => {
+                entries.extend(mounted_path.read_dir()?.map(|path| path.filename()));
+            }
+            _ => {
+                if !self.is_mount_directory(path) {
+                    return Err(VfsErrorKind::FileNotFound.into());
+                }
+            }
+        }
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        if self.is_mount_directory(path) {
+            return Err(VfsErrorKind::DirectoryExists.into());
+        }
+        self.path(path)?.create_dir()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        self.path(path)?.open_file()
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.ensure_not_mount_directory(path)?;
+        self.path(path)?.create_file()
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.ensure_not_mount_directory(path)?;
+        self.path(path)?.append_file()
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        if let Ok(mounted_path) = self.path(path) {
+            if mounted_path.exists()? {
+                return mounted_path.metadata();
+            }
+        }
+        if self.is_mount_directory(path) {
+            return Ok(VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                created: None,
+                modified: None,
+                accessed: None,
+            });
+        }
+        Err(VfsErrorKind::FileNotFound.into())
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_creation_time(time)
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_modification_time(time)
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_access_time(time)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        if self.is_mount_directory(path) {
+            return Ok(true);
+        }
+        self.path(path)
+            .map(|path| path.exists())
+            .unwrap_or(Ok(false))
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.ensure_not_mount_directory(path)?;
+        self.path(path)?.remove_file()
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.ensure_not_mount_directory(path)?;
+        self.path(path)?.remove_dir()
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let (src_mount, src_path) = self.resolve(src)?;
+        let (dest_mount, dest_path) = self.resolve(dest)?;
+        if src_mount != dest_mount {
+            return Err(VfsErrorKind::CrossMount.into());
+        }
+        src_path.copy_file(&dest_path)
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let (src_mount, src_path) = self.resolve(src)?;
+        let (dest_mount, dest_path) = self.resolve(dest)?;
+        if src_mount != dest_mount {
+            return Err(VfsErrorKind::CrossMount.into());
+        }
+        src_path.move_file(&dest_path)
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        if !self.mount_children(src).is_empty() {
+            // Mounts nested inside the source directory can not be moved along, and copying
+            // the directory instead would empty the nested filesystems
+            return Err(VfsErrorKind::Other("Directory contains a mount point".into()).into());
+        }
+        self.ensure_not_mount_directory(src)?;
+        let (src_mount, src_path) = self.resolve(src)?;
+        let (dest_mount, dest_path) = self.resolve(dest)?;
+        if src_mount != dest_mount || self.is_mount_directory(dest) {
+            return Err(VfsErrorKind::CrossMount.into());
+        }
+        src_path.move_dir(&dest_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryFS, PhysicalFS};
+    test_vfs!({
+        let mount_fs = MountFS::new();
+        mount_fs.mount("/", MemoryFS::new()).unwrap();
+        mount_fs
+    });
+
+    fn create_roots() -> (MountFS, VfsPath, VfsPath, VfsPath) {
+        let mount_fs = MountFS::new();
+        let root_fs: VfsPath = MemoryFS::new().into();
+        let tmp_fs: VfsPath = MemoryFS::new().into();
+        mount_fs.mount("/", root_fs.clone()).unwrap();
+        mount_fs.mount("/var/tmp", tmp_fs.clone()).unwrap();
+        let root: VfsPath = mount_fs.clone().into();
+        (mount_fs, root_fs, tmp_fs, root)
+    }
+
+    #[test]
+    fn longest_prefix_wins() -> VfsResult<()> {
+        let (_mount_fs, root_fs, tmp_fs, root) = create_roots();
+        root.join("var/tmp/foo.txt")?
+            .create_file()?
+            .write_all(b"tmp")?;
+        root.join("foo.txt")?.create_file()?.write_all(b"root")?;
+        assert_eq!(&tmp_fs.join("foo.txt")?.read_to_string()?, "tmp");
+        assert_eq!(&root_fs.join("foo.txt")?.read_to_string()?, "root");
+        assert!(!root_fs.join("var")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn mount_points_are_directories() -> VfsResult<()> {
+        let (_mount_fs, _root_fs, _tmp_fs, root) = create_roots();
+        root.join("etc")?.create_dir()?;
+        let mut entries: Vec<_> = root.read_dir()?.map(|path| path.filename()).collect();
+        entries.sort();
+        assert_eq!(entries, vec!["etc", "var"]);
+        assert!(root.join("var")?.is_dir()?);
+        assert!(root.join("var/tmp")?.is_dir()?);
+        let entries: Vec<_> = root
+            .join("var")?
+            .read_dir()?
+            .map(|path| path.filename())
+            .collect();
+        assert_eq!(entries, vec!["tmp"]);
+        Ok(())
+    }
+
+    #[test]
+    fn without_root_mount() -> VfsResult<()> {
+        let mount_fs = MountFS::new();
+        mount_fs.mount("/data", PhysicalFS::new("test/test_directory"))?;
+        let root: VfsPath = mount_fs.into();
+        assert!(root.exists()?);
+        assert!(root.is_dir()?);
+        let entries: Vec<_> = root.read_dir()?.map(|path| path.filename()).collect();
+        assert_eq!(entries, vec!["data"]);
+        assert_eq!(&root.join("data/a.txt")?.read_to_string()?, "a");
+        assert!(!root.join("foo")?.exists()?);
+        assert!(root.join("foo")?.create_dir().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mount_and_unmount() -> VfsResult<()> {
+        let (mount_fs, _root_fs, _tmp_fs, root) = create_roots();
+        let data_fs: VfsPath = MemoryFS::new().into();
+        data_fs.join("foo.txt")?.create_file()?;
+        mount_fs.mount("data/", data_fs.clone())?;
+        assert_eq!(mount_fs.mount_points(), vec!["", "/data", "/var/tmp"]);
+        assert!(root.join("data/foo.txt")?.exists()?);
+
+        let error = mount_fs.mount("/data", MemoryFS::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "An error occurred for '/data': FileSystem error: Mount point is already in use"
+        );
+
+        assert_eq!(mount_fs.unmount("/data")?, data_fs);
+        assert!(!root.join("data")?.exists()?);
+        assert!(mount_fs.unmount("/data").is_err());
+        assert!(mount_fs.mount("/foo/../bar", MemoryFS::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cross_mount_move_falls_back_to_copy() -> VfsResult<()> {
+        let (_mount_fs, root_fs, tmp_fs, root) = create_roots();
+        let src = root.join("foo.txt")?;
+        src.create_file()?.write_all(b"Hello World")?;
+        let dest = root.join("var/tmp/bar.txt")?;
+        assert!(matches!(
+            root.join("var")?
+                .metadata()
+                .map(|metadata| metadata.file_type),
+            Ok(VfsFileType::Directory)
+        ));
+        src.move_file(&dest)?;
+        assert!(!root_fs.join("foo.txt")?.exists()?);
+        assert_eq!(&tmp_fs.join("bar.txt")?.read_to_string()?, "Hello World");
+
+        root.join("dir/sub")?.create_dir_all()?;
+        root.join("dir/sub/a.txt")?.create_file()?;
+        root.join("dir")?.move_dir(&root.join("var/tmp/dir")?)?;
+        assert!(tmp_fs.join("dir/sub/a.txt")?.exists()?);
+        assert!(!root.join("dir")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn cross_mount_error() -> VfsResult<()> {
+        let (mount_fs, _root_fs, _tmp_fs, root) = create_roots();
+        root.join("foo.txt")?.create_file()?;
+        let result = mount_fs.move_file("/foo.txt", "/var/tmp/foo.txt");
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            VfsErrorKind::CrossMount
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn directories_containing_mount_points_can_not_be_moved() -> VfsResult<()> {
+        let (mount_fs, root_fs, _tmp_fs, root) = create_roots();
+        root.join("data")?.create_dir()?;
+        root.join("data/a.txt")?.create_file()?.write_all(b"a")?;
+        let cache_fs: VfsPath = MemoryFS::new().into();
+        cache_fs.join("b.txt")?.create_file()?.write_all(b"b")?;
+        mount_fs.mount("/data/cache", cache_fs.clone())?;
+
+        let error = root
+            .join("data")?
+            .move_dir(&root.join("moved")?)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not move directory '/data' to '/moved' for '/data': FileSystem error: Directory contains a mount point"
+        );
+        assert_eq!(cache_fs.join("b.txt")?.read_to_string()?, "b");
+        assert_eq!(root_fs.join("data/a.txt")?.read_to_string()?, "a");
+        assert!(!root.join("moved")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn mount_points_can_not_be_removed() -> VfsResult<()> {
+        let (_mount_fs, _root_fs, _tmp_fs, root) = create_roots();
+        let error = root.join("var/tmp")?.remove_dir().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not remove directory for '/var/tmp': FileSystem error: Path is a mount point"
+        );
+        assert!(root.join("var")?.remove_dir_all().is_err());
+        assert!(root.join("var/tmp")?.exists()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_readonly {
+    use super::*;
+    use crate::PhysicalFS;
+    test_vfs_readonly!({
+        let mount_fs = MountFS::new();
+        mount_fs
+            .mount("/", PhysicalFS::new("test/test_directory"))
+            .unwrap();
+        mount_fs
+            .mount("/a/x", PhysicalFS::new("test/test_directory/a/x"))
+            .unwrap();
+        mount_fs
+    });
+}
@@ -1,8 +1,16 @@
 //! An overlay file system combining two filesystems, an upper layer with read/write access and a lower layer with only read access
 
 use crate::error::VfsErrorKind;
-use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult};
+use crate::impls::altroot::strip_root;
+use crate::watch::is_watched;
+use crate::{
+    FileSystem, SeekAndRead, SeekAndWrite, VfsFileType, VfsMetadata, VfsPath, VfsResult, WatchEvent,
+};
 use std::collections::HashSet;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
 
 use std::time::SystemTime;
 
@@ -10,60 +18,201 @@ use std::time::SystemTime;
 ///
 /// Files in upper layers shadow those in lower layers. Directories are the merged view of all layers.
 ///
+/// By default all writes go to the first layer. A [`WriteRoutingPolicy`] can be used to send writes for certain paths
+/// to other layers instead (see [`OverlayFS::with_write_policy()`]).
+///
 /// NOTE: To allow removing files and directories (e.g. via remove_file()) from the lower layer filesystems, this mechanism creates a `.whiteout` folder in the root of the upper level filesystem to mark removed files
 ///
+/// Before a file or directory that only exists in a lower layer is modified (e.g. via append_file() or set_modification_time()),
+/// it is first copied up to the upper layer, preserving its timestamps where the upper layer supports them.
+///
+/// Watching an overlay watches the path in every layer and reports the changes of each layer as they happen,
+/// so copying a file up is reported as its creation. Marking a file as removed is reported as its removal.
+/// Layers that cannot be watched, like an `EmbeddedFS`, are skipped.
+///
 #[derive(Debug, Clone)]
 pub struct OverlayFS {
     layers: Vec<VfsPath>,
+    policy: Arc<dyn WriteRoutingPolicy>,
+}
+
+/// Decides which layer of an [`OverlayFS`] receives the writes to a given path
+///
+/// The path is absolute, as passed to the [`FileSystem`] methods.
+pub trait WriteRoutingPolicy: Debug + Sync + Send + 'static {
+    /// Returns the index of the layer that writes to this path should go to
+    fn route(&self, path: &str) -> usize;
+}
+
+/// A [`WriteRoutingPolicy`] sending all writes to the first layer, used by [`OverlayFS::new()`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpperLayerPolicy;
+
+impl WriteRoutingPolicy for UpperLayerPolicy {
+    fn route(&self, _path: &str) -> usize {
+        0
+    }
+}
+
+/// A rule based [`WriteRoutingPolicy`]
+///
+/// Rules are checked in the order they were added, the first matching rule decides the layer.
+/// Paths not matching any rule are written to the first layer.
+///
+/// ```
+/// # use vfs::{MemoryFS, OverlayFS, VfsError, VfsPath};
+/// use vfs::impls::overlay::RoutingRules;
+/// let upper: VfsPath = MemoryFS::new().into();
+/// let scratch: VfsPath = MemoryFS::new().into();
+/// let cache: VfsPath = MemoryFS::new().into();
+/// let rules = RoutingRules::new()
+///     .route_extension("log", 1)
+///     .route_prefix("/cache", 2);
+/// let root: VfsPath = OverlayFS::with_write_policy(&[upper.clone(), scratch.clone(), cache.clone()], rules).into();
+///
+/// root.join("server.log")?.create_file()?;
+/// root.join("cache")?.create_dir()?;
+/// root.join("cache/data.bin")?.create_file()?;
+/// root.join("README.md")?.create_file()?;
+///
+/// assert!(scratch.join("server.log")?.exists()?);
+/// assert!(cache.join("cache/data.bin")?.exists()?);
+/// assert!(upper.join("README.md")?.exists()?);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RoutingRules {
+    rules: Vec<(RoutingRule, usize)>,
+}
+
+#[derive(Debug, Clone)]
+enum RoutingRule {
+    Prefix(String),
+    Extension(String),
+}
+
+impl RoutingRules {
+    /// Creates an empty rule set, routing everything to the first layer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes the directory at `prefix` and everything below it to the given layer
+    pub fn route_prefix(mut self, prefix: impl Into<String>, layer: usize) -> Self {
+        let mut prefix = prefix.into();
+        if !prefix.is_empty() && !prefix.starts_with('/') {
+            prefix.insert(0, '/');
+        }
+        while prefix.ends_with('/') {
+            prefix.pop();
+        }
+        self.rules.push((RoutingRule::Prefix(prefix), layer));
+        self
+    }
+
+    /// Routes all files with the given extension (without the leading '.') to the given layer
+    pub fn route_extension(mut self, extension: impl Into<String>, layer: usize) -> Self {
+        self.rules
+            .push((RoutingRule::Extension(extension.into()), layer));
+        self
+    }
+}
+
+impl RoutingRule {
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            RoutingRule::Prefix(prefix) => {
+                path.starts_with(prefix.as_str())
+                    && (path.len() == prefix.len() || path[prefix.len()..].starts_with('/'))
+            }
+            RoutingRule::Extension(extension) => {
+                let filename = &path[path.rfind('/').map(|index| index + 1).unwrap_or(0)..];
+                match filename.rsplit_once('.') {
+                    Some((stem, suffix)) => !stem.is_empty() && suffix == extension,
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+impl WriteRoutingPolicy for RoutingRules {
+    fn route(&self, path: &str) -> usize {
+        self.rules
+            .iter()
+            .find(|(rule, _)| rule.matches(path))
+            .map(|(_, layer)| *layer)
+            .unwrap_or(0)
+    }
 }
 
 impl OverlayFS {
     /// Create a new overlay FileSystem from the given layers, only the first layer is written to
     pub fn new(layers: &[VfsPath]) -> Self {
+        Self::with_write_policy(layers, UpperLayerPolicy)
+    }
+
+    /// Create a new overlay FileSystem from the given layers, writes are sent to the layer chosen by `policy`
+    ///
+    /// Whiteouts for removed files are always kept in the first layer.
+    /// A file written to a layer is read back from that layer, even if an earlier layer contains the same path.
+    pub fn with_write_policy<P: WriteRoutingPolicy>(layers: &[VfsPath], policy: P) -> Self {
         if layers.is_empty() {
             panic!("OverlayFS needs at least one layer")
         }
         OverlayFS {
             layers: layers.to_vec(),
+            policy: Arc::new(policy),
         }
     }
 
-    fn write_layer(&self) -> &VfsPath {
+    fn upper_layer(&self) -> &VfsPath {
         &self.layers[0]
     }
 
+    fn write_layer(&self, path: &str) -> VfsResult<&VfsPath> {
+        let index = self.policy.route(path);
+        self.layers.get(index).ok_or_else(|| {
+            VfsErrorKind::Other(format!(
+                "Write routing policy returned layer {index}, but there are only {} layers",
+                self.layers.len()
+            ))
+            .into()
+        })
+    }
+
     fn read_path(&self, path: &str) -> VfsResult<VfsPath> {
         if path.is_empty() {
-            return Ok(self.layers[0].clone());
+            return Ok(self.upper_layer().clone());
         }
         if self.whiteout_path(path)?.exists()? {
             return Err(VfsErrorKind::FileNotFound.into());
         }
+        let write_path = self.write_path(path)?;
+        if write_path.exists()? {
+            return Ok(write_path);
+        }
         for layer in &self.layers {
             let layer_path = layer.join(&path[1..])?;
             if layer_path.exists()? {
                 return Ok(layer_path);
             }
         }
-        let read_path = self.write_layer().join(&path[1..])?;
-        if !read_path.exists()? {
-            return Err(VfsErrorKind::FileNotFound.into());
-        }
-        Ok(read_path)
+        Err(VfsErrorKind::FileNotFound.into())
     }
 
     fn write_path(&self, path: &str) -> VfsResult<VfsPath> {
         if path.is_empty() {
-            return Ok(self.layers[0].clone());
+            return Ok(self.upper_layer().clone());
         }
-        self.write_layer().join(&path[1..])
+        self.write_layer(path)?.join(&path[1..])
     }
 
     fn whiteout_path(&self, path: &str) -> VfsResult<VfsPath> {
         if path.is_empty() {
-            return self.write_layer().join(".whiteout/_wo");
+            return self.upper_layer().join(".whiteout/_wo");
         }
-        self.write_layer()
+        self.upper_layer()
             .join(format!(".whiteout/{}_wo", &path[1..]))
     }
 
@@ -72,12 +221,55 @@ impl OverlayFS {
         if let Some(index) = separator {
             let parent_path = &path[..index];
             if self.exists(parent_path)? {
-                self.write_path(parent_path)?.create_dir_all()?;
+                // The parent has to exist in the layer the path itself is written to
+                self.write_layer(path)?
+                    .join(parent_path.trim_start_matches('/'))?
+                    .create_dir_all()?;
                 return Ok(());
             }
         }
         Err(VfsErrorKind::Other("Parent path does not exist".into()).into())
     }
+
+    /// Copies a file or directory that only exists in a lower layer into the write layer
+    ///
+    /// This must happen before any mutation of content or metadata, so that the lower layers stay untouched
+    fn copy_up(&self, path: &str) -> VfsResult<VfsPath> {
+        let write_path = self.write_path(path)?;
+        if write_path.exists()? {
+            return Ok(write_path);
+        }
+        let read_path = self.read_path(path)?;
+        self.ensure_has_parent(path)?;
+        let metadata = read_path.metadata()?;
+        match metadata.file_type {
+            VfsFileType::File => read_path.copy_file(&write_path)?,
+            VfsFileType::Directory => write_path.create_dir()?,
+        }
+        copy_timestamps(&metadata, &write_path)?;
+        Ok(write_path)
+    }
+}
+
+/// Transfers the timestamps of the given metadata, ignoring those the destination does not support
+fn copy_timestamps(metadata: &VfsMetadata, destination: &VfsPath) -> VfsResult<()> {
+    let ignore_unsupported = |result: VfsResult<()>| match result {
+        Err(err) => match err.kind() {
+            VfsErrorKind::NotSupported => Ok(()),
+            _ => Err(err),
+        },
+        ok => ok,
+    };
+    if let Some(created) = metadata.created {
+        ignore_unsupported(destination.set_creation_time(created))?;
+    }
+    if let Some(modified) = metadata.modified {
+        ignore_unsupported(destination.set_modification_time(modified))?;
+    }
+    if let Some(accessed) = metadata.accessed {
+        ignore_unsupported(destination.set_access_time(accessed))?;
+    }
+    Ok(())
 }
 
 impl FileSystem for OverlayFS {
@@ -96,7 +288,7 @@ impl FileSystem for OverlayFS {
             }
         }
         // remove whiteout entries that have been removed
-        let whiteout_path = self.write_layer().join(format!(".whiteout{path}"))?;
+        let whiteout_path = self.upper_layer().join(format!(".whiteout{path}"))?;
         if whiteout_path.exists()? {
             for path in whiteout_path.read_dir()? {
                 let filename = path.filename();
@@ -133,12 +325,7 @@ impl FileSystem for OverlayFS {
     }
 
     fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
-        let write_path = self.write_path(path)?;
-        if !write_path.exists()? {
-            self.ensure_has_parent(path)?;
-            self.read_path(path)?.copy_file(&write_path)?;
-        }
-        write_path.append_file()
+        self.copy_up(path)?.append_file()
     }
 
     fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
@@ -146,15 +333,15 @@ impl FileSystem for OverlayFS {
     }
 
     fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
-        self.write_path(path)?.set_creation_time(time)
+        self.copy_up(path)?.set_creation_time(time)
     }
 
     fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
-        self.write_path(path)?.set_modification_time(time)
+        self.copy_up(path)?.set_modification_time(time)
     }
 
     fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
-        self.write_path(path)?.set_access_time(time)
+        self.copy_up(path)?.set_access_time(time)
     }
 
     fn exists(&self, path: &str) -> VfsResult<bool> {
@@ -195,6 +382,158 @@ impl FileSystem for OverlayFS {
         whiteout_path.create_file()?;
         Ok(())
     }
+
+    fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        // Ensure path exists
+        self.read_path(path)?;
+        let mut watches = Vec::new();
+        for (index, layer) in self.layers.iter().enumerate() {
+            let mut targets = Vec::new();
+            for (target, target_recursive) in watch_targets(index, path, recursive) {
+                targets.push(existing_target(layer, target, target_recursive)?);
+            }
+            for (target, target_recursive) in merge_targets(targets) {
+                let events = match layer
+                    .join(target.trim_start_matches('/'))?
+                    .watch(target_recursive)
+                {
+                    Ok(events) => events,
+                    // Layers that cannot be watched, like embedded files, are not expected to change
+                    Err(err) if matches!(err.kind(), VfsErrorKind::NotSupported) => continue,
+                    Err(err) => return Err(err),
+                };
+                watches.push((events, index, layer.as_str().to_string()));
+            }
+        }
+        let watched = path.to_string();
+        if watches.len() <= 1 {
+            let (events, index, layer_root) = watches.pop().ok_or(VfsErrorKind::NotSupported)?;
+            return Ok(Box::new(events.filter_map(move |event| {
+                translate_layer_event(event, index, &layer_root, &watched, recursive)
+            })));
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        for (events, index, layer_root) in watches {
+            let (stop, sender, watched) = (stop.clone(), sender.clone(), watched.clone());
+            thread::spawn(move || {
+                for event in events {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let event =
+                        translate_layer_event(event, index, &layer_root, &watched, recursive);
+                    if let Some(event) = event {
+                        if sender.send(event).is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+        Ok(Box::new(OverlayEvents { receiver, stop }))
+    }
+}
+
+/// The events of several watched layers, forwarded by a thread per layer
+///
+/// Dropping it stops the forwarding threads, each at the next event of its layer.
+struct OverlayEvents {
+    receiver: mpsc::Receiver<WatchEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Iterator for OverlayEvents {
+    type Item = WatchEvent;
+
+    fn next(&mut self) -> Option<WatchEvent> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for OverlayEvents {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The paths of a layer to watch for changes to `path`, each with whether to watch it recursively
+///
+/// The upper layer also holds the whiteouts of the path and of its entries.
+pub(crate) fn watch_targets(layer: usize, path: &str, recursive: bool) -> Vec<(String, bool)> {
+    let mut targets = vec![(path.to_string(), recursive)];
+    if layer == 0 {
+        if let Some(index) = path.rfind('/') {
+            targets.push((format!("/.whiteout{}", &path[..index]), false));
+        }
+        targets.push((format!("/.whiteout{path}"), recursive));
+    }
+    targets
+}
+
+/// Replaces a target missing in the layer by its closest existing ancestor
+///
+/// The ancestor is watched recursively, so that the target is seen once it is created.
+fn existing_target(layer: &VfsPath, target: String, recursive: bool) -> VfsResult<(String, bool)> {
+    let (mut target, mut recursive) = (target, recursive);
+    while !target.is_empty() && !layer.join(&target[1..])?.exists()? {
+        target.truncate(target.rfind('/').unwrap_or(0));
+        recursive = true;
+    }
+    Ok((target, recursive))
+}
+
+/// Drops the targets already covered by another target
+pub(crate) fn merge_targets(mut targets: Vec<(String, bool)>) -> Vec<(String, bool)> {
+    // Ancestors come before their descendants, recursive watches before equal non recursive ones
+    targets.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    let mut merged: Vec<(String, bool)> = Vec::with_capacity(targets.len());
+    for (target, recursive) in targets {
+        let covered = merged.iter().any(|(other, other_recursive)| {
+            if *other_recursive {
+                is_watched(&target, other, true)
+            } else {
+                *other == target && !recursive
+            }
+        });
+        if !covered {
+            merged.push((target, recursive));
+        }
+    }
+    merged
+}
+
+/// Translates an event of the given layer to the overlay, None if the watch does not report it
+///
+/// Whiteout files created in the upper layer are reported as the removal of the file they hide,
+/// other changes to the whiteout directory are not reported.
+pub(crate) fn translate_layer_event(
+    event: WatchEvent,
+    layer: usize,
+    layer_root: &str,
+    watched: &str,
+    recursive: bool,
+) -> Option<WatchEvent> {
+    let visible = |path: &str| {
+        strip_root(layer_root, path).filter(|path| {
+            !(layer == 0 && is_watched(path, "/.whiteout", true))
+                && is_watched(path, watched, recursive)
+        })
+    };
+    if let (0, WatchEvent::Created(path)) = (layer, &event) {
+        let hidden = strip_root(layer_root, path).and_then(|path| {
+            let whiteout = path.strip_prefix("/.whiteout/")?.strip_suffix("_wo")?;
+            Some(format!("/{whiteout}"))
+        });
+        if let Some(hidden) = hidden {
+            return is_watched(&hidden, watched, recursive).then_some(WatchEvent::Removed(hidden));
+        }
+    }
+    event.translate(visible)
 }
 
 #[cfg(test)]
@@ -299,6 +638,79 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn append_file_preserves_timestamps() -> VfsResult<()> {
+        let (lower_root, upper_root, overlay_root) = create_roots();
+        let lower_path = lower_root.join("bar.txt")?;
+        lower_path.create_file()?.write_all(b"Hello Lower\n")?;
+        let created = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        lower_path.set_creation_time(created)?;
+        overlay_root
+            .join("bar.txt")?
+            .append_file()?
+            .write_all(b"Hello Overlay\n")?;
+        assert_eq!(
+            upper_root.join("bar.txt")?.metadata()?.created,
+            Some(created)
+        );
+        assert_eq!(&lower_path.read_to_string()?, "Hello Lower\n");
+        Ok(())
+    }
+
+    #[test]
+    fn set_times_on_lower_file() -> VfsResult<()> {
+        let (lower_root, upper_root, overlay_root) = create_roots();
+        lower_root.join("foo")?.create_dir_all()?;
+        let lower_path = lower_root.join("foo/bar.txt")?;
+        lower_path.create_file()?.write_all(b"Hello Lower\n")?;
+        let accessed = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        lower_path.set_access_time(accessed)?;
+        let lower_modified = lower_path.metadata()?.modified;
+
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2_000_000);
+        let overlay_path = overlay_root.join("foo/bar.txt")?;
+        overlay_path.set_modification_time(modified)?;
+
+        let metadata = overlay_path.metadata()?;
+        assert_eq!(metadata.modified, Some(modified));
+        assert_eq!(metadata.accessed, Some(accessed));
+        assert_eq!(&overlay_path.read_to_string()?, "Hello Lower\n");
+        assert!(upper_root.join("foo/bar.txt")?.exists()?);
+        assert_eq!(lower_path.metadata()?.modified, lower_modified);
+        Ok(())
+    }
+
+    #[test]
+    fn set_times_on_lower_dir() -> VfsResult<()> {
+        let (lower_root, upper_root, overlay_root) = create_roots();
+        lower_root.join("foo/bar")?.create_dir_all()?;
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2_000_000);
+        overlay_root
+            .join("foo/bar")?
+            .set_modification_time(modified)?;
+        assert!(upper_root.join("foo/bar")?.is_dir()?);
+        assert_eq!(
+            overlay_root.join("foo/bar")?.metadata()?.modified,
+            Some(modified)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn set_times_on_removed_file() -> VfsResult<()> {
+        let (lower_root, _upper_root, overlay_root) = create_roots();
+        lower_root.join("bar.txt")?.create_file()?;
+        overlay_root.join("bar.txt")?.remove_file()?;
+        let result = overlay_root
+            .join("bar.txt")?
+            .set_modification_time(SystemTime::now());
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            VfsErrorKind::FileNotFound
+        ));
+        Ok(())
+    }
+
     #[test]
     fn remove_file() -> VfsResult<()> {
         let (lower_root, _upper_root, overlay_root) = create_roots();
@@ -372,6 +784,249 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn watch_reports_whiteouts_as_removals() -> VfsResult<()> {
+        let (lower_root, upper_root, overlay_root) = create_roots();
+        lower_root.join("foo")?.create_dir()?;
+        lower_root.join("foo/bar.txt")?.create_file()?;
+        let events = overlay_root.join("foo")?.watch(false)?;
+        overlay_root.join("foo/bar.txt")?.remove_file()?;
+        overlay_root.join("foo/baz.txt")?.create_file()?;
+        drop((lower_root, upper_root, overlay_root));
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![
+                WatchEvent::Removed("/foo/bar.txt".into()),
+                WatchEvent::Created("/foo".into()),
+                WatchEvent::Created("/foo/baz.txt".into()),
+                WatchEvent::Modified("/foo/baz.txt".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watch_skips_layers_without_watching() -> VfsResult<()> {
+        let upper_root: VfsPath = MemoryFS::new().into();
+        let lower_root: VfsPath = crate::FaultyFS::new(MemoryFS::new().into()).into();
+        lower_root.join("foo")?.create_dir()?;
+        let overlay_root: VfsPath = OverlayFS::new(&[upper_root.clone(), lower_root]).into();
+        let events = overlay_root.join("foo")?.watch(false)?;
+        overlay_root.join("foo/bar.txt")?.create_file()?;
+        drop((upper_root, overlay_root));
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![
+                WatchEvent::Created("/foo".into()),
+                WatchEvent::Created("/foo/bar.txt".into()),
+                WatchEvent::Modified("/foo/bar.txt".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watch_targets_cover_the_path_and_its_whiteouts() {
+        assert_eq!(
+            merge_targets(watch_targets(0, "/foo/bar", false)),
+            vec![
+                ("/.whiteout/foo".to_string(), false),
+                ("/.whiteout/foo/bar".to_string(), false),
+                ("/foo/bar".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            merge_targets(watch_targets(1, "/foo/bar", true)),
+            vec![("/foo/bar".to_string(), true)]
+        );
+        assert_eq!(
+            merge_targets(vec![
+                ("/foo".to_string(), false),
+                ("".to_string(), true),
+                ("/foo".to_string(), true),
+            ]),
+            vec![("".to_string(), true)]
+        );
+        assert_eq!(
+            merge_targets(vec![
+                ("/foo/bar".to_string(), false),
+                ("/foo".to_string(), false),
+                ("/foo".to_string(), true),
+            ]),
+            vec![("/foo".to_string(), true)]
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_routed {
+    use super::*;
+    use crate::MemoryFS;
+    test_vfs!({
+        let upper_root: VfsPath = MemoryFS::new().into();
+        let scratch_root: VfsPath = MemoryFS::new().into();
+        let lower_root: VfsPath = MemoryFS::new().into();
+        let rules = RoutingRules::new().route_extension("txt", 1);
+        OverlayFS::with_write_policy(&[upper_root, scratch_root, lower_root], rules)
+    });
+
+    fn create_roots() -> (VfsPath, VfsPath, VfsPath, VfsPath, VfsPath) {
+        let upper_root: VfsPath = MemoryFS::new().into();
+        let scratch_root: VfsPath = MemoryFS::new().into();
+        let cache_root: VfsPath = MemoryFS::new().into();
+        let lower_root: VfsPath = MemoryFS::new().into();
+        let rules = RoutingRules::new()
+            .route_extension("log", 1)
+            .route_prefix("cache/", 2);
+        let overlay_root: VfsPath = OverlayFS::with_write_policy(
+            &[
+                upper_root.clone(),
+                scratch_root.clone(),
+                cache_root.clone(),
+                lower_root.clone(),
+            ],
+            rules,
+        )
+        .into();
+        (
+            upper_root,
+            scratch_root,
+            cache_root,
+            lower_root,
+            overlay_root,
+        )
+    }
+
+    #[test]
+    fn routing_rules() {
+        let rules = RoutingRules::new()
+            .route_extension("log", 1)
+            .route_prefix("/cache", 2);
+        assert_eq!(rules.route("/server.log"), 1);
+        assert_eq!(rules.route("/foo/server.log"), 1);
+        assert_eq!(rules.route("/.log"), 0);
+        assert_eq!(rules.route("/cache"), 2);
+        assert_eq!(rules.route("/cache/foo.bin"), 2);
+        assert_eq!(rules.route("/cache/foo.log"), 1);
+        assert_eq!(rules.route("/cached"), 0);
+        assert_eq!(rules.route("/foo.txt"), 0);
+    }
+
+    #[test]
+    fn writes_are_routed() -> VfsResult<()> {
+        let (upper_root, scratch_root, cache_root, _lower_root, overlay_root) = create_roots();
+        overlay_root.join("foo")?.create_dir()?;
+        overlay_root.join("foo/server.log")?.create_file()?;
+        overlay_root.join("foo/bar.txt")?.create_file()?;
+        overlay_root.join("cache")?.create_dir()?;
+        overlay_root
+            .join("cache/data.bin")?
+            .create_file()?
+            .write_all(b"cached")?;
+
+        assert!(scratch_root.join("foo/server.log")?.exists()?);
+        assert!(!upper_root.join("foo/server.log")?.exists()?);
+        assert!(upper_root.join("foo/bar.txt")?.exists()?);
+        assert!(cache_root.join("cache/data.bin")?.exists()?);
+        assert!(!upper_root.join("cache")?.exists()?);
+
+        let mut entries: Vec<_> = overlay_root
+            .join("foo")?
+            .read_dir()?
+            .map(|path| path.filename())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, vec!["bar.txt", "server.log"]);
+        assert_eq!(
+            &overlay_root.join("cache/data.bin")?.read_to_string()?,
+            "cached"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn routed_write_shadows_lower_layers() -> VfsResult<()> {
+        let (upper_root, scratch_root, _cache_root, lower_root, overlay_root) = create_roots();
+        upper_root
+            .join("app.log")?
+            .create_file()?
+            .write_all(b"upper\n")?;
+        lower_root
+            .join("app.log")?
+            .create_file()?
+            .write_all(b"lower\n")?;
+        overlay_root
+            .join("app.log")?
+            .create_file()?
+            .write_all(b"scratch\n")?;
+        assert_eq!(
+            &overlay_root.join("app.log")?.read_to_string()?,
+            "scratch\n"
+        );
+        assert_eq!(
+            &scratch_root.join("app.log")?.read_to_string()?,
+            "scratch\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn append_copies_up_to_routed_layer() -> VfsResult<()> {
+        let (upper_root, scratch_root, _cache_root, lower_root, overlay_root) = create_roots();
+        lower_root.join("logs")?.create_dir()?;
+        lower_root
+            .join("logs/app.log")?
+            .create_file()?
+            .write_all(b"Hello Lower\n")?;
+        overlay_root
+            .join("logs/app.log")?
+            .append_file()?
+            .write_all(b"Hello Overlay\n")?;
+        assert_eq!(
+            &scratch_root.join("logs/app.log")?.read_to_string()?,
+            "Hello Lower\nHello Overlay\n"
+        );
+        assert!(!upper_root.join("logs/app.log")?.exists()?);
+        assert_eq!(
+            &lower_root.join("logs/app.log")?.read_to_string()?,
+            "Hello Lower\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn remove_routed_file() -> VfsResult<()> {
+        let (upper_root, scratch_root, _cache_root, lower_root, overlay_root) = create_roots();
+        lower_root.join("logs")?.create_dir()?;
+        lower_root.join("logs/app.log")?.create_file()?;
+        overlay_root.join("logs/app.log")?.append_file()?;
+        assert!(scratch_root.join("logs/app.log")?.exists()?);
+
+        overlay_root.join("logs/app.log")?.remove_file()?;
+        assert!(!overlay_root.join("logs/app.log")?.exists()?);
+        assert!(!scratch_root.join("logs/app.log")?.exists()?);
+        assert!(upper_root.join(".whiteout/logs/app.log_wo")?.exists()?);
+        assert_eq!(overlay_root.join("logs")?.read_dir()?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_layer() -> VfsResult<()> {
+        let upper_root: VfsPath = MemoryFS::new().into();
+        let rules = RoutingRules::new().route_extension("log", 3);
+        let overlay_root: VfsPath = OverlayFS::with_write_policy(&[upper_root], rules).into();
+        let error = overlay_root
+            .join("app.log")?
+            .create_file()
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not create file for '/app.log': FileSystem error: Write routing policy returned layer 3, but there are only 1 layers"
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,8 @@
 //! A "physical" file system implementation using the underlying OS file system
 
 use crate::error::VfsErrorKind;
+#[cfg(all(feature = "inotify", target_os = "linux"))]
+use crate::{impls::inotify_watch::InotifyWatch, WatchEvent};
 use crate::{FileSystem, SeekAndWrite, VfsMetadata};
 use crate::{SeekAndRead, VfsFileType};
 use crate::{VfsError, VfsResult};
@@ -11,6 +13,8 @@ use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 /// A physical filesystem implementation using the underlying OS file system
+///
+/// With the `inotify` feature, changes can be watched on Linux.
 #[derive(Debug)]
 pub struct PhysicalFS {
     root: PathBuf,
@@ -135,6 +139,15 @@ impl FileSystem for PhysicalFS {
         }
         Ok(())
     }
+
+    #[cfg(all(feature = "inotify", target_os = "linux"))]
+    fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        Ok(Box::new(InotifyWatch::new(&self.root, path, recursive)?))
+    }
 }
 
 #[cfg(test)]
@@ -240,4 +253,48 @@ mod tests {
         assert_eq!(metadata.len, 0);
         assert_eq!(metadata.file_type, VfsFileType::Directory);
     }
+
+    #[test]
+    #[cfg(all(feature = "inotify", target_os = "linux"))]
+    fn watch_reports_changes() -> VfsResult<()> {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        let root: VfsPath = PhysicalFS::new(&dir).into();
+        let events = root.watch(true)?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for event in events {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        let next = || receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        root.join("sub")?.create_dir()?;
+        assert_eq!(next(), WatchEvent::Created("/sub".into()));
+        // The new directory is watched once its creation was reported
+        root.join("sub/a.txt")?.create_file()?.write_all(b"a")?;
+        assert_eq!(next(), WatchEvent::Created("/sub/a.txt".into()));
+        assert_eq!(next(), WatchEvent::Modified("/sub/a.txt".into()));
+        root.join("sub/a.txt")?.move_file(&root.join("b.txt")?)?;
+        assert_eq!(
+            next(),
+            WatchEvent::Renamed {
+                from: "/sub/a.txt".into(),
+                to: "/b.txt".into()
+            }
+        );
+        root.join("b.txt")?.remove_file()?;
+        assert_eq!(next(), WatchEvent::Removed("/b.txt".into()));
+        let outside = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::rename(dir.join("sub"), &outside)?;
+        assert_eq!(next(), WatchEvent::Removed("/sub".into()));
+        std::fs::remove_dir_all(&outside)?;
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,212 @@
+//! A read-only view of another filesystem
+
+use crate::error::VfsErrorKind;
+use crate::impls::altroot::strip_root;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult, WatchEvent};
+
+use std::time::SystemTime;
+
+/// A read-only view of the directory tree at a particular path of another filesystem
+///
+/// All reads are passed through, all modifications fail with [`VfsErrorKind::ReadOnly`].
+/// See also [`VfsPath::read_only()`].
+#[derive(Debug, Clone)]
+pub struct ReadOnlyFS {
+    root: VfsPath,
+}
+
+impl ReadOnlyFS {
+    /// Create a new read-only FileSystem with its root at the given virtual path
+    pub fn new(root: VfsPath) -> Self {
+        ReadOnlyFS { root }
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+}
+
+impl FileSystem for ReadOnlyFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.path(path)?
+            .read_dir()
+            .map(|entries| Box::new(entries.map(|path| path.filename())) as Box<_>)
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        self.path(path)?.open_file()
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.path(path)?.metadata()
+    }
+
+    fn set_creation_time(&self, _path: &str, _time: SystemTime) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn set_modification_time(&self, _path: &str, _time: SystemTime) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn set_access_time(&self, _path: &str, _time: SystemTime) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.path(path)
+            .map(|path| path.exists())
+            .unwrap_or(Ok(false))
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn copy_file(&self, _src: &str, _dest: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn move_file(&self, _src: &str, _dest: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn move_dir(&self, _src: &str, _dest: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::ReadOnly.into())
+    }
+
+    fn watch(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        let root = self.root.as_str().to_string();
+        let events = self.path(path)?.watch(recursive)?;
+        Ok(Box::new(events.filter_map(move |event| {
+            event.translate(|path| strip_root(&root, path))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryFS, PhysicalFS};
+    test_vfs_readonly!({ ReadOnlyFS::new(PhysicalFS::new("test/test_directory").into()) });
+
+    fn assert_read_only<T>(result: VfsResult<T>) {
+        match result {
+            Ok(_) => panic!("Modification should have failed"),
+            Err(err) => assert!(
+                matches!(err.kind(), VfsErrorKind::ReadOnly),
+                "Unexpected error: {err}"
+            ),
+        }
+    }
+
+    #[test]
+    fn modifications_fail() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        memory_root.join("dir")?.create_dir()?;
+        memory_root.join("foo.txt")?.create_file()?;
+        let root = memory_root.read_only();
+        let file = root.join("foo.txt")?;
+        let dir = root.join("dir")?;
+
+        assert_read_only(root.join("bar")?.create_dir());
+        assert_read_only(root.join("bar.txt")?.create_file());
+        assert_read_only(file.append_file());
+        assert_read_only(file.remove_file());
+        assert_read_only(dir.remove_dir());
+        assert_read_only(file.set_creation_time(SystemTime::now()));
+        assert_read_only(file.set_modification_time(SystemTime::now()));
+        assert_read_only(file.set_access_time(SystemTime::now()));
+        assert_read_only(file.copy_file(&root.join("bar.txt")?));
+        assert_read_only(file.move_file(&root.join("bar.txt")?));
+        assert_read_only(dir.move_dir(&root.join("bar")?));
+
+        assert!(memory_root.join("foo.txt")?.exists()?);
+        assert!(memory_root.join("dir")?.exists()?);
+        assert!(!memory_root.join("bar.txt")?.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn error_message() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let error = root
+            .read_only()
+            .join("foo.txt")?
+            .create_file()
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not create file for '/foo.txt': The filesystem is read-only"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn copy_out_of_read_only_view() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        memory_root
+            .join("foo.txt")?
+            .create_file()?
+            .write_all(b"Hello World")?;
+        let target: VfsPath = MemoryFS::new().into();
+        memory_root
+            .read_only()
+            .join("foo.txt")?
+            .copy_file(&target.join("foo.txt")?)?;
+        assert_eq!(&target.join("foo.txt")?.read_to_string()?, "Hello World");
+        Ok(())
+    }
+
+    #[test]
+    fn watch_changes_below_view() -> VfsResult<()> {
+        let memory_root: VfsPath = MemoryFS::new().into();
+        memory_root.join("dir")?.create_dir()?;
+        let events = memory_root.join("dir")?.read_only().watch(true)?;
+        memory_root.join("dir/foo")?.create_dir()?;
+        memory_root.join("other")?.create_dir()?;
+        drop(memory_root);
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            vec![WatchEvent::Created("/foo".into())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subtree_view() -> VfsResult<()> {
+        let physical_root: VfsPath = PhysicalFS::new("test/test_directory").into();
+        let root = physical_root.join("a")?.read_only();
+        let mut entries: Vec<_> = root.read_dir()?.map(|path| path.filename()).collect();
+        entries.sort();
+        assert_eq!(entries, vec!["d.txt", "x"]);
+        assert!(root.join("x/y/z")?.is_file()?);
+        assert!(!root.join("a.txt")?.exists()?);
+        assert_eq!(root.parent(), root.root());
+        Ok(())
+    }
+}
@@ -0,0 +1,375 @@
+//! A "physical" file system implementation that is confined to a root directory
+
+use crate::error::VfsErrorKind;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsError, VfsFileType, VfsMetadata, VfsResult};
+use cap_fs_ext::{DirExt, SystemTimeSpec};
+use cap_std::fs::{Dir, OpenOptions};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Maximum number of symbolic links followed while resolving a single path
+const MAX_SYMLINKS: usize = 40;
+
+/// A physical filesystem implementation that can not escape its root directory
+///
+/// Unlike [`PhysicalFS`](crate::PhysicalFS) and [`AltrootFS`](crate::AltrootFS), every path is resolved relative to an
+/// open handle of the root directory, component by component.
+/// Symbolic links are followed only as long as they stay inside the root,
+/// paths leading outside of it (via `..` or symbolic links) fail with [`VfsErrorKind::EscapesRoot`].
+///
+/// This makes it suitable for handling user-supplied paths.
+#[derive(Debug)]
+pub struct SandboxedPhysicalFS {
+    root: Dir,
+}
+
+impl SandboxedPhysicalFS {
+    /// Create a new sandboxed filesystem rooted in the existing directory `root`
+    pub fn new<T: AsRef<Path>>(root: T) -> VfsResult<Self> {
+        let root =
+            Dir::open_ambient_dir(root.as_ref(), cap_std::ambient_authority()).map_err(|err| {
+                VfsError::from(err).with_path(root.as_ref().to_string_lossy().to_string())
+            })?;
+        Ok(SandboxedPhysicalFS { root })
+    }
+
+    /// Checks that the path stays inside the root and returns it relative to the root
+    ///
+    /// The returned path is not resolved, so that operations like remove_file() act on symbolic links themselves
+    fn get_path(&self, path: &str) -> VfsResult<PathBuf> {
+        let relative = path.trim_start_matches('/');
+        let mut resolved: Vec<String> = vec![];
+        let mut todo: VecDeque<String> = relative.split('/').map(String::from).collect();
+        let mut symlinks = 0;
+        while let Some(component) = todo.pop_front() {
+            match component.as_str() {
+                "" | "." => {}
+                ".." => {
+                    if resolved.pop().is_none() {
+                        return Err(VfsErrorKind::EscapesRoot.into());
+                    }
+                }
+                _ => {
+                    resolved.push(component);
+                    // All previous components have been resolved already, so only the last one may be a symlink
+                    let current = resolved.join("/");
+                    let is_symlink = self
+                        .root
+                        .symlink_metadata(&current)
+                        .map(|metadata| metadata.file_type().is_symlink())
+                        .unwrap_or(false);
+                    if !is_symlink {
+                        continue;
+                    }
+                    symlinks += 1;
+                    if symlinks > MAX_SYMLINKS {
+                        return Err(VfsErrorKind::Other(
+                            "Too many levels of symbolic links".into(),
+                        )
+                        .into());
+                    }
+                    let target = self.root.read_link_contents(&current)?;
+                    if target.has_root() {
+                        return Err(VfsErrorKind::EscapesRoot.into());
+                    }
+                    let target = target.to_str().ok_or(VfsErrorKind::InvalidPath)?;
+                    resolved.pop();
+                    for component in target.split('/').rev() {
+                        todo.push_front(component.to_string());
+                    }
+                }
+            }
+        }
+        if relative.is_empty() {
+            return Ok(PathBuf::from("."));
+        }
+        Ok(PathBuf::from(relative))
+    }
+}
+
+fn convert_time(time: std::io::Result<cap_std::time::SystemTime>) -> Option<SystemTime> {
+    time.ok().map(|time| time.into_std())
+}
+
+fn time_spec(time: SystemTime) -> SystemTimeSpec {
+    SystemTimeSpec::from(cap_std::time::SystemTime::from_std(time))
+}
+
+impl FileSystem for SandboxedPhysicalFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let mut entries = vec![];
+        for entry in self.root.read_dir(self.get_path(path)?)? {
+            // Names that are not valid UTF-8 can not be represented as virtual paths
+            if let Ok(name) = entry?.file_name().into_string() {
+                entries.push(name);
+            }
+        }
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        let fs_path = self.get_path(path)?;
+        self.root
+            .create_dir(&fs_path)
+            .map_err(|err| match err.kind() {
+                ErrorKind::AlreadyExists => {
+                    if self.root.is_dir(&fs_path) {
+                        return VfsError::from(VfsErrorKind::DirectoryExists);
+                    }
+                    VfsError::from(VfsErrorKind::FileExists)
+                }
+                _ => err.into(),
+            })?;
+        Ok(())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        Ok(Box::new(self.root.open(self.get_path(path)?)?.into_std()))
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Ok(Box::new(self.root.create(self.get_path(path)?)?.into_std()))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Ok(Box::new(
+            self.root
+                .open_with(self.get_path(path)?, OpenOptions::new().append(true))?
+                .into_std(),
+        ))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let metadata = self.root.metadata(self.get_path(path)?)?;
+        let (file_type, len) = if metadata.is_dir() {
+            (VfsFileType::Directory, 0)
+        } else {
+            (VfsFileType::File, metadata.len())
+        };
+        Ok(VfsMetadata {
+            file_type,
+            len,
+            modified: convert_time(metadata.modified()),
+            created: convert_time(metadata.created()),
+            accessed: convert_time(metadata.accessed()),
+        })
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.root.set_mtime(self.get_path(path)?, time_spec(time))?;
+        Ok(())
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.root.set_atime(self.get_path(path)?, time_spec(time))?;
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(self.root.exists(self.get_path(path)?))
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.root.remove_file(self.get_path(path)?)?;
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.root.remove_dir(self.get_path(path)?)?;
+        Ok(())
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.root
+            .copy(self.get_path(src)?, &self.root, self.get_path(dest)?)?;
+        Ok(())
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.root
+            .rename(self.get_path(src)?, &self.root, self.get_path(dest)?)?;
+        Ok(())
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        let result = self
+            .root
+            .rename(self.get_path(src)?, &self.root, self.get_path(dest)?);
+        if result.is_err() {
+            // Error possibly due to different filesystems, return not supported and let the fallback handle it
+            return Err(VfsErrorKind::NotSupported.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VfsPath;
+    test_vfs!({
+        let temp_dir = std::env::temp_dir();
+        let dir = temp_dir.join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        SandboxedPhysicalFS::new(dir).unwrap()
+    });
+    test_vfs_readonly!({ SandboxedPhysicalFS::new("test/test_directory").unwrap() });
+
+    #[test]
+    #[cfg(unix)]
+    fn read_dir_skips_non_utf8_names() -> VfsResult<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(OsStr::from_bytes(b"invalid\xff")), b"")?;
+        std::fs::write(dir.join("valid.txt"), b"")?;
+        let root: VfsPath = SandboxedPhysicalFS::new(&dir)?.into();
+        let entries: Vec<_> = root.read_dir()?.map(|path| path.filename()).collect();
+        assert_eq!(entries, vec!["valid.txt"]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn missing_root() {
+        let error = SandboxedPhysicalFS::new("test/does_not_exist").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "An error occurred for 'test/does_not_exist': The file or directory could not be found"
+        );
+    }
+
+    #[test]
+    fn parent_directory_escape() {
+        let fs = SandboxedPhysicalFS::new("test/test_directory").unwrap();
+        for path in [
+            "/..",
+            "/../Cargo.toml",
+            "/a/../../Cargo.toml",
+            "/a/x/../../..",
+        ] {
+            let error = fs.open_file(path).map(|_| ()).unwrap_err();
+            assert!(
+                matches!(error.kind(), VfsErrorKind::EscapesRoot),
+                "{path}: {error}"
+            );
+        }
+        assert!(fs.exists("/a/x/../../a.txt").unwrap());
+    }
+
+    #[test]
+    fn parent_directory_via_vfs_path_stays_inside() -> VfsResult<()> {
+        let root: VfsPath = SandboxedPhysicalFS::new("test/test_directory")?.into();
+        let path = root.join("a/../../../a.txt")?;
+        assert_eq!(path.as_str(), "/a.txt");
+        assert_eq!(&path.read_to_string()?, "a");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    mod symlinks {
+        use super::*;
+        use std::os::unix::fs::symlink;
+
+        /// Creates a sandbox directory next to a "secret" directory
+        fn create_sandbox() -> (PathBuf, VfsPath) {
+            let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+            std::fs::create_dir_all(dir.join("secret")).unwrap();
+            std::fs::write(dir.join("secret/password.txt"), "hunter2").unwrap();
+            let sandbox = dir.join("sandbox");
+            std::fs::create_dir_all(sandbox.join("sub")).unwrap();
+            std::fs::write(sandbox.join("sub/inside.txt"), "inside").unwrap();
+            let root = SandboxedPhysicalFS::new(&sandbox).unwrap().into();
+            (sandbox, root)
+        }
+
+        fn assert_escapes(result: VfsResult<impl Sized>) {
+            match result {
+                Ok(_) => panic!("Escape was not detected"),
+                Err(err) => assert!(
+                    matches!(err.kind(), VfsErrorKind::EscapesRoot),
+                    "Unexpected error: {err}"
+                ),
+            }
+        }
+
+        #[test]
+        fn absolute_symlink() -> VfsResult<()> {
+            let (sandbox, root) = create_sandbox();
+            symlink(
+                sandbox.parent().unwrap().join("secret/password.txt"),
+                sandbox.join("link.txt"),
+            )?;
+            assert_escapes(root.join("link.txt")?.open_file());
+            assert_escapes(root.join("link.txt")?.metadata());
+            assert_escapes(root.join("link.txt")?.create_file());
+            Ok(())
+        }
+
+        #[test]
+        fn relative_symlink() -> VfsResult<()> {
+            let (sandbox, root) = create_sandbox();
+            symlink("../../secret/password.txt", sandbox.join("sub/link.txt"))?;
+            assert_escapes(root.join("sub/link.txt")?.open_file());
+            assert_escapes(root.join("sub/link.txt")?.append_file());
+            Ok(())
+        }
+
+        #[test]
+        fn symlinked_directory() -> VfsResult<()> {
+            let (sandbox, root) = create_sandbox();
+            symlink("../secret", sandbox.join("secret"))?;
+            assert_escapes(root.join("secret/password.txt")?.open_file());
+            assert_escapes(root.join("secret")?.read_dir());
+            assert_escapes(root.join("secret/new.txt")?.create_file());
+            assert_escapes(root.join("secret/password.txt")?.remove_file());
+            assert!(sandbox
+                .parent()
+                .unwrap()
+                .join("secret/password.txt")
+                .exists());
+            Ok(())
+        }
+
+        #[test]
+        fn symlink_chain() -> VfsResult<()> {
+            let (sandbox, root) = create_sandbox();
+            symlink("sub", sandbox.join("first"))?;
+            symlink("../..", sandbox.join("sub/second"))?;
+            assert_escapes(root.join("first/second/secret/password.txt")?.open_file());
+            Ok(())
+        }
+
+        #[test]
+        fn symlink_loop() -> VfsResult<()> {
+            let (sandbox, root) = create_sandbox();
+            symlink("loop_b", sandbox.join("loop_a"))?;
+            symlink("loop_a", sandbox.join("loop_b"))?;
+            let error = root.join("loop_a")?.open_file().map(|_| ()).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "Could not open file for '/loop_a': FileSystem error: Too many levels of symbolic links"
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn symlinks_inside_root_are_followed() -> VfsResult<()> {
+            let (sandbox, root) = create_sandbox();
+            symlink("sub/inside.txt", sandbox.join("link.txt"))?;
+            symlink("../sub", sandbox.join("sub/self"))?;
+            assert_eq!(&root.join("link.txt")?.read_to_string()?, "inside");
+            assert_eq!(
+                &root.join("sub/self/self/inside.txt")?.read_to_string()?,
+                "inside"
+            );
+            root.join("link.txt")?.remove_file()?;
+            assert!(sandbox.join("sub/inside.txt").exists());
+            Ok(())
+        }
+    }
+}
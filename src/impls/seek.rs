@@ -0,0 +1,46 @@
+//! Seeking within files whose position is tracked by the wrapper instead of the inner file
+
+use std::io::{self, SeekFrom};
+
+/// Returns the absolute position a seek resolves to, for a file with the given position and length
+pub(crate) fn resolve_seek(pos: SeekFrom, position: u64, len: u64) -> io::Result<u64> {
+    let target = match pos {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::Current(offset) => add_signed(position, offset),
+        SeekFrom::End(offset) => add_signed(len, offset),
+    };
+    target.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+fn add_signed(base: u64, offset: i64) -> Option<u64> {
+    if offset < 0 {
+        base.checked_sub(offset.unsigned_abs())
+    } else {
+        base.checked_add(offset as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_positions() {
+        assert_eq!(resolve_seek(SeekFrom::Start(3), 5, 10).unwrap(), 3);
+        assert_eq!(resolve_seek(SeekFrom::Current(-2), 5, 10).unwrap(), 3);
+        assert_eq!(resolve_seek(SeekFrom::End(-4), 5, 10).unwrap(), 6);
+        assert_eq!(resolve_seek(SeekFrom::End(4), 5, 10).unwrap(), 14);
+    }
+
+    #[test]
+    fn rejects_invalid_positions() {
+        let error = resolve_seek(SeekFrom::Current(-6), 5, 10).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(resolve_seek(SeekFrom::Current(1), u64::MAX, 10).is_err());
+    }
+}
@@ -0,0 +1,547 @@
+//! A filesystem wrapper recording all calls, e.g. for debugging failing tests
+
+use crate::error::VfsErrorKind;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsMetadata, VfsPath, VfsResult};
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The kind of operation recorded in a [`TraceEvent`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TraceOperation {
+    /// [`FileSystem::read_dir()`]
+    ReadDir,
+    /// [`FileSystem::create_dir()`]
+    CreateDir,
+    /// [`FileSystem::open_file()`]
+    OpenFile,
+    /// [`FileSystem::create_file()`]
+    CreateFile,
+    /// [`FileSystem::append_file()`]
+    AppendFile,
+    /// [`FileSystem::metadata()`]
+    Metadata,
+    /// [`FileSystem::set_creation_time()`]
+    SetCreationTime,
+    /// [`FileSystem::set_modification_time()`]
+    SetModificationTime,
+    /// [`FileSystem::set_access_time()`]
+    SetAccessTime,
+    /// [`FileSystem::exists()`]
+    Exists,
+    /// [`FileSystem::remove_file()`]
+    RemoveFile,
+    /// [`FileSystem::remove_dir()`]
+    RemoveDir,
+    /// [`FileSystem::copy_file()`]
+    CopyFile,
+    /// [`FileSystem::move_file()`]
+    MoveFile,
+    /// [`FileSystem::move_dir()`]
+    MoveDir,
+    /// Reading from a handle returned by `open_file()`, recorded when the handle is dropped
+    Read,
+    /// Writing to a handle returned by `create_file()` or `append_file()`, recorded when the handle is dropped
+    Write,
+}
+
+impl Display for TraceOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TraceOperation::ReadDir => "read_dir",
+            TraceOperation::CreateDir => "create_dir",
+            TraceOperation::OpenFile => "open_file",
+            TraceOperation::CreateFile => "create_file",
+            TraceOperation::AppendFile => "append_file",
+            TraceOperation::Metadata => "metadata",
+            TraceOperation::SetCreationTime => "set_creation_time",
+            TraceOperation::SetModificationTime => "set_modification_time",
+            TraceOperation::SetAccessTime => "set_access_time",
+            TraceOperation::Exists => "exists",
+            TraceOperation::RemoveFile => "remove_file",
+            TraceOperation::RemoveDir => "remove_dir",
+            TraceOperation::CopyFile => "copy_file",
+            TraceOperation::MoveFile => "move_file",
+            TraceOperation::MoveDir => "move_dir",
+            TraceOperation::Read => "read",
+            TraceOperation::Write => "write",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A single recorded call
+#[derive(Debug)]
+pub struct TraceEvent {
+    /// The called operation
+    pub operation: TraceOperation,
+    /// The path the operation was called with, `/` for the root
+    pub path: String,
+    /// The destination path of copy and move operations
+    pub dest: Option<String>,
+    /// The error kind if the operation failed
+    pub error: Option<VfsErrorKind>,
+    /// The time spent in the call, for handles the total time spent reading or writing
+    pub duration: Duration,
+    /// The number of bytes read or written through a handle
+    pub bytes: Option<u64>,
+}
+
+impl Clone for TraceEvent {
+    fn clone(&self) -> Self {
+        TraceEvent {
+            operation: self.operation,
+            path: self.path.clone(),
+            dest: self.dest.clone(),
+            error: self.error.as_ref().map(VfsErrorKind::duplicate),
+            duration: self.duration,
+            bytes: self.bytes,
+        }
+    }
+}
+
+/// Formats the event without its duration, e.g. `copy_file /a.txt -> /b.txt: ok` or `read /a.txt: ok (5 bytes)`
+impl Display for TraceEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.operation, self.path)?;
+        if let Some(dest) = &self.dest {
+            write!(f, " -> {dest}")?;
+        }
+        match &self.error {
+            None => write!(f, ": ok")?,
+            Some(kind) => write!(f, ": {kind}")?,
+        }
+        if let Some(bytes) = self.bytes {
+            write!(f, " ({bytes} bytes)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Receives the events recorded by a [`TracingFS`]
+pub trait TraceSink: Debug + Sync + Send + 'static {
+    /// Called for every recorded event
+    fn record(&self, event: &TraceEvent);
+}
+
+/// A [`TraceSink`] keeping all events in memory, so tests can assert on the sequence of operations
+///
+/// Clones share the same list of events.
+#[derive(Debug, Clone, Default)]
+pub struct TraceRecorder {
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+impl TraceRecorder {
+    /// Creates an empty recorder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all events recorded so far
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Returns the [`Display`] representation of all events recorded so far
+    pub fn summary(&self) -> Vec<String> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Forgets all recorded events
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+impl TraceSink for TraceRecorder {
+    fn record(&self, event: &TraceEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tracer {
+    sinks: Vec<Arc<dyn TraceSink>>,
+}
+
+impl Tracer {
+    fn emit(&self, event: TraceEvent) {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(
+            target: "vfs",
+            operation = %event.operation,
+            path = %event.path,
+            dest = ?event.dest,
+            error = ?event.error,
+            duration = ?event.duration,
+            bytes = ?event.bytes,
+            "{}",
+            event
+        );
+        for sink in &self.sinks {
+            sink.record(&event);
+        }
+    }
+}
+
+/// A view of the directory tree at a particular path of another filesystem, recording every call
+///
+/// Each call is recorded as a [`TraceEvent`] with its paths, result and duration.
+/// Handles returned by `open_file()`, `create_file()` and `append_file()` record a `read` or `write` event
+/// with the number of bytes transferred when they are dropped.
+///
+/// Events are passed to all sinks added with [`TracingFS::with_sink()`], e.g. a [`TraceRecorder`].
+/// With the `tracing` feature, all events are also emitted as `DEBUG` events with the target `vfs`
+/// through the [tracing](https://docs.rs/tracing/) facade.
+///
+/// ```
+/// # use vfs::{MemoryFS, TracingFS, VfsError, VfsPath};
+/// use vfs::impls::tracing::TraceRecorder;
+/// let recorder = TraceRecorder::new();
+/// let root: VfsPath = TracingFS::new(MemoryFS::new().into())
+///     .with_sink(recorder.clone())
+///     .into();
+///
+/// root.join("foo.txt")?.create_file()?.write_all(b"Hello")?;
+/// assert!(root.join("bar.txt")?.open_file().is_err());
+///
+/// assert_eq!(
+///     recorder.summary(),
+///     vec![
+///         // VfsPath checks that the parent directory exists
+///         "exists /: ok",
+///         "metadata /: ok",
+///         "create_file /foo.txt: ok",
+///         "write /foo.txt: ok (5 bytes)",
+///         "open_file /bar.txt: The file or directory could not be found",
+///     ]
+/// );
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TracingFS {
+    root: VfsPath,
+    tracer: Arc<Tracer>,
+}
+
+impl TracingFS {
+    /// Create a new tracing FileSystem with its root at the given virtual path, without any sinks
+    pub fn new(root: VfsPath) -> Self {
+        TracingFS {
+            root,
+            tracer: Arc::new(Tracer::default()),
+        }
+    }
+
+    /// Adds a sink receiving all events
+    pub fn with_sink(mut self, sink: impl TraceSink) -> Self {
+        let mut sinks = self.tracer.sinks.clone();
+        sinks.push(Arc::new(sink));
+        self.tracer = Arc::new(Tracer { sinks });
+        self
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root.join(&path[1..])
+    }
+
+    fn trace<T>(
+        &self,
+        operation: TraceOperation,
+        path: &str,
+        dest: Option<&str>,
+        call: impl FnOnce() -> VfsResult<T>,
+    ) -> VfsResult<T> {
+        let start = Instant::now();
+        let result = call();
+        self.tracer.emit(TraceEvent {
+            operation,
+            path: display_path(path),
+            dest: dest.map(display_path),
+            error: result.as_ref().err().map(|error| error.kind().duplicate()),
+            duration: start.elapsed(),
+            bytes: None,
+        });
+        result
+    }
+
+    fn handle<T>(&self, operation: TraceOperation, path: &str, inner: T) -> TracedHandle<T> {
+        TracedHandle {
+            inner,
+            tracer: self.tracer.clone(),
+            operation,
+            path: display_path(path),
+            bytes: 0,
+            duration: Duration::ZERO,
+            error: None,
+        }
+    }
+}
+
+fn display_path(path: &str) -> String {
+    if path.is_empty() {
+        "/".into()
+    } else {
+        path.into()
+    }
+}
+
+impl FileSystem for TracingFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.trace(TraceOperation::ReadDir, path, None, || {
+            self.path(path)?
+                .read_dir()
+                .map(|entries| Box::new(entries.map(|path| path.filename())) as Box<_>)
+        })
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.trace(TraceOperation::CreateDir, path, None, || {
+            self.path(path)?.create_dir()
+        })
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let file = self.trace(TraceOperation::OpenFile, path, None, || {
+            self.path(path)?.open_file()
+        })?;
+        Ok(Box::new(self.handle(TraceOperation::Read, path, file)))
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.trace(TraceOperation::CreateFile, path, None, || {
+            self.path(path)?.create_file()
+        })?;
+        Ok(Box::new(self.handle(TraceOperation::Write, path, file)))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let file = self.trace(TraceOperation::AppendFile, path, None, || {
+            self.path(path)?.append_file()
+        })?;
+        Ok(Box::new(self.handle(TraceOperation::Write, path, file)))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.trace(TraceOperation::Metadata, path, None, || {
+            self.path(path)?.metadata()
+        })
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.trace(TraceOperation::SetCreationTime, path, None, || {
+            self.path(path)?.set_creation_time(time)
+        })
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.trace(TraceOperation::SetModificationTime, path, None, || {
+            self.path(path)?.set_modification_time(time)
+        })
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.trace(TraceOperation::SetAccessTime, path, None, || {
+            self.path(path)?.set_access_time(time)
+        })
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.trace(TraceOperation::Exists, path, None, || {
+            self.path(path)?.exists()
+        })
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.trace(TraceOperation::RemoveFile, path, None, || {
+            self.path(path)?.remove_file()
+        })
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.trace(TraceOperation::RemoveDir, path, None, || {
+            self.path(path)?.remove_dir()
+        })
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.trace(TraceOperation::CopyFile, src, Some(dest), || {
+            self.path(src)?.copy_file(&self.path(dest)?)
+        })
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.trace(TraceOperation::MoveFile, src, Some(dest), || {
+            self.path(src)?.move_file(&self.path(dest)?)
+        })
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.trace(TraceOperation::MoveDir, src, Some(dest), || {
+            self.path(src)?.move_dir(&self.path(dest)?)
+        })
+    }
+}
+
+/// A file handle counting the bytes transferred, recording an event when dropped
+struct TracedHandle<T> {
+    inner: T,
+    tracer: Arc<Tracer>,
+    operation: TraceOperation,
+    path: String,
+    bytes: u64,
+    duration: Duration,
+    error: Option<VfsErrorKind>,
+}
+
+impl<T> TracedHandle<T> {
+    fn track<R>(&mut self, call: impl FnOnce(&mut T) -> io::Result<R>) -> io::Result<R> {
+        let start = Instant::now();
+        let result = call(&mut self.inner);
+        self.duration += start.elapsed();
+        if let Err(error) = &result {
+            self.error.get_or_insert_with(|| {
+                VfsErrorKind::IoError(io::Error::new(error.kind(), error.to_string()))
+            });
+        }
+        result
+    }
+}
+
+impl<T: Read> Read for TracedHandle<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.track(|inner| inner.read(buf))?;
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Write> Write for TracedHandle<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.track(|inner| inner.write(buf))?;
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.track(|inner| inner.flush())
+    }
+}
+
+impl<T: Seek> Seek for TracedHandle<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.track(|inner| inner.seek(pos))
+    }
+}
+
+impl<T> Drop for TracedHandle<T> {
+    fn drop(&mut self) {
+        self.tracer.emit(TraceEvent {
+            operation: self.operation,
+            path: std::mem::take(&mut self.path),
+            dest: None,
+            error: self.error.take(),
+            duration: self.duration,
+            bytes: Some(self.bytes),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFS;
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        TracingFS::new(memory_root).with_sink(TraceRecorder::new())
+    });
+
+    #[test]
+    fn records_sequence_of_operations() -> VfsResult<()> {
+        let recorder = TraceRecorder::new();
+        let root: VfsPath = TracingFS::new(MemoryFS::new().into())
+            .with_sink(recorder.clone())
+            .into();
+
+        root.join("dir")?.create_dir()?;
+        root.join("dir/a.txt")?
+            .create_file()?
+            .write_all(b"Hello World")?;
+        let mut content = String::new();
+        root.join("dir/a.txt")?
+            .open_file()?
+            .read_to_string(&mut content)?;
+        root.join("dir/a.txt")?
+            .copy_file(&root.join("dir/b.txt")?)?;
+        assert_eq!(root.read_dir()?.count(), 1);
+        assert!(root.join("dir")?.remove_dir().is_err());
+
+        assert_eq!(
+            recorder.summary(),
+            vec![
+                "exists /: ok",
+                "metadata /: ok",
+                "create_dir /dir: ok",
+                "exists /dir: ok",
+                "metadata /dir: ok",
+                "create_file /dir/a.txt: ok",
+                "write /dir/a.txt: ok (11 bytes)",
+                "open_file /dir/a.txt: ok",
+                "read /dir/a.txt: ok (11 bytes)",
+                "exists /dir/b.txt: ok",
+                "copy_file /dir/a.txt -> /dir/b.txt: ok",
+                "read_dir /: ok",
+                "remove_dir /dir: FileSystem error: Directory to remove is not empty",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn records_error_kinds() -> VfsResult<()> {
+        let recorder = TraceRecorder::new();
+        let fs = TracingFS::new(MemoryFS::new().into()).with_sink(recorder.clone());
+        assert!(fs.open_file("/missing.txt").is_err());
+        fs.create_dir("/dir")?;
+        assert!(fs.create_dir("/dir").is_err());
+
+        let events = recorder.events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].operation, TraceOperation::OpenFile);
+        assert_eq!(events[0].path, "/missing.txt");
+        assert!(matches!(events[0].error, Some(VfsErrorKind::FileNotFound)));
+        assert!(events[1].error.is_none());
+        assert!(matches!(
+            events[2].error,
+            Some(VfsErrorKind::DirectoryExists)
+        ));
+
+        recorder.clear();
+        assert!(recorder.events().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn passes_events_to_all_sinks() -> VfsResult<()> {
+        let first = TraceRecorder::new();
+        let second = TraceRecorder::new();
+        let fs = TracingFS::new(MemoryFS::new().into())
+            .with_sink(first.clone())
+            .with_sink(second.clone());
+        assert_eq!(fs.read_dir("")?.count(), 0);
+        assert_eq!(first.summary(), vec!["read_dir /: ok"]);
+        assert_eq!(second.summary(), vec!["read_dir /: ok"]);
+        Ok(())
+    }
+}
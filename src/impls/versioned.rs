@@ -0,0 +1,452 @@
+//! A filesystem wrapper keeping previous versions of files
+
+use crate::error::VfsErrorKind;
+use crate::{FileSystem, SeekAndRead, SeekAndWrite, VfsError, VfsMetadata, VfsPath, VfsResult};
+
+use std::io;
+use std::time::SystemTime;
+
+/// The hidden directory in the inner filesystem storing the previous versions
+const STORE_DIR: &str = ".versions";
+const DELETED_SUFFIX: &str = ".deleted";
+
+/// Why a version of a file was kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VersionReason {
+    /// The file was overwritten, appended to or restored to another version
+    Modified,
+    /// The file was removed
+    Deleted,
+}
+
+/// A previous version of a file kept by a [`VersionedFS`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    /// The identifier of this version, increasing with every version of the same path
+    pub id: u64,
+    /// Why this version was kept
+    pub reason: VersionReason,
+    /// Length of this version in bytes
+    pub len: u64,
+    /// Modification time of this version, if supported by the inner filesystem
+    pub modified: Option<SystemTime>,
+}
+
+/// A view of the directory tree at a particular path of another filesystem, keeping previous versions of files
+///
+/// Before a file is overwritten, appended to or removed, its content is copied to a hidden `.versions`
+/// directory in the inner filesystem, so the history survives restarts. Only the newest versions of each path
+/// are kept, 10 by default. The versions can be listed, read and restored with [`VersionedFS::versions()`],
+/// [`VersionedFS::open_version()`] and [`VersionedFS::restore_version()`].
+///
+/// The history belongs to a path, moving a file or directory leaves the history at the old path.
+///
+/// ```
+/// # use vfs::{MemoryFS, VersionedFS, VfsError, VfsPath};
+/// let versioned = VersionedFS::new(MemoryFS::new().into());
+/// let root: VfsPath = versioned.clone().into();
+/// let config = root.join("config.toml")?;
+///
+/// config.create_file()?.write_all(b"theme = \"light\"")?;
+/// config.create_file()?.write_all(b"theme = \"dark\"")?;
+///
+/// let versions = versioned.versions("/config.toml")?;
+/// assert_eq!(versions.len(), 1);
+/// versioned.restore_version("/config.toml", versions[0].id)?;
+/// assert_eq!(config.read_to_string()?, "theme = \"light\"");
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct VersionedFS {
+    root: VfsPath,
+    max_versions: usize,
+}
+
+impl VersionedFS {
+    /// Create a new versioned FileSystem with its root at the given virtual path
+    pub fn new(root: VfsPath) -> Self {
+        VersionedFS {
+            root,
+            max_versions: 10,
+        }
+    }
+
+    /// Sets the number of previous versions kept per path
+    ///
+    /// Older versions are removed when a new version is recorded, zero disables versioning.
+    pub fn max_versions(mut self, max_versions: usize) -> Self {
+        self.max_versions = max_versions;
+        self
+    }
+
+    /// Returns the previous versions of the file at `path`, oldest first
+    ///
+    /// Versions of removed files are still listed.
+    pub fn versions(&self, path: &str) -> VfsResult<Vec<FileVersion>> {
+        let path = normalize(path)?;
+        self.path(&path).map_err(|err| err.with_path(&path))?;
+        let versions = self
+            .stored_versions(&path)
+            .map_err(|err| err.with_path(&path))?;
+        Ok(versions.into_iter().map(|(version, _)| version).collect())
+    }
+
+    /// Opens a previous version of the file at `path` for reading
+    pub fn open_version(&self, path: &str, id: u64) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let path = normalize(path)?;
+        self.stored_version(&path, id)?.open_file()
+    }
+
+    /// Restores a previous version of the file at `path`
+    ///
+    /// The current content is kept as a new version first, so restoring can be undone as well.
+    /// Removed files are recreated, including their parent directories.
+    pub fn restore_version(&self, path: &str, id: u64) -> VfsResult<()> {
+        let path = normalize(path)?;
+        let version = self.stored_version(&path, id)?;
+        let file = self.path(&path)?;
+        self.add_version(&path, VersionReason::Modified)?;
+        file.parent().create_dir_all()?;
+        io::copy(&mut version.open_file()?, &mut file.create_file()?)
+            .map_err(|err| VfsError::from(err).with_path(&path))?;
+        self.prune(&path)
+    }
+
+    fn path(&self, path: &str) -> VfsResult<VfsPath> {
+        if path.is_empty() {
+            return Ok(self.root.clone());
+        }
+        if path[1..].split('/').next() == Some(STORE_DIR) {
+            return Err(VfsErrorKind::InvalidPath.into());
+        }
+        self.root.join(&path[1..])
+    }
+
+    /// The directory keeping the versions of a path, all paths are stored in a flat directory
+    fn history_dir(&self, path: &str) -> VfsResult<VfsPath> {
+        let mut name = String::with_capacity(path.len());
+        for character in path[1..].chars() {
+            match character {
+                '%' => name.push_str("%25"),
+                '/' => name.push_str("%2F"),
+                character => name.push(character),
+            }
+        }
+        self.root.join(STORE_DIR)?.join(name)
+    }
+
+    /// Returns the stored versions of a path with their location, oldest first
+    fn stored_versions(&self, path: &str) -> VfsResult<Vec<(FileVersion, VfsPath)>> {
+        let history = self.history_dir(path)?;
+        if !history.exists()? {
+            return Ok(vec![]);
+        }
+        let mut versions = vec![];
+        for entry in history.read_dir()? {
+            let filename = entry.filename();
+            let (id, reason) = match filename.strip_suffix(DELETED_SUFFIX) {
+                Some(id) => (id, VersionReason::Deleted),
+                None => (filename.as_str(), VersionReason::Modified),
+            };
+            // Ignore unrelated files, e.g. left by other tools
+            let id = match id.parse() {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let metadata = entry.metadata()?;
+            let version = FileVersion {
+                id,
+                reason,
+                len: metadata.len,
+                modified: metadata.modified.or(metadata.created),
+            };
+            versions.push((version, entry));
+        }
+        versions.sort_by_key(|(version, _)| version.id);
+        Ok(versions)
+    }
+
+    fn stored_version(&self, path: &str, id: u64) -> VfsResult<VfsPath> {
+        self.path(path)
+            .and_then(|_| self.stored_versions(path))
+            .and_then(|versions| {
+                versions
+                    .into_iter()
+                    .find(|(version, _)| version.id == id)
+                    .map(|(_, location)| location)
+                    .ok_or_else(|| {
+                        VfsError::from(VfsErrorKind::FileNotFound)
+                            .with_context(|| format!("Could not find version {id}"))
+                    })
+            })
+            .map_err(|err| err.with_path(path))
+    }
+
+    /// Keeps the current content of the file at `path` as a new version, if it exists
+    fn add_version(&self, path: &str, reason: VersionReason) -> VfsResult<()> {
+        let file = self.path(path)?;
+        if self.max_versions == 0 || !file.is_file()? {
+            return Ok(());
+        }
+        let history = self.history_dir(path)?;
+        history.create_dir_all()?;
+        let id = self
+            .stored_versions(path)?
+            .last()
+            .map_or(1, |(version, _)| version.id + 1);
+        let name = match reason {
+            VersionReason::Modified => id.to_string(),
+            VersionReason::Deleted => format!("{id}{DELETED_SUFFIX}"),
+        };
+        file.copy_file(&history.join(name)?)
+    }
+
+    /// Removes the oldest versions of `path` exceeding the maximum number of versions
+    fn prune(&self, path: &str) -> VfsResult<()> {
+        let versions = self.stored_versions(path)?;
+        let excess = versions.len().saturating_sub(self.max_versions);
+        for (_, location) in versions.into_iter().take(excess) {
+            location.remove_file()?;
+        }
+        Ok(())
+    }
+
+    fn record(&self, path: &str, reason: VersionReason) -> VfsResult<()> {
+        self.add_version(path, reason)?;
+        self.prune(path)
+    }
+}
+
+/// Converts a file path to the `/a/b` form used by the `FileSystem` trait
+fn normalize(path: &str) -> VfsResult<String> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Err(VfsError::from(VfsErrorKind::InvalidPath).with_path("/"));
+    }
+    Ok(format!("/{path}"))
+}
+
+impl FileSystem for VersionedFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let entries = self.path(path)?.read_dir()?.map(|path| path.filename());
+        if path.is_empty() {
+            return Ok(Box::new(entries.filter(|name| name != STORE_DIR)));
+        }
+        Ok(Box::new(entries))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.create_dir()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        self.path(path)?.open_file()
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.record(path, VersionReason::Modified)?;
+        self.path(path)?.create_file()
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        self.record(path, VersionReason::Modified)?;
+        self.path(path)?.append_file()
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.path(path)?.metadata()
+    }
+
+    fn set_creation_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_creation_time(time)
+    }
+
+    fn set_modification_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_modification_time(time)
+    }
+
+    fn set_access_time(&self, path: &str, time: SystemTime) -> VfsResult<()> {
+        self.path(path)?.set_access_time(time)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.path(path)
+            .map(|path| path.exists())
+            .unwrap_or(Ok(false))
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        self.record(path, VersionReason::Deleted)?;
+        self.path(path)?.remove_file()
+    }
+
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        self.path(path)?.remove_dir()
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.copy_file(&self.path(dest)?)
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_file(&self.path(dest)?)
+    }
+
+    fn move_dir(&self, src: &str, dest: &str) -> VfsResult<()> {
+        self.path(src)?.move_dir(&self.path(dest)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFS;
+    use std::io::{Read, Seek, SeekFrom};
+    test_vfs!({
+        let memory_root: VfsPath = MemoryFS::new().into();
+        VersionedFS::new(memory_root)
+    });
+
+    fn write(root: &VfsPath, path: &str, content: &str) -> VfsResult<()> {
+        root.join(path)?
+            .create_file()?
+            .write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    fn read_version(fs: &VersionedFS, path: &str, id: u64) -> VfsResult<String> {
+        let mut content = String::new();
+        fs.open_version(path, id)?.read_to_string(&mut content)?;
+        Ok(content)
+    }
+
+    #[test]
+    fn keeps_versions_of_writes_and_deletions() -> VfsResult<()> {
+        let fs = VersionedFS::new(MemoryFS::new().into());
+        let root: VfsPath = fs.clone().into();
+        root.join("dir")?.create_dir()?;
+        write(&root, "dir/a.txt", "one")?;
+        assert!(fs.versions("/dir/a.txt")?.is_empty());
+        write(&root, "dir/a.txt", "two")?;
+        root.join("dir/a.txt")?.append_file()?.write_all(b"+")?;
+        root.join("dir/a.txt")?.remove_file()?;
+
+        let versions = fs.versions("/dir/a.txt")?;
+        let summary: Vec<_> = versions
+            .iter()
+            .map(|version| (version.id, version.reason, version.len))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, VersionReason::Modified, 3),
+                (2, VersionReason::Modified, 3),
+                (3, VersionReason::Deleted, 4),
+            ]
+        );
+        assert_eq!(read_version(&fs, "/dir/a.txt", 1)?, "one");
+        assert_eq!(read_version(&fs, "dir/a.txt", 3)?, "two+");
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_only_newest_versions() -> VfsResult<()> {
+        let fs = VersionedFS::new(MemoryFS::new().into()).max_versions(2);
+        let root: VfsPath = fs.clone().into();
+        for content in ["1", "2", "3", "4", "5"] {
+            write(&root, "a.txt", content)?;
+        }
+        let ids: Vec<_> = fs.versions("/a.txt")?.iter().map(|v| v.id).collect();
+        assert_eq!(ids, vec![3, 4]);
+        assert_eq!(read_version(&fs, "/a.txt", 4)?, "4");
+        let error = fs.open_version("/a.txt", 1).map(|_| ()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not find version 1 for '/a.txt': The file or directory could not be found"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn restore_version() -> VfsResult<()> {
+        let fs = VersionedFS::new(MemoryFS::new().into()).max_versions(2);
+        let root: VfsPath = fs.clone().into();
+        write(&root, "a.txt", "first")?;
+        write(&root, "a.txt", "second")?;
+        write(&root, "a.txt", "third")?;
+        fs.restore_version("/a.txt", 1)?;
+        assert_eq!(root.join("a.txt")?.read_to_string()?, "first");
+
+        // Restoring keeps the overwritten content and prunes afterwards
+        let ids: Vec<_> = fs.versions("/a.txt")?.iter().map(|v| v.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        fs.restore_version("/a.txt", 3)?;
+        assert_eq!(root.join("a.txt")?.read_to_string()?, "third");
+        Ok(())
+    }
+
+    #[test]
+    fn restore_deleted_file() -> VfsResult<()> {
+        let fs = VersionedFS::new(MemoryFS::new().into());
+        let root: VfsPath = fs.clone().into();
+        root.join("a/b")?.create_dir_all()?;
+        write(&root, "a/b/c.txt", "content")?;
+        root.join("a")?.remove_dir_all()?;
+        assert!(!root.join("a")?.exists()?);
+
+        let versions = fs.versions("/a/b/c.txt")?;
+        assert_eq!(versions[0].reason, VersionReason::Deleted);
+        fs.restore_version("/a/b/c.txt", versions[0].id)?;
+        assert_eq!(root.join("a/b/c.txt")?.read_to_string()?, "content");
+        Ok(())
+    }
+
+    #[test]
+    fn history_survives_restarts() -> VfsResult<()> {
+        let inner: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = VersionedFS::new(inner.clone()).into();
+        write(&root, "a.txt", "old")?;
+        write(&root, "a.txt", "new")?;
+
+        let fs = VersionedFS::new(inner);
+        assert_eq!(fs.versions("/a.txt")?.len(), 1);
+        let mut version = fs.open_version("/a.txt", 1)?;
+        version.seek(SeekFrom::Start(1))?;
+        let mut content = String::new();
+        version.read_to_string(&mut content)?;
+        assert_eq!(content, "ld");
+        Ok(())
+    }
+
+    #[test]
+    fn store_is_hidden() -> VfsResult<()> {
+        let inner: VfsPath = MemoryFS::new().into();
+        let root: VfsPath = VersionedFS::new(inner.clone()).into();
+        write(&root, "a.txt", "old")?;
+        write(&root, "a.txt", "new")?;
+        assert!(inner.join(".versions")?.exists()?);
+
+        let names: Vec<_> = root.read_dir()?.map(|path| path.filename()).collect();
+        assert_eq!(names, vec!["a.txt"]);
+        assert!(!root.join(".versions")?.exists()?);
+        assert!(root.join(".versions/a.txt/1")?.open_file().is_err());
+        assert!(root.join(".versions")?.create_dir().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn paths_with_separators_do_not_collide() -> VfsResult<()> {
+        let fs = VersionedFS::new(MemoryFS::new().into());
+        let root: VfsPath = fs.clone().into();
+        root.join("a")?.create_dir()?;
+        for path in ["a/b", "a%2Fb"] {
+            write(&root, path, path)?;
+            write(&root, path, "new")?;
+        }
+        assert_eq!(read_version(&fs, "/a/b", 1)?, "a/b");
+        assert_eq!(read_version(&fs, "/a%2Fb", 1)?, "a%2Fb");
+        assert!(fs.versions("/").is_err());
+        assert!(fs.versions("/.versions/a").is_err());
+        Ok(())
+    }
+}
@@ -8,9 +8,22 @@
 //! This crate currently has the following implementations:
 //!
 //!  * **[`PhysicalFS`](impls/physical/struct.PhysicalFS.html)** - the actual filesystem of the underlying OS
+//!  * **[`SandboxedPhysicalFS`](impls/sandboxed/struct.SandboxedPhysicalFS.html)** - the underlying OS file system confined to a root directory, requires `sandboxed-fs` feature
 //!  * **[`MemoryFS`](impls/memory/struct.MemoryFS.html)** - an ephemeral in-memory implementation (intended for unit tests)
 //!  * **[`AltrootFS`](impls/altroot/struct.AltrootFS.html)** - a file system with its root in a particular directory of another filesystem
 //!  * **[`OverlayFS`](impls/overlay/struct.OverlayFS.html)** - a union file system consisting of a read/writable upper layer and several read-only lower layers
+//!  * **[`ReadOnlyFS`](impls/readonly/struct.ReadOnlyFS.html)** - a read-only view of another filesystem
+//!  * **[`FilteredFS`](impls/filtered/struct.FilteredFS.html)** - a view of another filesystem hiding paths based on glob patterns
+//!  * **[`CaseInsensitiveFS`](impls/case_insensitive/struct.CaseInsensitiveFS.html)** - a view of another filesystem resolving paths case-insensitively
+//!  * **[`CachingFS`](impls/caching/struct.CachingFS.html)** - a read-through cache for another filesystem
+//!  * **[`TracingFS`](impls/tracing/struct.TracingFS.html)** - a wrapper recording all calls to another filesystem, optionally through the `tracing` feature
+//!  * **[`FaultyFS`](impls/faulty/struct.FaultyFS.html)** - a wrapper injecting faults into calls to another filesystem, for testing error handling
+//!  * **[`MetricsFS`](impls/metrics/struct.MetricsFS.html)** - a wrapper collecting call counts, errors, byte totals and latencies of another filesystem
+//!  * **[`VersionedFS`](impls/versioned/struct.VersionedFS.html)** - a view of another filesystem keeping previous versions of files in a hidden directory
+//!  * **[`MountFS`](impls/mount/struct.MountFS.html)** - a file system composing several filesystems by mounting them at different paths
+//!  * **[`CasFS`](impls/cas/struct.CasFS.html)** - a content-addressed file system deduplicating identical files, requires `cas-fs` feature
+//!  * **[`CompressedFS`](impls/compressed/struct.CompressedFS.html)** - a view of another filesystem storing files as seekable compressed chunks, requires `compressed-fs` feature
+//!  * **[`EncryptedFS`](impls/encrypted/struct.EncryptedFS.html)** - a view of another filesystem encrypting and authenticating all files, requires `encrypted-fs` feature
 //!  * **[`EmbeddedFS`](impls/embedded/struct.EmbeddedFs.html)** - a read-only file system embedded in the executable, requires `embedded-fs` feature
 //!
 //! # Usage Examples
@@ -58,18 +71,44 @@ pub mod test_macros;
 
 pub mod error;
 pub mod filesystem;
+mod glob;
 pub mod impls;
 pub mod path;
+pub mod progress;
+pub mod watch;
 
-#[cfg(feature = "async-vfs")]
+#[cfg(feature = "async-vfs-core")]
 pub mod async_vfs;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 
 pub use error::{VfsError, VfsResult};
 pub use filesystem::FileSystem;
 pub use impls::altroot::AltrootFS;
+pub use impls::caching::CachingFS;
+#[cfg(feature = "cas-fs")]
+pub use impls::cas::CasFS;
+pub use impls::case_insensitive::CaseInsensitiveFS;
+#[cfg(feature = "compressed-fs")]
+pub use impls::compressed::CompressedFS;
 #[cfg(feature = "embedded-fs")]
 pub use impls::embedded::EmbeddedFS;
+#[cfg(feature = "encrypted-fs")]
+pub use impls::encrypted::EncryptedFS;
+pub use impls::faulty::FaultyFS;
+pub use impls::filtered::FilteredFS;
 pub use impls::memory::MemoryFS;
+pub use impls::metrics::MetricsFS;
+pub use impls::mount::MountFS;
 pub use impls::overlay::OverlayFS;
 pub use impls::physical::PhysicalFS;
+pub use impls::readonly::ReadOnlyFS;
+#[cfg(feature = "sandboxed-fs")]
+pub use impls::sandboxed::SandboxedPhysicalFS;
+pub use impls::tracing::TracingFS;
+pub use impls::versioned::VersionedFS;
+#[cfg(feature = "parallel")]
+pub use parallel::ParWalkDirIterator;
 pub use path::*;
+pub use progress::{CancellationToken, DirOptions, Progress};
+pub use watch::{PollingEvents, PollingWatcher, WatchEvent};
@@ -0,0 +1,450 @@
+//! Parallel directory traversal for the synchronous API, requires the `parallel` feature
+//!
+//! [`VfsPath::par_walk_dir()`] and [`VfsPath::par_copy_dir()`] spread the per-entry calls to the
+//! filesystem over a pool of worker threads. This works with any [`FileSystem`](crate::FileSystem),
+//! since filesystems are `Send + Sync` already, and pays off for filesystems where each call has
+//! a noticeable latency, like [`PhysicalFS`](crate::PhysicalFS) on large trees.
+
+use crate::error::VfsErrorKind;
+use crate::{VfsError, VfsFileType, VfsPath, VfsResult};
+
+use std::num::NonZeroUsize;
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+impl VfsPath {
+    /// Recursively iterates over all the directories and files at this path, using up to `threads` threads
+    ///
+    /// Returns the same paths as [`walk_dir()`](VfsPath::walk_dir), but in no particular order,
+    /// except that directories are still visited before their children.
+    /// The entries are processed in the background, dropping the iterator stops the worker threads.
+    /// A panic of a worker thread is resumed by the iterator once the remaining workers have stopped.
+    ///
+    /// ```
+    /// # use vfs::{MemoryFS, VfsError, VfsPath, VfsResult};
+    /// let root = VfsPath::new(MemoryFS::new());
+    /// root.join("foo/bar")?.create_dir_all()?;
+    /// root.join("fizz/buzz")?.create_dir_all()?;
+    /// root.join("foo/bar/baz")?.create_file()?;
+    ///
+    /// let mut directories = root.par_walk_dir(4)?.collect::<VfsResult<Vec<_>>>()?;
+    ///
+    /// directories.sort_by_key(|path| path.as_str().to_string());
+    /// let expected = vec!["fizz", "fizz/buzz", "foo", "foo/bar", "foo/bar/baz"].iter().map(|path| root.join(path)).collect::<VfsResult<Vec<_>>>()?;
+    /// assert_eq!(directories, expected);
+    /// # Ok::<(), VfsError>(())
+    /// ```
+    pub fn par_walk_dir(&self, threads: usize) -> VfsResult<ParWalkDirIterator> {
+        let queue = Arc::new(WorkQueue::new(self.read_dir()?.collect()));
+        let (sender, receiver) = mpsc::channel();
+        let workers = spawn_workers(&queue, threads, move |path, queue| {
+            let result = path.metadata().map(|metadata| {
+                if metadata.file_type == VfsFileType::Directory {
+                    Some(path.clone())
+                } else {
+                    None
+                }
+            });
+            let directory = match result {
+                Ok(directory) => {
+                    if sender.send(Ok(path)).is_err() {
+                        return false;
+                    }
+                    directory
+                }
+                Err(err) => return sender.send(Err(err)).is_ok(),
+            };
+            // The children are only queued once their directory has been sent
+            if let Some(directory) = directory {
+                match directory.read_dir() {
+                    Ok(entries) => queue.push_all(entries),
+                    Err(err) => return sender.send(Err(err)).is_ok(),
+                }
+            }
+            true
+        });
+        Ok(ParWalkDirIterator {
+            receiver,
+            queue,
+            workers,
+        })
+    }
+
+    /// Copies a directory to a new destination, recursively, using several threads
+    ///
+    /// Behaves like [`copy_dir()`](VfsPath::copy_dir), but copies several files at once.
+    /// Each directory is created before any of its children are copied.
+    /// The copy stops at the first error, which is returned once the running copies have finished.
+    ///
+    /// The destination must not exist, but the parent directory must
+    ///
+    /// Returns the number of files copied
+    ///
+    /// ```
+    /// use vfs::{MemoryFS, VfsError, VfsPath};
+    /// let path = VfsPath::new(MemoryFS::new());
+    /// let src = path.join("foo")?;
+    /// src.join("dir")?.create_dir_all()?;
+    /// src.join("dir/a.txt")?.create_file()?.write_all(b"a")?;
+    /// let dest = path.join("bar")?;
+    ///
+    /// assert_eq!(src.par_copy_dir(&dest)?, 2);
+    ///
+    /// assert_eq!(dest.join("dir/a.txt")?.read_to_string()?, "a");
+    /// # Ok::<(), VfsError>(())
+    /// ```
+    pub fn par_copy_dir(&self, destination: &VfsPath) -> VfsResult<u64> {
+        let files_copied = Arc::new(AtomicU64::new(0));
+        || -> VfsResult<()> {
+            if destination.exists()? {
+                return Err(VfsError::from(VfsErrorKind::Other(
+                    "Destination exists already".into(),
+                ))
+                .with_path(destination.as_str()));
+            }
+            destination.create_dir()?;
+            let queue = Arc::new(WorkQueue::new(self.read_dir()?.collect()));
+            let first_error = Arc::new(Mutex::new(None));
+            let prefix_len = self.as_str().len();
+            let destination = destination.clone();
+            let files = files_copied.clone();
+            let error = first_error.clone();
+            let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+            let workers = spawn_workers(&queue, threads, move |src_path, queue| {
+                let result = || -> VfsResult<()> {
+                    let dest_path = destination.join(&src_path.as_str()[prefix_len + 1..])?;
+                    match src_path.metadata()?.file_type {
+                        VfsFileType::Directory => {
+                            dest_path.create_dir()?;
+                            queue.push_all(src_path.read_dir()?);
+                        }
+                        VfsFileType::File => src_path.copy_file(&dest_path)?,
+                    }
+                    files.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                }();
+                match result {
+                    Ok(()) => true,
+                    Err(err) => {
+                        error.lock().unwrap().get_or_insert(err);
+                        false
+                    }
+                }
+            });
+            for worker in workers {
+                worker.join().expect("parallel copy worker panicked");
+            }
+            let error = first_error.lock().unwrap().take();
+            match error {
+                Some(err) => Err(err),
+                None => Ok(()),
+            }
+        }()
+        .map_err(|err| {
+            err.with_path(self.as_str()).with_context(|| {
+                format!(
+                    "Could not copy directory '{}' to '{}'",
+                    self.as_str(),
+                    destination.as_str()
+                )
+            })
+        })?;
+        Ok(files_copied.load(Ordering::Relaxed))
+    }
+}
+
+/// An iterator over the results of a parallel walk, see [`VfsPath::par_walk_dir()`]
+pub struct ParWalkDirIterator {
+    receiver: Receiver<VfsResult<VfsPath>>,
+    queue: Arc<WorkQueue>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for ParWalkDirIterator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ParWalkDirIterator")
+    }
+}
+
+impl Iterator for ParWalkDirIterator {
+    type Item = VfsResult<VfsPath>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Ends once all workers have finished and dropped their senders
+        if let Ok(result) = self.receiver.recv() {
+            return Some(result);
+        }
+        for worker in self.workers.drain(..) {
+            if let Err(panic) = worker.join() {
+                panic::resume_unwind(panic);
+            }
+        }
+        None
+    }
+}
+
+impl Drop for ParWalkDirIterator {
+    fn drop(&mut self) {
+        self.queue.stop();
+    }
+}
+
+/// Spawns the given number of workers, at least one, each processing paths from the queue until it is drained
+///
+/// The work function returns false to stop all workers, as does a panic of the work function.
+fn spawn_workers<F>(queue: &Arc<WorkQueue>, threads: usize, work: F) -> Vec<JoinHandle<()>>
+where
+    F: Fn(VfsPath, &WorkQueue) -> bool + Clone + Send + 'static,
+{
+    (0..threads.max(1))
+        .map(|_| {
+            let queue = queue.clone();
+            let work = work.clone();
+            thread::spawn(move || {
+                while let Some(path) = queue.pop() {
+                    let processing = Processing(&queue);
+                    if !work(path, &queue) {
+                        queue.stop();
+                    }
+                    drop(processing);
+                }
+            })
+        })
+        .collect()
+}
+
+/// Marks a path returned by [`WorkQueue::pop()`] as processed when dropped, even if processing it panicked
+struct Processing<'a>(&'a WorkQueue);
+
+impl Drop for Processing<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.stop();
+        }
+        self.0.done();
+    }
+}
+
+/// The paths still to process, shared by the workers
+struct WorkQueue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+struct QueueState {
+    todo: Vec<VfsPath>,
+    /// number of paths currently being processed, which might add more work
+    busy: usize,
+    stopped: bool,
+}
+
+impl WorkQueue {
+    fn new(todo: Vec<VfsPath>) -> Self {
+        WorkQueue {
+            state: Mutex::new(QueueState {
+                todo,
+                busy: 0,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn push_all(&self, paths: impl Iterator<Item = VfsPath>) {
+        self.state.lock().unwrap().todo.extend(paths);
+        self.changed.notify_all();
+    }
+
+    /// Waits for the next path, returns None once all work is done or the queue was stopped
+    fn pop(&self) -> Option<VfsPath> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.stopped {
+                return None;
+            }
+            if let Some(path) = state.todo.pop() {
+                state.busy += 1;
+                return Some(path);
+            }
+            if state.busy == 0 {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Marks a path returned by `pop()` as processed
+    fn done(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.busy -= 1;
+        if state.busy == 0 {
+            self.changed.notify_all();
+        }
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spawn_workers, WorkQueue};
+    use crate::error::VfsErrorKind;
+    use crate::impls::faulty::{Fault, FaultRule};
+    use crate::impls::tracing::TraceOperation;
+    use crate::{FaultyFS, MemoryFS, PhysicalFS, VfsPath, VfsResult};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    fn create_tree() -> VfsResult<VfsPath> {
+        let root: VfsPath = MemoryFS::new().into();
+        for dir in 0..10 {
+            root.join(format!("dir{}/sub", dir))?.create_dir_all()?;
+            for file in 0..10 {
+                root.join(format!("dir{}/sub/{}.txt", dir, file))?
+                    .create_file()?
+                    .write_all(format!("{} {}", dir, file).as_bytes())?;
+            }
+        }
+        Ok(root)
+    }
+
+    fn sorted_paths(paths: impl Iterator<Item = VfsResult<VfsPath>>) -> VfsResult<Vec<String>> {
+        let mut paths = paths
+            .map(|path| path.map(|path| path.as_str().to_string()))
+            .collect::<VfsResult<Vec<_>>>()?;
+        paths.sort();
+        Ok(paths)
+    }
+
+    #[test]
+    fn par_walk_dir_matches_walk_dir() -> VfsResult<()> {
+        let root = create_tree()?;
+        let expected = sorted_paths(root.walk_dir()?)?;
+        assert_eq!(expected.len(), 120);
+        assert_eq!(sorted_paths(root.par_walk_dir(4)?)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn par_walk_dir_physical() -> VfsResult<()> {
+        let root: VfsPath = PhysicalFS::new("test/test_directory").into();
+        assert_eq!(
+            sorted_paths(root.par_walk_dir(4)?)?,
+            sorted_paths(root.walk_dir()?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn par_walk_dir_visits_directories_first() -> VfsResult<()> {
+        let root = create_tree()?;
+        let mut seen = HashSet::new();
+        for path in root.par_walk_dir(4)? {
+            let path = path?;
+            assert!(
+                path.parent().is_root() || seen.contains(path.parent().as_str()),
+                "{} visited before its parent",
+                path.as_str()
+            );
+            seen.insert(path.as_str().to_string());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn par_walk_dir_can_be_dropped_early() -> VfsResult<()> {
+        let root = create_tree()?;
+        assert_eq!(root.par_walk_dir(4)?.take(3).count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn panicking_worker_stops_the_others() -> VfsResult<()> {
+        let root = create_tree()?;
+        let queue = Arc::new(WorkQueue::new(root.read_dir()?.collect()));
+        let workers = spawn_workers(&queue, 4, |path, queue| {
+            if path.as_str() == "/dir5/sub" {
+                panic!("worker failed");
+            }
+            if let Ok(entries) = path.read_dir() {
+                queue.push_all(entries);
+            }
+            true
+        });
+        let panicked = workers
+            .into_iter()
+            .map(JoinHandle::join)
+            .filter(Result::is_err)
+            .count();
+        assert_eq!(panicked, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn par_walk_dir_missing() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let error = root.join("foo")?.par_walk_dir(4).expect_err("par_walk_dir");
+        assert!(matches!(error.kind(), VfsErrorKind::FileNotFound));
+        Ok(())
+    }
+
+    #[test]
+    fn par_copy_dir() -> VfsResult<()> {
+        let root = create_tree()?;
+        let dest = root.join("copy")?;
+        let src = root.join("dir3")?;
+        assert_eq!(src.par_copy_dir(&dest)?, 11);
+        assert_eq!(dest.join("sub/7.txt")?.read_to_string()?, "3 7");
+        assert_eq!(
+            sorted_paths(dest.walk_dir()?)?
+                .iter()
+                .map(|path| &path[5..])
+                .collect::<Vec<_>>(),
+            sorted_paths(src.walk_dir()?)?
+                .iter()
+                .map(|path| &path[5..])
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn par_copy_dir_to_existing() -> VfsResult<()> {
+        let root = create_tree()?;
+        let error_message = root
+            .join("dir1")?
+            .par_copy_dir(&root.join("dir2")?)
+            .expect_err("par_copy_dir")
+            .to_string();
+        assert!(
+            error_message.starts_with("Could not copy directory '/dir1' to '/dir2'"),
+            "Actual message: {}",
+            error_message
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn par_copy_dir_stops_at_error() -> VfsResult<()> {
+        let root: VfsPath = FaultyFS::new(create_tree()?)
+            .with_rule(
+                FaultRule::new(Fault::Error(VfsErrorKind::ReadOnly))
+                    .on(TraceOperation::CopyFile)
+                    .matching("5.txt"),
+            )
+            .into();
+        let error = root
+            .join("dir4")?
+            .par_copy_dir(&root.join("copy")?)
+            .expect_err("par_copy_dir");
+        assert!(matches!(error.kind(), VfsErrorKind::ReadOnly));
+        Ok(())
+    }
+}
@@ -8,7 +8,8 @@ use std::sync::Arc;
 use std::time::SystemTime;
 
 use crate::error::VfsErrorKind;
-use crate::{FileSystem, VfsError, VfsResult};
+use crate::progress::{DirOptions, Progress, CHUNK_SIZE};
+use crate::{FileSystem, VfsError, VfsResult, WatchEvent};
 
 /// Trait combining Seek and Read, return value for opening files
 pub trait SeekAndRead: Seek + Read {}
@@ -93,7 +94,7 @@ pub(crate) trait PathLike: Clone {
 }
 
 /// Type of file
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum VfsFileType {
     /// A plain file
     File,
@@ -102,7 +103,7 @@ pub enum VfsFileType {
 }
 
 /// File metadata information
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct VfsMetadata {
     /// The type of file
     pub file_type: VfsFileType,
@@ -226,6 +227,27 @@ impl VfsPath {
         self.path.is_empty()
     }
 
+    /// Returns a read-only view of the directory tree at this path
+    ///
+    /// The returned path is the root of a [`ReadOnlyFS`](crate::ReadOnlyFS), all modifications fail with
+    /// `VfsErrorKind::ReadOnly`
+    ///
+    /// ```
+    /// # use vfs::{MemoryFS, VfsError, VfsPath};
+    /// let path = VfsPath::new(MemoryFS::new());
+    /// path.join("foo")?.create_dir()?;
+    /// path.join("foo/bar.txt")?.create_file()?;
+    ///
+    /// let read_only = path.join("foo")?.read_only();
+    ///
+    /// assert!(read_only.join("bar.txt")?.exists()?);
+    /// assert!(read_only.join("baz.txt")?.create_file().is_err());
+    /// # Ok::<(), VfsError>(())
+    /// ```
+    pub fn read_only(&self) -> VfsPath {
+        crate::ReadOnlyFS::new(self.clone()).into()
+    }
+
     /// Creates the directory at this path
     ///
     /// Note that the parent directory must exist, while the given path must not exist.
@@ -495,6 +517,29 @@ impl VfsPath {
         Ok(())
     }
 
+    /// Watches this file or directory for changes, if the filesystem supports it
+    ///
+    /// Reports changes to this path and its direct children, or all its descendants if `recursive` is set.
+    /// The event paths are absolute within the filesystem, like the result of [`as_str()`](VfsPath::as_str).
+    /// The returned iterator blocks until the next change is reported.
+    ///
+    /// ```
+    /// use vfs::{MemoryFS, VfsError, VfsPath, WatchEvent};
+    /// let path = VfsPath::new(MemoryFS::new());
+    /// let mut events = path.watch(true)?;
+    ///
+    /// path.join("foo")?.create_dir()?;
+    ///
+    /// assert_eq!(events.next(), Some(WatchEvent::Created("/foo".into())));
+    /// # Ok::<(), VfsError>(())
+    /// ```
+    pub fn watch(&self, recursive: bool) -> VfsResult<Box<dyn Iterator<Item = WatchEvent> + Send>> {
+        self.fs.fs.watch(&self.path, recursive).map_err(|err| {
+            err.with_path(&*self.path)
+                .with_context(|| "Could not watch path")
+        })
+    }
+
     /// Returns the file metadata for the file at this path
     ///
     /// ```
@@ -812,7 +857,7 @@ impl VfsPath {
                 let result = self.fs.fs.copy_file(&self.path, &destination.path);
                 match result {
                     Err(err) => match err.kind() {
-                        VfsErrorKind::NotSupported => {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
                             // continue
                         }
                         _ => return Err(err),
@@ -871,7 +916,7 @@ impl VfsPath {
                 let result = self.fs.fs.move_file(&self.path, &destination.path);
                 match result {
                     Err(err) => match err.kind() {
-                        VfsErrorKind::NotSupported => {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
                             // continue
                         }
                         _ => return Err(err),
@@ -901,6 +946,23 @@ impl VfsPath {
         Ok(())
     }
 
+    /// Moves the file over the destination, replacing it
+    ///
+    /// The native move of the filesystem is tried first, which replaces the destination atomically on
+    /// filesystems like [`PhysicalFS`](crate::PhysicalFS). Otherwise the destination is removed before moving.
+    #[cfg(feature = "cas-fs")]
+    pub(crate) fn replace_file(&self, destination: &VfsPath) -> VfsResult<()> {
+        if Arc::ptr_eq(&self.fs, &destination.fs)
+            && self.fs.fs.move_file(&self.path, &destination.path).is_ok()
+        {
+            return Ok(());
+        }
+        if destination.exists()? {
+            destination.remove_file()?;
+        }
+        self.move_file(destination)
+    }
+
     /// Copies a directory to a new destination, recursively
     ///
     /// The destination must not exist, but the parent directory must
@@ -985,7 +1047,7 @@ impl VfsPath {
                 let result = self.fs.fs.move_dir(&self.path, &destination.path);
                 match result {
                     Err(err) => match err.kind() {
-                        VfsErrorKind::NotSupported => {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
                             // continue
                         }
                         _ => return Err(err),
@@ -1018,6 +1080,238 @@ impl VfsPath {
         })?;
         Ok(())
     }
+
+    /// Copies a directory to a new destination like [`copy_dir()`](VfsPath::copy_dir),
+    /// reporting progress and checking for cancellation
+    ///
+    /// The files are copied in chunks, progress is reported and cancellation checked after each chunk.
+    /// A cancelled copy fails with [`VfsErrorKind::Cancelled`], holding the progress made so far,
+    /// and leaves the destination partially copied.
+    ///
+    /// Returns the number of files copied
+    ///
+    /// ```
+    /// use vfs::{CancellationToken, DirOptions, MemoryFS, VfsError, VfsPath};
+    /// use vfs::error::VfsErrorKind;
+    /// let path = VfsPath::new(MemoryFS::new());
+    /// let src = path.join("foo")?;
+    /// src.join("dir")?.create_dir_all()?;
+    /// src.join("dir/a.txt")?.create_file()?.write_all(b"a")?;
+    ///
+    /// let token = CancellationToken::new();
+    /// let options = DirOptions::new().with_cancellation(token.clone());
+    /// assert_eq!(src.copy_dir_with(&path.join("bar")?, &options)?, 2);
+    ///
+    /// token.cancel();
+    /// let error = src.copy_dir_with(&path.join("baz")?, &options).unwrap_err();
+    /// assert!(matches!(error.kind(), VfsErrorKind::Cancelled(progress) if progress.files_total == 1));
+    /// # Ok::<(), VfsError>(())
+    /// ```
+    pub fn copy_dir_with(&self, destination: &VfsPath, options: &DirOptions) -> VfsResult<u64> {
+        || -> VfsResult<u64> {
+            if destination.exists()? {
+                return Err(VfsError::from(VfsErrorKind::Other(
+                    "Destination exists already".into(),
+                ))
+                .with_path(&*destination.path));
+            }
+            self.copy_entries_with(destination, options)
+        }()
+        .map_err(|err| {
+            err.with_path(&*self.path).with_context(|| {
+                format!(
+                    "Could not copy directory '{}' to '{}'",
+                    self.as_str(),
+                    destination.as_str()
+                )
+            })
+        })
+    }
+
+    /// Moves a directory to a new destination like [`move_dir()`](VfsPath::move_dir),
+    /// reporting progress and checking for cancellation
+    ///
+    /// If the filesystem can move the directory itself, this happens in a single step without
+    /// progress reports. Otherwise the directory is copied like in [`copy_dir_with()`](VfsPath::copy_dir_with)
+    /// and the source is removed once everything was copied. A cancelled move leaves the source untouched
+    /// and the destination partially copied.
+    ///
+    /// ```
+    /// use vfs::{DirOptions, MemoryFS, VfsError, VfsPath};
+    /// let path = VfsPath::new(MemoryFS::new());
+    /// let src = path.join("foo")?;
+    /// src.join("dir")?.create_dir_all()?;
+    /// let dest = path.join("bar")?;
+    ///
+    /// src.move_dir_with(&dest, &DirOptions::new())?;
+    ///
+    /// assert!(dest.join("dir")?.exists()?);
+    /// assert!(!src.exists()?);
+    /// # Ok::<(), VfsError>(())
+    /// ```
+    pub fn move_dir_with(&self, destination: &VfsPath, options: &DirOptions) -> VfsResult<()> {
+        || -> VfsResult<()> {
+            if destination.exists()? {
+                return Err(VfsError::from(VfsErrorKind::Other(
+                    "Destination exists already".into(),
+                ))
+                .with_path(&*destination.path));
+            }
+            options.check_cancelled(&Progress::default())?;
+            if Arc::ptr_eq(&self.fs, &destination.fs) {
+                let result = self.fs.fs.move_dir(&self.path, &destination.path);
+                match result {
+                    Err(err) => match err.kind() {
+                        VfsErrorKind::NotSupported | VfsErrorKind::CrossMount => {
+                            // continue
+                        }
+                        _ => return Err(err),
+                    },
+                    other => return other,
+                }
+            }
+            self.copy_entries_with(destination, options)?;
+            self.remove_dir_all()?;
+            Ok(())
+        }()
+        .map_err(|err| {
+            err.with_path(&*self.path).with_context(|| {
+                format!(
+                    "Could not move directory '{}' to '{}'",
+                    self.as_str(),
+                    destination.as_str()
+                )
+            })
+        })
+    }
+
+    /// Ensures that the directory at this path is removed like [`remove_dir_all()`](VfsPath::remove_dir_all),
+    /// reporting progress and checking for cancellation
+    ///
+    /// The files are removed first, progress is reported and cancellation checked after each file.
+    /// A cancelled removal fails with [`VfsErrorKind::Cancelled`], holding the progress made so far,
+    /// and leaves the remaining files and all directories in place.
+    ///
+    /// ```
+    /// use vfs::{DirOptions, MemoryFS, VfsError, VfsPath};
+    /// let path = VfsPath::new(MemoryFS::new());
+    /// let directory = path.join("foo")?;
+    /// directory.join("bar")?.create_dir_all()?;
+    /// directory.join("bar/baz.txt")?.create_file()?;
+    ///
+    /// directory.remove_dir_all_with(&DirOptions::new().on_progress(|progress| {
+    ///     assert_eq!(progress.files_total, 1);
+    /// }))?;
+    ///
+    /// assert!(!directory.exists()?);
+    /// # Ok::<(), VfsError>(())
+    /// ```
+    pub fn remove_dir_all_with(&self, options: &DirOptions) -> VfsResult<()> {
+        if !self.exists()? {
+            return Ok(());
+        }
+        || -> VfsResult<()> {
+            let mut progress = Progress::default();
+            let entries = self.scan_dir(&mut progress)?;
+            options.report(&progress);
+            let mut directories = vec![];
+            for (path, metadata) in entries {
+                match metadata.file_type {
+                    VfsFileType::File => {
+                        options.check_cancelled(&progress)?;
+                        path.remove_file()?;
+                        progress.files_done += 1;
+                        progress.bytes_done += metadata.len;
+                        options.report(&progress);
+                    }
+                    VfsFileType::Directory => directories.push(path),
+                }
+            }
+            // Directories are walked before their children, so the children are removed first here
+            for directory in directories.into_iter().rev() {
+                directory.remove_dir()?;
+            }
+            self.remove_dir()
+        }()
+        .map_err(|err| {
+            err.with_path(&*self.path)
+                .with_context(|| format!("Could not remove directory '{}'", self.as_str()))
+        })
+    }
+
+    /// Collects all entries below this directory, adding the files to the totals of the progress
+    fn scan_dir(&self, progress: &mut Progress) -> VfsResult<Vec<(VfsPath, VfsMetadata)>> {
+        let mut entries = vec![];
+        for path in self.walk_dir()? {
+            let path = path?;
+            let metadata = path.metadata()?;
+            if metadata.file_type == VfsFileType::File {
+                progress.files_total += 1;
+                progress.bytes_total += metadata.len;
+            }
+            entries.push((path, metadata));
+        }
+        Ok(entries)
+    }
+
+    /// Copies the contents of this directory to the not yet existing destination, reporting progress
+    fn copy_entries_with(&self, destination: &VfsPath, options: &DirOptions) -> VfsResult<u64> {
+        let mut progress = Progress::default();
+        let entries = self.scan_dir(&mut progress)?;
+        options.report(&progress);
+        destination.create_dir()?;
+        let prefix_len = self.path.len();
+        let mut files_copied = 0u64;
+        for (src_path, metadata) in entries {
+            options.check_cancelled(&progress)?;
+            let dest_path = destination.join(&src_path.as_str()[prefix_len + 1..])?;
+            match metadata.file_type {
+                VfsFileType::Directory => dest_path.create_dir()?,
+                VfsFileType::File => {
+                    src_path.copy_file_chunked(&dest_path, options, &mut progress)?;
+                    progress.files_done += 1;
+                    options.report(&progress);
+                }
+            }
+            files_copied += 1;
+        }
+        Ok(files_copied)
+    }
+
+    /// Copies a file chunk by chunk, reporting progress and checking for cancellation between chunks
+    fn copy_file_chunked(
+        &self,
+        destination: &VfsPath,
+        options: &DirOptions,
+        progress: &mut Progress,
+    ) -> VfsResult<()> {
+        let mut src = self.open_file()?;
+        let mut dest = destination.create_file()?;
+        let mut buffer = vec![0; CHUNK_SIZE];
+        loop {
+            let read = src.read(&mut buffer).map_err(|source| {
+                VfsError::from(source)
+                    .with_path(&*self.path)
+                    .with_context(|| "Could not read path")
+            })?;
+            if read == 0 {
+                break;
+            }
+            options.check_cancelled(progress)?;
+            dest.write_all(&buffer[..read]).map_err(|source| {
+                VfsError::from(source)
+                    .with_path(&*destination.path)
+                    .with_context(|| "Could not write path")
+            })?;
+            progress.bytes_done += read as u64;
+            options.report(progress);
+        }
+        dest.flush().map_err(|source| {
+            VfsError::from(source)
+                .with_path(&*destination.path)
+                .with_context(|| "Could not write path")
+        })
+    }
 }
 
 /// An iterator for recursively walking a file hierarchy
@@ -0,0 +1,230 @@
+//! Progress reporting and cancellation for long-running directory operations
+//!
+//! Operations like [`VfsPath::copy_dir_with()`](crate::VfsPath::copy_dir_with) take a [`DirOptions`],
+//! which reports the [`Progress`] after each file and each chunk of a file, and stops the operation
+//! once its [`CancellationToken`] is cancelled. A cancelled operation fails with
+//! [`VfsErrorKind::Cancelled`](crate::error::VfsErrorKind::Cancelled), which holds the progress made so far.
+
+use crate::error::VfsErrorKind;
+use crate::{VfsError, VfsResult};
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The size of the chunks in which files are copied, progress is reported and cancellation is checked
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The progress of a directory operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of files processed so far
+    pub files_done: u64,
+    /// Number of files to process in total
+    pub files_total: u64,
+    /// Number of bytes processed so far
+    pub bytes_done: u64,
+    /// Number of bytes to process in total
+    pub bytes_total: u64,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} files, {} of {} bytes",
+            self.files_done, self.files_total, self.bytes_done, self.bytes_total
+        )
+    }
+}
+
+/// A token to cancel a running operation, possibly from another thread
+///
+/// Clones share the same cancellation state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a new token that is not cancelled yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations using this token, they stop at the next file or chunk
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Options for observing and cancelling directory operations
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+/// use vfs::{CancellationToken, DirOptions, MemoryFS, VfsError, VfsPath};
+/// let root = VfsPath::new(MemoryFS::new());
+/// root.join("foo/bar")?.create_dir_all()?;
+/// root.join("foo/bar/baz.txt")?.create_file()?.write_all(b"Hello")?;
+///
+/// let bytes = Arc::new(AtomicU64::new(0));
+/// let reported = bytes.clone();
+/// let options = DirOptions::new()
+///     .on_progress(move |progress| reported.store(progress.bytes_done, Ordering::SeqCst))
+///     .with_cancellation(CancellationToken::new());
+/// root.join("foo")?.copy_dir_with(&root.join("copy")?, &options)?;
+///
+/// assert_eq!(bytes.load(Ordering::SeqCst), 5);
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct DirOptions {
+    progress: Option<Arc<dyn Fn(&Progress) + Send + Sync>>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl fmt::Debug for DirOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirOptions")
+            .field("progress", &self.progress.is_some())
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
+
+impl DirOptions {
+    /// Create options without progress reporting or cancellation
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls the given function with the current progress after each file and each chunk of a file
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Stops the operation once the given token is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub(crate) fn report(&self, progress: &Progress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
+
+    /// Fails with a [`VfsErrorKind::Cancelled`] error if the operation was cancelled
+    pub(crate) fn check_cancelled(&self, progress: &Progress) -> VfsResult<()> {
+        match &self.cancellation {
+            Some(token) if token.is_cancelled() => {
+                Err(VfsError::from(VfsErrorKind::Cancelled(*progress)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryFS, VfsPath};
+    use std::sync::Mutex;
+
+    fn create_large_file(root: &VfsPath) -> VfsResult<VfsPath> {
+        let src = root.join("src")?;
+        src.create_dir()?;
+        let content: Vec<u8> = (0..3 * CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        src.join("large.bin")?.create_file()?.write_all(&content)?;
+        Ok(src)
+    }
+
+    #[test]
+    fn progress_is_reported_per_chunk() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let src = create_large_file(&root)?;
+        let reports = Arc::new(Mutex::new(vec![]));
+        let recorded = reports.clone();
+        let options = DirOptions::new()
+            .on_progress(move |progress| recorded.lock().unwrap().push(progress.bytes_done));
+        src.copy_dir_with(&root.join("dest")?, &options)?;
+        let chunk = CHUNK_SIZE as u64;
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![
+                0,
+                chunk,
+                2 * chunk,
+                3 * chunk,
+                3 * chunk + 100,
+                3 * chunk + 100
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cancelled_between_chunks() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let src = create_large_file(&root)?;
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let options = DirOptions::new()
+            .with_cancellation(token)
+            .on_progress(move |progress| {
+                if progress.bytes_done > 0 {
+                    cancel.cancel();
+                }
+            });
+        let error = src
+            .copy_dir_with(&root.join("dest")?, &options)
+            .expect_err("copy_dir_with");
+        let expected = Progress {
+            files_done: 0,
+            files_total: 1,
+            bytes_done: CHUNK_SIZE as u64,
+            bytes_total: 3 * CHUNK_SIZE as u64 + 100,
+        };
+        assert!(matches!(error.kind(), VfsErrorKind::Cancelled(progress) if *progress == expected));
+        assert_eq!(
+            error.to_string(),
+            "Could not copy directory '/src' to '/dest' for '/src': \
+             The operation was cancelled after 0 of 1 files, 65536 of 196708 bytes"
+        );
+        assert_eq!(
+            root.join("dest/large.bin")?.metadata()?.len,
+            CHUNK_SIZE as u64
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cancelled_before_start() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let src = create_large_file(&root)?;
+        let token = CancellationToken::new();
+        token.cancel();
+        let error = src
+            .move_dir_with(
+                &root.join("dest")?,
+                &DirOptions::new().with_cancellation(token),
+            )
+            .expect_err("move_dir_with");
+        assert!(
+            matches!(error.kind(), VfsErrorKind::Cancelled(progress) if progress.bytes_done == 0)
+        );
+        assert!(src.join("large.bin")?.exists()?);
+        Ok(())
+    }
+}
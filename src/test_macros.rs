@@ -693,6 +693,7 @@ use super::*;
             }
 
             #[test]
+            #[allow(clippy::useless_vec)]
             fn read_to_string_nonutf8() -> VfsResult<()> {
                 let root = create_root();
                 let path = root.join("foobar.txt")?;
@@ -976,6 +977,109 @@ use super::*;
                Ok(())
             }
 
+            #[test]
+            fn copy_dir_with_progress() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.join("bar/biz")?.create_dir_all()?;
+                src.join("bar/baz.txt")?.create_file()?.write_all(b"Hello World")?;
+                src.join("bar/biz/a.txt")?.create_file()?.write_all(b"a")?;
+
+                let reports = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+                let recorded = reports.clone();
+                let options = $crate::DirOptions::new()
+                    .on_progress(move |progress| recorded.lock().unwrap().push(*progress));
+                let dest = root.join("foo2")?;
+                assert_eq!(src.copy_dir_with(&dest, &options)?, 4);
+                assert_eq!(&dest.join("bar/baz.txt")?.read_to_string()?, "Hello World");
+                assert_eq!(&dest.join("bar/biz/a.txt")?.read_to_string()?, "a");
+                let reports = reports.lock().unwrap();
+                assert_eq!(
+                    reports.last(),
+                    Some(&$crate::Progress {
+                        files_done: 2,
+                        files_total: 2,
+                        bytes_done: 12,
+                        bytes_total: 12,
+                    })
+                );
+                Ok(())
+            }
+
+            #[test]
+            fn copy_dir_with_cancelled() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.join("bar")?.create_dir_all()?;
+                src.join("bar/baz.txt")?.create_file()?.write_all(b"Hello World")?;
+                src.join("a.txt")?.create_file()?.write_all(b"a")?;
+
+                let token = $crate::CancellationToken::new();
+                let cancel = token.clone();
+                let options = $crate::DirOptions::new()
+                    .with_cancellation(token)
+                    .on_progress(move |progress| {
+                        if progress.files_done == 1 {
+                            cancel.cancel();
+                        }
+                    });
+                let error = src.copy_dir_with(&root.join("foo2")?, &options).expect_err("copy_dir_with");
+                match error.kind() {
+                    VfsErrorKind::Cancelled(progress) => {
+                        assert_eq!((progress.files_done, progress.files_total), (1, 2));
+                    }
+                    other => panic!("Unexpected error: {}", other),
+                }
+                assert!(
+                    error.to_string().starts_with("Could not copy directory '/foo' to '/foo2'"),
+                    "Actual message: {}",
+                    error
+                );
+                assert!(src.join("bar/baz.txt")?.exists()?, "source should be untouched");
+                Ok(())
+            }
+
+            #[test]
+            fn move_dir_with() -> VfsResult<()> {
+                let root = create_root();
+                let src = root.join("foo")?;
+                src.join("bar/biz")?.create_dir_all()?;
+                src.join("bar/baz.txt")?.create_file()?.write_all(b"Hello World")?;
+
+                let dest = root.join("foo2")?;
+                src.move_dir_with(&dest, &$crate::DirOptions::new())?;
+                assert_eq!(&dest.join("bar/baz.txt")?.read_to_string()?, "Hello World");
+                assert!(&dest.join("bar/biz")?.exists()?, "directory should exist");
+                assert!(!src.exists()?, "source directory should not exist");
+                Ok(())
+            }
+
+            #[test]
+            fn remove_dir_all_with_cancelled() -> VfsResult<()> {
+                let root = create_root();
+                let path = root.join("foo")?;
+                path.join("bar")?.create_dir_all()?;
+                path.join("bar/a.txt")?.create_file()?.write_all(b"a")?;
+                path.join("b.txt")?.create_file()?.write_all(b"bb")?;
+
+                let token = $crate::CancellationToken::new();
+                let cancel = token.clone();
+                let options = $crate::DirOptions::new()
+                    .with_cancellation(token.clone())
+                    .on_progress(move |progress| {
+                        if progress.files_done == 1 {
+                            cancel.cancel();
+                        }
+                    });
+                let error = path.remove_dir_all_with(&options).expect_err("remove_dir_all_with");
+                assert!(matches!(error.kind(), VfsErrorKind::Cancelled(progress) if progress.files_done == 1));
+                assert!(path.exists()?);
+
+                path.remove_dir_all_with(&$crate::DirOptions::new())?;
+                assert!(!path.exists()?);
+                Ok(())
+            }
+
             #[test]
             fn is_file_is_dir() -> VfsResult<()> {
                 let root = create_root();
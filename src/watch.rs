@@ -0,0 +1,690 @@
+//! Change events for watching filesystems
+//!
+//! [`FileSystem::watch()`](crate::FileSystem::watch) and, with the `async-vfs-core` feature,
+//! `AsyncFileSystem::watch()` report the changes below a path as [`WatchEvent`]s.
+//! Watching is optional, filesystems without support fail with
+//! [`VfsErrorKind::NotSupported`](crate::error::VfsErrorKind::NotSupported).
+//! A [`PollingWatcher`] reports the changes of any filesystem by comparing snapshots of the tree instead.
+
+use crate::error::VfsErrorKind;
+use crate::progress::{CancellationToken, CHUNK_SIZE};
+use crate::{VfsFileType, VfsPath, VfsResult};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hasher;
+use std::io::Read;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A change to a file or directory
+///
+/// Paths are absolute within the watched filesystem, like the paths passed to
+/// [`FileSystem`](crate::FileSystem) methods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// A file or directory was created
+    Created(String),
+    /// The content of a file was written
+    Modified(String),
+    /// A file or directory was removed
+    Removed(String),
+    /// A file or directory was moved or renamed
+    Renamed {
+        /// The previous path
+        from: String,
+        /// The new path
+        to: String,
+    },
+}
+
+impl WatchEvent {
+    /// Returns the path affected by this event, the new path for renames
+    pub fn path(&self) -> &str {
+        match self {
+            WatchEvent::Created(path) | WatchEvent::Modified(path) | WatchEvent::Removed(path) => {
+                path
+            }
+            WatchEvent::Renamed { to, .. } => to,
+        }
+    }
+
+    /// Translates the paths of this event, `translate` returns None for paths that are not visible
+    ///
+    /// Renames from or to invisible paths become creations or removals, other events of invisible paths are dropped.
+    pub(crate) fn translate<F>(self, translate: F) -> Option<WatchEvent>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        match self {
+            WatchEvent::Created(path) => translate(&path).map(WatchEvent::Created),
+            WatchEvent::Modified(path) => translate(&path).map(WatchEvent::Modified),
+            WatchEvent::Removed(path) => translate(&path).map(WatchEvent::Removed),
+            WatchEvent::Renamed { from, to } => match (translate(&from), translate(&to)) {
+                (Some(from), Some(to)) => Some(WatchEvent::Renamed { from, to }),
+                (Some(from), None) => Some(WatchEvent::Removed(from)),
+                (None, Some(to)) => Some(WatchEvent::Created(to)),
+                (None, None) => None,
+            },
+        }
+    }
+
+    fn concerns(&self, watched: &str, recursive: bool) -> bool {
+        match self {
+            WatchEvent::Renamed { from, to } => {
+                is_watched(from, watched, recursive) || is_watched(to, watched, recursive)
+            }
+            other => is_watched(other.path(), watched, recursive),
+        }
+    }
+}
+
+/// Returns true if changes to `path` are reported by a watch of `watched`
+///
+/// A watch covers the watched path itself and its direct children, or all descendants if it is recursive.
+pub(crate) fn is_watched(path: &str, watched: &str, recursive: bool) -> bool {
+    if path == watched {
+        return true;
+    }
+    match path.strip_prefix(watched) {
+        Some(rest) if rest.starts_with('/') => recursive || !rest[1..].contains('/'),
+        _ => false,
+    }
+}
+
+/// The watches registered with a filesystem emitting its own events
+#[derive(Default)]
+pub(crate) struct Watchers {
+    watchers: Mutex<Vec<Watcher>>,
+}
+
+struct Watcher {
+    path: String,
+    recursive: bool,
+    /// Delivers an event, returns false once the receiving side is gone
+    sink: Box<dyn Fn(&WatchEvent) -> bool + Send>,
+}
+
+impl fmt::Debug for Watchers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Watchers({})", self.watchers.lock().unwrap().len())
+    }
+}
+
+impl Watchers {
+    /// Registers a watch delivering events to the given sink
+    pub(crate) fn add<F>(&self, path: &str, recursive: bool, sink: F)
+    where
+        F: Fn(&WatchEvent) -> bool + Send + 'static,
+    {
+        self.watchers.lock().unwrap().push(Watcher {
+            path: path.to_string(),
+            recursive,
+            sink: Box::new(sink),
+        });
+    }
+
+    /// Registers a watch, returning a blocking iterator over its events
+    pub(crate) fn subscribe(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> Box<dyn Iterator<Item = WatchEvent> + Send> {
+        let (sender, receiver) = mpsc::channel();
+        self.add(path, recursive, move |event| {
+            sender.send(event.clone()).is_ok()
+        });
+        Box::new(receiver.into_iter())
+    }
+
+    /// Delivers an event to all matching watches, dropping those whose receivers are gone
+    pub(crate) fn emit(&self, event: WatchEvent) {
+        self.watchers.lock().unwrap().retain(|watcher| {
+            !event.concerns(&watcher.path, watcher.recursive) || (watcher.sink)(&event)
+        });
+    }
+}
+
+/// Watches any filesystem by periodically comparing snapshots of the tree
+///
+/// A snapshot holds the names, sizes and modification times of all entries, and optionally
+/// hashes of the file contents. Once a change is seen, the watcher waits until the tree has
+/// not changed for the debounce period, so a burst of changes is compared as a whole.
+/// Changes of a burst lasting longer than the maximum debounce period are reported nevertheless.
+/// Removed and created files with the same size, modification time and hash are reported as renamed.
+///
+/// This works for filesystems without native change notifications, and reports the same events as
+/// [`VfsPath::watch()`], so it can take over where that is not supported:
+///
+/// ```
+/// use std::time::Duration;
+/// use vfs::{MemoryFS, PollingWatcher, VfsError, VfsPath, WatchEvent};
+/// let path = VfsPath::new(MemoryFS::new());
+/// let watcher = PollingWatcher::new(path.clone())
+///     .interval(Duration::from_millis(10))
+///     .debounce(Duration::ZERO);
+/// let mut events = watcher.watch(true)?;
+///
+/// path.join("foo")?.create_dir()?;
+///
+/// assert_eq!(events.next(), Some(WatchEvent::Created("/foo".into())));
+/// # Ok::<(), VfsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct PollingWatcher {
+    path: VfsPath,
+    interval: Duration,
+    debounce: Duration,
+    max_debounce: Duration,
+    compare_contents: bool,
+    cancellation: Option<CancellationToken>,
+}
+
+impl PollingWatcher {
+    /// Create a watcher for the given path, polling every second with a debounce period of 200ms of at most 2s
+    pub fn new(path: VfsPath) -> Self {
+        PollingWatcher {
+            path,
+            interval: Duration::from_secs(1),
+            debounce: Duration::from_millis(200),
+            max_debounce: Duration::from_secs(2),
+            compare_contents: false,
+            cancellation: None,
+        }
+    }
+
+    /// Sets the time between two snapshots
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how long the tree must stay unchanged before its changes are reported
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Sets how long the changes of a burst are held back at most, even if the tree keeps changing
+    pub fn max_debounce(mut self, max_debounce: Duration) -> Self {
+        self.max_debounce = max_debounce;
+        self
+    }
+
+    /// Hashes the contents of all files, detecting changes that keep the size and modification time
+    ///
+    /// This reads every file for every snapshot.
+    pub fn compare_contents(mut self, compare_contents: bool) -> Self {
+        self.compare_contents = compare_contents;
+        self
+    }
+
+    /// Ends the iteration over the events once the given token is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Starts watching the path and its direct children, or all its descendants if `recursive` is set
+    ///
+    /// The returned iterator blocks until the next change is seen.
+    pub fn watch(&self, recursive: bool) -> VfsResult<PollingEvents> {
+        let watch = || -> VfsResult<PollingEvents> {
+            if !self.path.exists()? {
+                return Err(VfsErrorKind::FileNotFound.into());
+            }
+            Ok(PollingEvents {
+                snapshot: self.snapshot(recursive),
+                watcher: self.clone(),
+                recursive,
+                burst: None,
+                pending: VecDeque::new(),
+            })
+        };
+        watch().map_err(|err| {
+            err.with_path(self.path.as_str())
+                .with_context(|| "Could not watch path")
+        })
+    }
+
+    fn is_cancelled(&self) -> bool {
+        matches!(&self.cancellation, Some(token) if token.is_cancelled())
+    }
+
+    /// Takes a snapshot of the tree, entries vanishing while it is taken are left out
+    fn snapshot(&self, recursive: bool) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        let is_dir = match self.add_entry(&mut snapshot, &self.path) {
+            Some(file_type) => file_type == VfsFileType::Directory,
+            None => return snapshot,
+        };
+        if !is_dir {
+            return snapshot;
+        }
+        let entries: Box<dyn Iterator<Item = VfsResult<VfsPath>>> = if recursive {
+            match self.path.walk_dir() {
+                Ok(entries) => Box::new(entries),
+                Err(_) => return snapshot,
+            }
+        } else {
+            match self.path.read_dir() {
+                Ok(entries) => Box::new(entries.map(Ok)),
+                Err(_) => return snapshot,
+            }
+        };
+        for entry in entries.flatten() {
+            self.add_entry(&mut snapshot, &entry);
+        }
+        snapshot
+    }
+
+    fn add_entry(&self, snapshot: &mut Snapshot, path: &VfsPath) -> Option<VfsFileType> {
+        let metadata = path.metadata().ok()?;
+        let state = match metadata.file_type {
+            VfsFileType::Directory => EntryState {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                modified: None,
+                hash: None,
+            },
+            VfsFileType::File => EntryState {
+                file_type: VfsFileType::File,
+                len: metadata.len,
+                modified: metadata.modified,
+                hash: match self.compare_contents {
+                    true => Some(hash_content(path).ok()?),
+                    false => None,
+                },
+            },
+        };
+        snapshot.insert(path.as_str().to_string(), state);
+        Some(metadata.file_type)
+    }
+}
+
+/// The state of a file or directory in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct EntryState {
+    file_type: VfsFileType,
+    len: u64,
+    modified: Option<SystemTime>,
+    hash: Option<u64>,
+}
+
+impl EntryState {
+    /// Returns true if this state identifies a file well enough to detect renames
+    fn is_identifiable(&self) -> bool {
+        self.file_type == VfsFileType::File && (self.modified.is_some() || self.hash.is_some())
+    }
+}
+
+type Snapshot = BTreeMap<String, EntryState>;
+
+fn hash_content(path: &VfsPath) -> VfsResult<u64> {
+    let mut file = path.open_file()?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
+/// Returns the events turning the old snapshot into the new one
+///
+/// Removals come first, deepest paths first, followed by renames, creations and modifications.
+fn compare(old: &Snapshot, new: &Snapshot) -> Vec<WatchEvent> {
+    let mut removed = vec![];
+    let mut created = vec![];
+    let mut modified = vec![];
+    for (path, state) in old {
+        match new.get(path) {
+            None => removed.push(path),
+            Some(current) if current.file_type != state.file_type => {
+                removed.push(path);
+                created.push(path);
+            }
+            Some(current) if current != state => modified.push(path),
+            Some(_) => {}
+        }
+    }
+    created.extend(new.keys().filter(|path| !old.contains_key(*path)));
+    created.sort();
+
+    // Files that vanished and appeared with the same state were renamed, if that pairing is unambiguous
+    let vanished = group_by_state(&removed, old);
+    let appeared = group_by_state(&created, new);
+    let mut renamed = vec![];
+    let mut renamed_to = HashSet::new();
+    removed.retain(|from| {
+        let state = &old[*from];
+        match (vanished.get(state), appeared.get(state)) {
+            (Some(from_paths), Some(to_paths)) if from_paths.len() == 1 && to_paths.len() == 1 => {
+                renamed_to.insert(to_paths[0]);
+                renamed.push(WatchEvent::Renamed {
+                    from: from.to_string(),
+                    to: to_paths[0].to_string(),
+                });
+                false
+            }
+            _ => true,
+        }
+    });
+    created.retain(|path| !renamed_to.contains(path));
+
+    removed
+        .into_iter()
+        .rev()
+        .map(|path| WatchEvent::Removed(path.clone()))
+        .chain(renamed)
+        .chain(
+            created
+                .into_iter()
+                .map(|path| WatchEvent::Created(path.clone())),
+        )
+        .chain(
+            modified
+                .into_iter()
+                .map(|path| WatchEvent::Modified(path.clone())),
+        )
+        .collect()
+}
+
+/// Groups the paths of identifiable files by their state
+fn group_by_state<'a>(
+    paths: &[&'a String],
+    snapshot: &'a Snapshot,
+) -> HashMap<&'a EntryState, Vec<&'a String>> {
+    let mut groups: HashMap<&EntryState, Vec<&String>> = HashMap::new();
+    for path in paths {
+        let state = &snapshot[*path];
+        if state.is_identifiable() {
+            groups.entry(state).or_default().push(path);
+        }
+    }
+    groups
+}
+
+/// A blocking iterator over the changes seen by a [`PollingWatcher`]
+///
+/// The iteration ends once the cancellation token of the watcher is cancelled.
+#[derive(Debug)]
+pub struct PollingEvents {
+    watcher: PollingWatcher,
+    recursive: bool,
+    /// The tree as of the last reported changes
+    snapshot: Snapshot,
+    burst: Option<Burst>,
+    pending: VecDeque<WatchEvent>,
+}
+
+/// Changes seen but not reported yet
+#[derive(Debug)]
+struct Burst {
+    latest: Snapshot,
+    started: Instant,
+    changed: Instant,
+}
+
+impl PollingEvents {
+    /// Takes in a snapshot taken at `now`, queuing the changes once the tree settled or the burst lasted too long
+    fn observe(&mut self, current: Snapshot, now: Instant) {
+        match &mut self.burst {
+            None if current == self.snapshot => return,
+            None => {
+                self.burst = Some(Burst {
+                    latest: current,
+                    started: now,
+                    changed: now,
+                })
+            }
+            Some(burst) if burst.latest != current => {
+                burst.latest = current;
+                burst.changed = now;
+            }
+            Some(_) => {}
+        }
+        if let Some(burst) = &self.burst {
+            if now.duration_since(burst.changed) < self.watcher.debounce
+                && now.duration_since(burst.started) < self.watcher.max_debounce
+            {
+                return;
+            }
+        }
+        if let Some(burst) = self.burst.take() {
+            self.pending.extend(compare(&self.snapshot, &burst.latest));
+            self.snapshot = burst.latest;
+        }
+    }
+
+    /// How long to wait for the next snapshot
+    fn delay(&self) -> Duration {
+        match &self.burst {
+            Some(burst) => {
+                let remaining = self
+                    .watcher
+                    .max_debounce
+                    .saturating_sub(burst.started.elapsed());
+                self.watcher.debounce.min(remaining)
+            }
+            None => self.watcher.interval,
+        }
+    }
+}
+
+impl Iterator for PollingEvents {
+    type Item = WatchEvent;
+
+    fn next(&mut self) -> Option<WatchEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            thread::sleep(self.delay());
+            if self.watcher.is_cancelled() {
+                return None;
+            }
+            let current = self.watcher.snapshot(self.recursive);
+            self.observe(current, Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFS;
+
+    #[test]
+    fn watched_paths() {
+        assert!(is_watched("/a", "/a", false));
+        assert!(is_watched("/a/b", "/a", false));
+        assert!(!is_watched("/a/b/c", "/a", false));
+        assert!(is_watched("/a/b/c", "/a", true));
+        assert!(!is_watched("/ab", "/a", true));
+        assert!(is_watched("/a", "", false));
+        assert!(!is_watched("/a/b", "", false));
+        assert!(is_watched("/a/b", "", true));
+    }
+
+    #[test]
+    fn translate_renames() {
+        let inside = |path: &str| path.strip_prefix("/root").map(str::to_string);
+        let rename = |from: &str, to: &str| WatchEvent::Renamed {
+            from: from.into(),
+            to: to.into(),
+        };
+        assert_eq!(
+            rename("/root/a", "/root/b").translate(inside),
+            Some(rename("/a", "/b"))
+        );
+        assert_eq!(
+            rename("/root/a", "/b").translate(inside),
+            Some(WatchEvent::Removed("/a".into()))
+        );
+        assert_eq!(
+            rename("/a", "/root/b").translate(inside),
+            Some(WatchEvent::Created("/b".into()))
+        );
+        assert_eq!(rename("/a", "/b").translate(inside), None);
+    }
+
+    #[test]
+    fn dropped_receivers_are_removed() {
+        let watchers = Watchers::default();
+        let events = watchers.subscribe("", true);
+        drop(events);
+        watchers.emit(WatchEvent::Created("/a".into()));
+        assert_eq!(watchers.watchers.lock().unwrap().len(), 0);
+    }
+
+    fn polling(path: &VfsPath) -> PollingWatcher {
+        PollingWatcher::new(path.clone())
+            .interval(Duration::from_millis(1))
+            .debounce(Duration::ZERO)
+    }
+
+    #[test]
+    fn polling_reports_differences() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        root.join("old/deep")?.create_dir_all()?;
+        root.join("old/deep/file.txt")?.create_file()?;
+        root.join("changed.txt")?.create_file()?;
+        let mut events = polling(&root).watch(true)?;
+
+        root.join("old")?.remove_dir_all()?;
+        root.join("new")?.create_dir()?;
+        root.join("new/file.txt")?.create_file()?;
+        root.join("changed.txt")?
+            .append_file()?
+            .write_all(b"Hello")?;
+        let expected = vec![
+            WatchEvent::Removed("/old/deep/file.txt".into()),
+            WatchEvent::Removed("/old/deep".into()),
+            WatchEvent::Removed("/old".into()),
+            WatchEvent::Created("/new".into()),
+            WatchEvent::Created("/new/file.txt".into()),
+            WatchEvent::Modified("/changed.txt".into()),
+        ];
+        assert_eq!(events.by_ref().take(6).collect::<Vec<_>>(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn polling_detects_renames() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        root.join("a.txt")?.create_file()?.write_all(b"a")?;
+        let mut events = polling(&root).watch(true)?;
+
+        root.join("a.txt")?.move_file(&root.join("b.txt")?)?;
+        assert_eq!(
+            events.next(),
+            Some(WatchEvent::Renamed {
+                from: "/a.txt".into(),
+                to: "/b.txt".into()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn polling_compares_contents() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let file = root.join("a.txt")?;
+        file.create_file()?.write_all(b"a")?;
+        let modified = file.metadata()?.modified.expect("modified");
+        let mut events = polling(&root).compare_contents(true).watch(true)?;
+
+        // Same size and modification time, only the content differs
+        file.create_file()?.write_all(b"b")?;
+        file.set_modification_time(modified)?;
+        assert_eq!(events.next(), Some(WatchEvent::Modified("/a.txt".into())));
+        Ok(())
+    }
+
+    #[test]
+    fn polling_non_recursive_until_cancelled() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        root.join("dir")?.create_dir()?;
+        let token = CancellationToken::new();
+        let mut events = polling(&root)
+            .with_cancellation(token.clone())
+            .watch(false)?;
+
+        root.join("dir/deep.txt")?.create_file()?;
+        root.join("top.txt")?.create_file()?;
+        assert_eq!(events.next(), Some(WatchEvent::Created("/top.txt".into())));
+        token.cancel();
+        assert_eq!(events.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn polling_debounces_bursts() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let watcher = polling(&root)
+            .debounce(Duration::from_millis(200))
+            .max_debounce(Duration::from_secs(1));
+        let mut events = watcher.watch(true)?;
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        root.join("a.txt")?.create_file()?;
+        events.observe(watcher.snapshot(true), at(0));
+        root.join("a.txt")?.remove_file()?;
+        events.observe(watcher.snapshot(true), at(100));
+        root.join("b.txt")?.create_file()?;
+        events.observe(watcher.snapshot(true), at(200));
+        events.observe(watcher.snapshot(true), at(300));
+        assert!(events.pending.is_empty());
+        // The short-lived file is never reported
+        events.observe(watcher.snapshot(true), at(400));
+        assert_eq!(
+            events.pending.drain(..).collect::<Vec<_>>(),
+            vec![WatchEvent::Created("/b.txt".into())]
+        );
+        events.observe(watcher.snapshot(true), at(600));
+        assert!(events.pending.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn polling_reports_long_bursts() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let watcher = polling(&root)
+            .debounce(Duration::from_millis(200))
+            .max_debounce(Duration::from_secs(1));
+        let mut events = watcher.watch(true)?;
+        let start = Instant::now();
+
+        for index in 0..10 {
+            root.join(format!("{index}.txt"))?.create_file()?;
+            events.observe(
+                watcher.snapshot(true),
+                start + Duration::from_millis(index * 100),
+            );
+            assert!(events.pending.is_empty());
+        }
+        root.join("10.txt")?.create_file()?;
+        events.observe(watcher.snapshot(true), start + Duration::from_secs(1));
+        assert_eq!(events.pending.len(), 11);
+        Ok(())
+    }
+
+    #[test]
+    fn polling_missing_path() -> VfsResult<()> {
+        let root: VfsPath = MemoryFS::new().into();
+        let error = polling(&root.join("foo")?).watch(true).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not watch path for '/foo': The file or directory could not be found"
+        );
+        Ok(())
+    }
+}